@@ -1,70 +1,595 @@
 use bbl_frontend::ast::{
-    AssignmentExpr, BinOpExpr, Expr, FunctionDef, Identifier, IfExpr, ListExpr, MethodCallExpr,
-    PrintExpr, ReassignmentExpr, RepExpr, Type, UnOpExpr,
+    AssignmentExpr, BinOp, BinOpExpr, ConstDef, DebugExpr, Expr, FStringExpr, FStringPart, ForExpr,
+    FunctionDef, IfExpr, InputHeader, ListExpr, ListInitExpr, MethodCallExpr, PrintExpr, RangeExpr,
+    ReassignmentExpr, RepExpr, StructDef, TernaryExpr, TryExpr, TupleExpr, Type, UnOpExpr,
 };
+use bbl_frontend::consteval::ConstValue;
+use bbl_frontend::format::{parse_format, FormatPart};
+use bbl_frontend::lint::reachable;
+use bbl_frontend::typeck::{method_signatures, param_types, Narrowing, TypeChecker, TypeResult};
 
+use crate::helpers::{self, Helper};
 use cpp_codegen::{Block, Line, Program};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+thread_local! {
+    // Mirrors the declarations seen so far so codegen can ask for the type of
+    // a subexpression when the emitted C++ depends on it
+    static TYPES: RefCell<TypeChecker> = RefCell::new(TypeChecker::new());
+    // C++ names of variables that shadow an earlier one. C++ rejects a second
+    // definition in one scope, and an initializer would see the new variable
+    // instead of the shadowed one, so each shadowing declaration is renamed.
+    static NAMES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    // whether prints end with endl, flushing, instead of '\n'
+    static INTERACTIVE: Cell<bool> = const { Cell::new(false) };
+    // whether the value of a `try` is being generated, where whatever can
+    // fail must throw for the fallback to catch
+    static IN_TRY: Cell<bool> = const { Cell::new(false) };
+}
+
+// Codegen only runs on programs the checker accepted, so the mirror failing
+// means it has fallen out of step with the code; no C++ is better than C++
+// built on a made-up type
+fn checked<T>(result: TypeResult<T>) -> T {
+    result.unwrap_or_else(|e| panic!("codegen lost track of types: {}", e.message))
+}
+
+fn expr_type(expr: &Expr) -> Type {
+    TYPES.with(|types| checked(types.borrow_mut().check_expr(expr)))
+}
+
+fn is_pure(expr: &Expr) -> bool {
+    TYPES.with(|types| types.borrow_mut().is_pure(expr))
+}
+
+// Generates an operand that only runs sometimes, like the right side of `&&`
+// or a ternary branch. Statements it needs ahead of its value, like a token
+// read, go with it into a lambda called in its place, so they only run when
+// the operand does.
+fn conditionally(context: &mut Block, expr: &Expr) -> Option<String> {
+    let result_type = get_type_string(&expr_type(expr));
+    let mut lambda = Block::new_with_pre_block(
+        format!("[&]() -> {} ", result_type),
+        context.indent_level + 1,
+    );
+    let value = process_expression(&mut lambda, expr)?;
+    if lambda.is_empty() {
+        return Some(value);
+    }
+    lambda.add_line(format!("return {};", value));
+    Some(format!("{}()", lambda.to_string()))
+}
+
+// The values of `operands`, evaluated left to right as in bbl. C++ leaves the
+// order of function arguments and most operands unspecified, and an operand
+// may add statements ahead of the whole expression, like a token read. So
+// every operand followed by one with side effects is copied into a temporary
+// first, e.g. `const ll bbl_v3 = (ll)xs.size();` for `len(xs) + xs.pop()`;
+// even a variable, which the later operand might assign.
+fn generate_in_order(context: &mut Block, operands: &[&Expr]) -> Option<Vec<String>> {
+    generate_operands(context, operands, &[])
+}
+
+// How an expression uses an operand, which decides how generate_in_order
+// moves it ahead
+#[derive(Clone, Copy, PartialEq)]
+enum Use {
+    // copied, so a later operand can't change it
+    Read,
+    // bound by reference, like a list indexed into
+    Bound,
+    // bound by reference and written through, like a receiver a method changes
+    // or a `ref` argument
+    Written,
+}
+
+// generate_in_order where each operand is used as `uses` says; those past
+// its end are read
+fn generate_operands(context: &mut Block, operands: &[&Expr], uses: &[Use]) -> Option<Vec<String>> {
+    let effectful = operands
+        .iter()
+        .map(|operand| !is_pure(operand))
+        .collect::<Vec<bool>>();
+    let mut values = Vec::new();
+    for (i, operand) in operands.iter().enumerate() {
+        let operand_use = uses.get(i).copied().unwrap_or(Use::Read);
+        let value = match operand_use {
+            Use::Written => generate_place(context, operand)?,
+            _ => process_expression(context, operand)?,
+        };
+        // a variable, or a temporary a token read or pop() left
+        let named = value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let literal = matches!(
+            operand,
+            Expr::Integer(_)
+                | Expr::Float(_)
+                | Expr::Boolean(_)
+                | Expr::String(_)
+                | Expr::NoneLiteral(_)
+        );
+        let bound = operand_use != Use::Read;
+        let temporary = named && !matches!(operand, Expr::Identifier(_));
+        if literal || !effectful[i + 1..].contains(&true) || (named && bound) || temporary {
+            values.push(value);
+            continue;
+        }
+        let temp = generate_variable_name();
+        if bound {
+            context.add_line(format!("auto&& {} = {};", temp, value));
+        } else {
+            // spelled out, as `auto` would keep a vector<bool> element a reference
+            let value_type = get_type_string(&expr_type(operand));
+            context.add_line(format!("const {} {} = {};", value_type, temp, value));
+        }
+        values.push(temp);
+    }
+    Some(values)
+}
+
+// Generates an expression that is written to. Writing `m[k]` makes the key
+// if it's missing, so only here does a map index use operator[]; reading
+// leaves the map as it is, and may read a `val` map.
+fn generate_place(context: &mut Block, expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::IndexExpr(index) => {
+            let values = generate_operands(context, &[&index.list, &index.index], &[Use::Written])?;
+            Some(format!("{}[{}]", values[0], values[1]))
+        }
+        Expr::FieldAccessExpr(access) => {
+            let object = generate_place(context, &access.object)?;
+            Some(format!("{}.{}", object, ascii_name(&access.field.value)))
+        }
+        _ => process_expression(context, expr),
+    }
+}
+
+// Runs `body` in a new block, dropping anything it declares afterwards
+fn in_scope<R>(body: impl FnOnce() -> R) -> R {
+    let scope = TYPES.with(|types| types.borrow().enter_scope());
+    let names = NAMES.with(|names| names.borrow().clone());
+    let result = body();
+    TYPES.with(|types| types.borrow_mut().exit_scope(scope));
+    NAMES.with(|outer| *outer.borrow_mut() = names);
+    result
+}
+
+fn cpp_name(name: &str) -> String {
+    NAMES
+        .with(|names| names.borrow().get(name).cloned())
+        .unwrap_or_else(|| ascii_name(name))
+}
+
+// Compilers differ on non-ASCII names, so a name with any is spelled out in
+// ASCII behind the reserved `bbl_u`, each character other than a letter or
+// digit by its code point: `größe` becomes `bbl_ugr_u00f6_u00dfe`
+fn ascii_name(name: &str) -> String {
+    if name.is_ascii() {
+        return name.to_string();
+    }
+    let mut ascii = String::from("bbl_u");
+    for c in name.chars() {
+        match c as u32 {
+            _ if c.is_ascii_alphanumeric() => ascii.push(c),
+            code @ ..=0xffff => ascii.push_str(&format!("_u{:04x}", code)),
+            code => ascii.push_str(&format!("_U{:08x}", code)),
+        }
+    }
+    ascii
+}
+
+// Picks the C++ name of a variable about to be declared; call before the
+// declaration reaches the mirror
+fn bind_name(name: &str) -> String {
+    let shadows = TYPES.with(|types| types.borrow().symbol_table.contains_key(name));
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        if shadows {
+            let renamed = fresh_name(name);
+            names.insert(name.to_string(), renamed.clone());
+            renamed
+        } else {
+            names.remove(name);
+            ascii_name(name)
+        }
+    })
+}
+
+// A new C++ name for `name`, e.g. `bbl_v3_x`. bbl names can't start with
+// `bbl_`, and the counter tells apart every name made here.
+fn fresh_name(name: &str) -> String {
+    format!("{}_{}", generate_variable_name(), ascii_name(name))
+}
+
+// Overloads of a function share its bbl name, so each one that overloads
+// another gets a fresh C++ name. Calls find it under a key naming the
+// signature.
+fn bind_function_name(func: &FunctionDef) -> String {
+    let name = &func.name.value;
+    let signature = func.get_type();
+    let overloading = TYPES.with(|types| {
+        matches!(types.borrow().symbol_table.get(name), Some(existing @ Type::FunctionType(..))
+            if param_types(existing) != param_types(&signature))
+    });
+    let cpp = if overloading {
+        let cpp = fresh_name(name);
+        NAMES.with(|names| names.borrow_mut().insert(name.to_string(), cpp.clone()));
+        cpp
+    } else {
+        bind_name(name)
+    };
+    NAMES.with(|names| {
+        names
+            .borrow_mut()
+            .insert(overload_key(name, &signature), cpp.clone())
+    });
+    cpp
+}
+
+fn overload_key(name: &str, signature: &Type) -> String {
+    format!("{}{:?}", name, param_types(signature))
+}
+
+// The C++ name of the function a call goes to, picking among overloads
+fn callee_name(name: &str, args: &[Expr]) -> String {
+    let overload = TYPES.with(|types| types.borrow_mut().resolve_overload(name, args));
+    match overload {
+        Ok(Some(signature)) => NAMES
+            .with(|names| names.borrow().get(&overload_key(name, &signature)).cloned())
+            .unwrap_or_else(|| cpp_name(name)),
+        _ => cpp_name(name),
+    }
+}
+
+// The parameter types of the function a call to `name` with `args` goes to;
+// none for a builtin
+fn callee_params(name: &str, args: &[Expr]) -> Vec<Type> {
+    TYPES.with(|types| {
+        let mut types = types.borrow_mut();
+        let signature = match types.resolve_overload(name, args) {
+            Ok(Some(signature)) => Some(signature),
+            _ => types.symbol_table.get(name).cloned(),
+        };
+        match signature {
+            Some(Type::FunctionType(params, _)) => params,
+            _ => Vec::new(),
+        }
+    })
+}
+
+// Declares a parameter or loop variable, returning its C++ name
+fn declare_local(name: &str, local_type: Type) -> String {
+    let cpp = bind_name(name);
+    TYPES.with(|types| {
+        types
+            .borrow_mut()
+            .symbol_table
+            .insert(name.to_string(), local_type)
+    });
+    cpp
+}
+
+// Records the names introduced by a declaration
+fn declare(expr: &Expr) {
+    TYPES.with(|types| {
+        checked(types.borrow_mut().check_expr(expr));
+    });
+}
 
 pub fn generate(ast: &bbl_frontend::ast::Program) -> String {
     // Create a new program with solve function
     let mut program = Program::new();
-    let solve_block = &mut program.solve_block;
+    TYPES.with(|types| *types.borrow_mut() = TypeChecker::new());
+    NAMES.with(|names| names.borrow_mut().clear());
+    INTERACTIVE.with(|interactive| interactive.set(ast.interactive));
+    program.interactive = ast.interactive;
+    helpers::take_required();
 
-    // Generate code for each expression
-    for expr in &ast.expressions {
-        process_expression(solve_block, expr);
+    // Generate code for each expression; top-level structs go above solve()
+    let live = reachable(&ast.expressions);
+    let mut ahead = Vec::new();
+    for (index, expr) in live.iter().enumerate() {
+        match expr {
+            Expr::StructDef(def) => {
+                declare(expr);
+                program.add_global(Line::Block(generate_struct_def(def, 0)))
+            }
+            Expr::CppBlock(cpp) if cpp.global => {
+                program.add_global(Line::Statement(cpp_lines(&cpp.code).join("\n")))
+            }
+            Expr::ConstDef(def) => {
+                declare(expr);
+                if let Some(line) = generate_const_def(def) {
+                    program.add_global(Line::Statement(line));
+                }
+            }
+            _ => {
+                ahead.extend(forward_declare(&mut program.solve_block, live, index));
+                match expr {
+                    Expr::FunctionDef(func) if ahead.contains(&index) => {
+                        generate_function_def(&mut program.solve_block, func, true);
+                    }
+                    _ => process_statement(&mut program.solve_block, expr),
+                }
+            }
+        }
+    }
+    // every top-level variable lives in solve(), so each test case starts afresh
+    if ast.multi_test {
+        helpers::require(Helper::Input);
+        helpers::require(Helper::TestCount);
+        program.test_count = Some("bbl_read_test_count()".to_string());
     }
+    // helpers go first so user structs and functions can use them
+    let helpers = helpers::take_required()
+        .into_iter()
+        .map(|source| Line::Statement(source.to_string()));
+    program.globals.splice(0..0, helpers);
     program.to_string()
 }
 
+// Quotes `text` as a C++ string literal, escaping what C++ would misread
+fn cpp_string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            // octal escapes stop after three digits, unlike hex ones
+            c if c.is_ascii_control() => literal.push_str(&format!("\\{:03o}", c as u32)),
+            // other text goes in as its UTF-8 bytes, whatever charset the
+            // compiler takes the source to be in
+            c if !c.is_ascii() => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    literal.push_str(&format!("\\{:03o}", byte));
+                }
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+// Lines of a raw C++ block with surrounding blank lines and the common
+// indentation removed, so they can be re-indented where they are emitted
+fn cpp_lines(code: &str) -> Vec<String> {
+    let lines = code
+        .lines()
+        .skip_while(|l| l.trim().is_empty())
+        .collect::<Vec<_>>();
+    let end = lines
+        .iter()
+        .rposition(|l| !l.trim().is_empty())
+        .map_or(0, |i| i + 1);
+    let indent = lines[..end]
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines[..end]
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end().to_string())
+        .collect()
+}
+
 // Helper function to generate a unique variable name
 fn generate_variable_name() -> String {
     static mut COUNTER: u32 = 0;
     unsafe {
         COUNTER += 1;
-        format!("var_{}", COUNTER)
+        format!("bbl_v{}", COUNTER)
+    }
+}
+
+// Process an expression in statement position; a value it produces (e.g. from
+// a call made for its side effects) is evaluated and discarded
+// The reachable statements of a block, in order
+fn process_statements(block: &mut Block, body: &[Expr]) {
+    let live = reachable(body);
+    let mut ahead = Vec::new();
+    for (index, expr) in live.iter().enumerate() {
+        ahead.extend(forward_declare(block, live, index));
+        match expr {
+            Expr::FunctionDef(func) if ahead.contains(&index) => {
+                generate_function_def(block, func, true);
+            }
+            _ => process_statement(block, expr),
+        }
+    }
+}
+
+// Functions that an earlier one in the same run of definitions calls must
+// exist before it, so they are declared empty, e.g. `function<bool(ll)> g;`,
+// and assigned where they are defined. Returns the indices of those functions.
+fn forward_declare(block: &mut Block, body: &[Expr], index: usize) -> Vec<usize> {
+    let ahead = TYPES.with(|types| types.borrow_mut().declare_ahead(body, index));
+    for &at in &ahead {
+        if let Expr::FunctionDef(func) = &body[at] {
+            block.add_line(format!(
+                "{} {};",
+                function_type_string(func),
+                cpp_name(&func.name.value)
+            ));
+        }
+    }
+    ahead
+}
+
+fn process_statement(context: &mut Block, expr: &Expr) {
+    if let Some(value) = process_expression(context, expr) {
+        context.add_line(format!("{};", value));
     }
 }
 
 // Process an expression and optionally return a string or add it to the block
 fn process_expression(context: &mut Block, expr: &Expr) -> Option<String> {
     match expr {
-        Expr::Integer(i) => Some(format!("{}LL", i.value)),
-        Expr::Float(f) => Some(format!("{}LL", f.value)),
-        Expr::String(s) => Some(format!("\"{}\"", s.value)),
+        Expr::Integer(i) => Some(generate_integer(i.value)),
+        // Debug formatting always keeps a `.` or exponent, so C++ reads a double
+        Expr::Float(f) => Some(format!("{:?}", f.value)),
+        Expr::String(s) => Some(cpp_string_literal(&s.value)),
+        Expr::FString(fstring) => {
+            let stream = generate_fstring_stream(context, fstring)?;
+            Some(format!(
+                "[&]() {{ ostringstream _fs; _fs{}; return _fs.str(); }}()",
+                stream
+            ))
+        }
         Expr::AssignmentExpr(assign) => {
             generate_assignment(context, assign);
+            declare(expr);
+            None
+        }
+        // only reachable at the top level, handled by generate()
+        Expr::ConstDef(_) => None,
+        Expr::DestructuringAssignmentExpr(assign) => {
+            let value = process_expression(context, &assign.value)?;
+            let const_header = if assign.const_var { "const " } else { "" };
+            let targets = assign
+                .targets
+                .iter()
+                .map(|t| bind_name(&t.value))
+                .collect::<Vec<_>>();
+            if assign.list_pattern {
+                // evaluate the list once, then copy out its leading elements
+                let list = generate_variable_name();
+                context.add_line(format!("const auto& {} = {};", list, value));
+                for (i, target) in targets.iter().enumerate() {
+                    context.add_line(format!(
+                        "{}auto {} = {}[{}];",
+                        const_header, target, list, i
+                    ));
+                }
+                declare(expr);
+                return None;
+            }
+            context.add_line(format!(
+                "{}auto [{}] = {};",
+                const_header,
+                targets.join(", "),
+                value
+            ));
+            declare(expr);
             None
         }
         Expr::ReassignmentExpr(reassign) => {
             generate_reassignment(context, reassign);
             None
         }
-        Expr::MethodCallExpr(method) => {
-            todo!()
-        }
+        Expr::MethodCallExpr(method) => generate_call(context, method),
         Expr::PrintExpr(print) => {
             generate_print(context, print);
             None
         }
+        Expr::DebugExpr(debug) => {
+            generate_debug(context, debug);
+            None
+        }
         Expr::IfExpr(if_expr) => {
             generate_if(context, if_expr);
             None
         }
+        Expr::TernaryExpr(ternary) => generate_ternary(context, ternary),
+        Expr::TryExpr(try_expr) => generate_try(context, try_expr),
+        Expr::RangeExpr(range) => generate_range(context, range),
+        Expr::ListInitExpr(init) => generate_list_init(context, init),
+        Expr::EmptyCollectionExpr(empty) => {
+            Some(format!("{}()", get_type_string(&empty.collection_type)))
+        }
+        Expr::ConversionExpr(conversion) => {
+            let value = process_expression(context, &conversion.value)?;
+            Some(format!(
+                "{}({})",
+                get_type_string(&conversion.target_type),
+                value
+            ))
+        }
+        Expr::GridInitExpr(init) => {
+            let mut operands = vec![init.rows.as_ref(), init.cols.as_ref()];
+            operands.extend(init.fill.as_deref());
+            let values = generate_in_order(context, &operands)?;
+            let (rows, row) = (&values[0], values[1..].join(", "));
+            let elem = get_type_string(&init.elem_type);
+            Some(format!(
+                "vector<vector<{}>>({}, vector<{}>({}))",
+                elem, rows, elem, row
+            ))
+        }
+        Expr::InputHeader(header) => {
+            generate_input_header(context, header);
+            None
+        }
+        Expr::ForExpr(for_expr) => generate_for(context, for_expr),
         Expr::RepExpr(rep) => {
             generate_rep(context, rep);
             None
         }
-        Expr::Identifier(id) => Some(id.value.clone()),
-        Expr::ListExpr(list) => generate_list_expr(context, list),
+        Expr::Identifier(id) => Some(cpp_name(&id.value)),
+        Expr::ListExpr(list) => generate_list_expr(context, list, &expr_type(expr)),
+        Expr::TupleExpr(tuple) => generate_tuple_expr(context, tuple, &expr_type(expr)),
+        Expr::TupleAccessExpr(access) => {
+            let tuple = process_expression(context, &access.tuple)?;
+            Some(format!("get<{}>({})", access.index, tuple))
+        }
         Expr::BinOp(binop) => generate_binop(context, binop),
         Expr::UnOp(unop) => generate_unop(context, unop),
         Expr::FunctionDef(func) => {
-            generate_function_def(context, func);
+            generate_function_def(context, func, false);
+            None
+        }
+        Expr::StructDef(def) => {
+            declare(expr);
+            context.add_block(generate_struct_def(def, context.indent_level + 1));
             None
         }
+        Expr::IndexExpr(index) => {
+            let values = generate_operands(context, &[&index.list, &index.index], &[Use::Bound])?;
+            match expr_type(&index.list) {
+                Type::Map(..) => {
+                    helpers::require(Helper::MapGet);
+                    Some(format!("bbl_map_read({}, {})", values[0], values[1]))
+                }
+                // out of range throws only with .at()
+                _ if IN_TRY.with(Cell::get) => Some(format!("{}.at({})", values[0], values[1])),
+                _ => Some(format!("{}[{}]", values[0], values[1])),
+            }
+        }
+        Expr::FieldAccessExpr(access) => {
+            let object = process_expression(context, &access.object)?;
+            Some(format!("{}.{}", object, ascii_name(&access.field.value)))
+        }
         Expr::ReturnExpr(ret) => {
-            todo!()
+            match ret.value.as_ref() {
+                Expr::NoneExpr(_) => context.add_line_s("return;"),
+                value => {
+                    let value = process_expression(context, value)?;
+                    context.add_line(format!("return {};", value));
+                }
+            }
+            None
+        }
+        Expr::NoneLiteral(_) => Some("nullopt".to_string()),
+        // resolved away before codegen
+        Expr::ImportExpr(_) => None,
+        // the C++ function already exists; calls use its name as written
+        Expr::ExternDef(def) => {
+            declare(expr);
+            NAMES.with(|names| {
+                let name = &def.name.value;
+                names.borrow_mut().insert(name.clone(), name.clone())
+            });
+            None
+        }
+        Expr::CppBlock(cpp) => {
+            for line in cpp_lines(&cpp.code) {
+                context.add_line(line);
+            }
+            None
         }
         Expr::NoneExpr(_) => {
             // No-op
@@ -80,48 +605,745 @@ fn process_expression(context: &mut Block, expr: &Expr) -> Option<String> {
     }
 }
 
+// C++ has no literals past 64 bits, so bigger ones are built from 18-digit
+// chunks, which all carry the literal's sign
+fn generate_integer(value: i128) -> String {
+    // -2^63 has no literal form: the minus applies to a positive literal
+    if value == i64::MIN as i128 {
+        return "(-9223372036854775807LL - 1)".to_string();
+    }
+    if i64::try_from(value).is_ok() {
+        return format!("{}LL", value);
+    }
+    helpers::require(Helper::I128);
+    const CHUNK: i128 = 1_000_000_000_000_000_000;
+    format!(
+        "(((__int128){}LL * {}LL + {}LL) * {}LL + {}LL)",
+        value / CHUNK / CHUNK,
+        CHUNK,
+        value / CHUNK % CHUNK,
+        CHUNK,
+        value % CHUNK
+    )
+}
+
+// Whether a string comes out as a C++ char array rather than a std::string:
+// a literal, or a choice between two
+fn is_char_array(expr: &Expr) -> bool {
+    match expr {
+        Expr::String(_) => true,
+        Expr::TernaryExpr(ternary) => {
+            is_char_array(&ternary.then_value) && is_char_array(&ternary.else_value)
+        }
+        _ => false,
+    }
+}
+
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::Int | Type::I128)
+}
+
 fn get_type_string(inp_type: &Type) -> String {
     match inp_type {
-        Type::Int => "int".to_string(),
-        Type::Float => "float".to_string(),
+        Type::Int => "ll".to_string(),
+        Type::I128 => {
+            helpers::require(Helper::I128);
+            "__int128".to_string()
+        }
+        Type::Float => "double".to_string(),
         Type::String => "string".to_string(),
         Type::Bool => "bool".to_string(),
         Type::None => "none".to_string(),
+        Type::Ref(inner) => format!("{}&", get_type_string(inner)),
+        Type::Range => {
+            helpers::require(Helper::Range);
+            "bbl_range".to_string()
+        }
         Type::List(c) => format!("vector<{}>", get_type_string(c)),
-        Type::Tuple(_) => todo!(),
-        Type::FunctionType(_, _) => "auto".to_string(),
+        Type::Tuple(elems) => format!(
+            "tuple<{}>",
+            elems
+                .iter()
+                .map(get_type_string)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Type::Pair(first, second) => {
+            format!(
+                "pair<{}, {}>",
+                get_type_string(first),
+                get_type_string(second)
+            )
+        }
+        Type::Struct(name) => ascii_name(name),
+        Type::Option(inner) => format!("optional<{}>", get_type_string(inner)),
+        Type::Map(key, value) => {
+            format!("map<{}, {}>", get_type_string(key), get_type_string(value))
+        }
+        Type::Set(elem) => format!("set<{}>", get_type_string(elem)),
+        Type::Grid(elem) => format!("vector<vector<{}>>", get_type_string(elem)),
+        Type::Bitset => {
+            helpers::require(Helper::Bitset);
+            "bbl_bitset".to_string()
+        }
+        Type::ModInt(modulus) => {
+            helpers::require(Helper::ModInt);
+            format!("bbl_mint<{}>", modulus)
+        }
+        Type::Stack(elem) => format!("stack<{}>", get_type_string(elem)),
+        Type::Queue(elem) => format!("queue<{}>", get_type_string(elem)),
+        Type::Deque(elem) => format!("deque<{}>", get_type_string(elem)),
+        Type::Heap(elem, false) => format!("priority_queue<{}>", get_type_string(elem)),
+        Type::Heap(elem, true) => {
+            let elem = get_type_string(elem);
+            format!(
+                "priority_queue<{}, vector<{}>, greater<{}>>",
+                elem, elem, elem
+            )
+        }
+        Type::FunctionType(params, return_type) => format!(
+            "function<{}({})>",
+            return_type
+                .as_ref()
+                .as_ref()
+                .map(get_type_string)
+                .unwrap_or("void".to_string()),
+            params
+                .iter()
+                .map(get_type_string)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
     }
 }
 
-fn generate_function_def(context: &mut Block, func: &FunctionDef) -> Option<String> {
-    let fn_pre_header = format!(
-        "auto {} = [&]({}) -> {} ",
-        func.name.value,
+// Functions are lambdas capturing the enclosing scope by reference. They are
+// stored in a std::function, which is declared before its initializer runs, so
+// the body can call the function recursively.
+// `declared` is set for a function already declared by forward_declare, which
+// only needs assigning
+fn generate_function_def(
+    context: &mut Block,
+    func: &FunctionDef,
+    declared: bool,
+) -> Option<String> {
+    let return_type = func
+        .return_type
+        .as_ref()
+        .map(get_type_string)
+        .unwrap_or("void".to_string());
+    let name = if declared {
+        cpp_name(&func.name.value)
+    } else {
+        bind_function_name(func)
+    };
+    declare(&Expr::FunctionDef(func.clone()));
+    let new_block = in_scope(|| {
+        let params = func
+            .args
+            .iter()
+            .map(|arg| {
+                let arg_name = declare_local(&arg.value.value, arg.associated_type.without_ref());
+                format!("{} {}", get_type_string(&arg.associated_type), arg_name)
+            })
+            .collect::<Vec<String>>();
+        let fn_pre_header = format!(
+            "{}{} = [&]({}) -> {} ",
+            if declared {
+                String::new()
+            } else {
+                format!("{} ", function_type_string(func))
+            },
+            name,
+            params.join(", "),
+            return_type
+        );
+        let mut new_block = Block::new_with_pre_block(fn_pre_header, context.indent_level + 1);
+        new_block.set_post_block(";".to_string());
+        process_statements(&mut new_block, &func.body);
+        new_block
+    });
+    context.add_block(new_block);
+
+    None
+}
+
+// e.g. `function<ll(ll, string)>`
+fn function_type_string(func: &FunctionDef) -> String {
+    format!(
+        "function<{}({})>",
+        func.return_type
+            .as_ref()
+            .map(get_type_string)
+            .unwrap_or("void".to_string()),
         func.args
             .iter()
-            .map(|arg| format!(
+            .map(|arg| get_type_string(&arg.associated_type))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+// Structs get a value-initializing default constructor (so they can live in
+// vectors) and a positional one matching `Name(a, b)` construction in bbl.
+fn generate_struct_def(def: &StructDef, indent_level: usize) -> Block {
+    let name = ascii_name(&def.name.value);
+    let fields = def
+        .fields
+        .iter()
+        .map(|f| ascii_name(&f.value.value))
+        .collect::<Vec<String>>();
+    let mut block = Block::new_with_pre_block(format!("struct {} ", name), indent_level);
+    for (field, field_name) in def.fields.iter().zip(&fields) {
+        block.add_line(format!(
+            "{} {}{{}};",
+            get_type_string(&field.associated_type),
+            field_name
+        ));
+    }
+    block.add_line(format!("{}() = default;", name));
+    block.add_line(format!(
+        "{}({}) : {} {{}}",
+        name,
+        def.fields
+            .iter()
+            .zip(&fields)
+            .map(|(f, field_name)| format!(
                 "{} {}",
-                get_type_string(&arg.associated_type),
-                arg.value.value
+                get_type_string(&f.associated_type),
+                field_name
             ))
             .collect::<Vec<String>>()
             .join(", "),
-        "auto"
-    );
+        fields
+            .iter()
+            .map(|field_name| format!("{}({})", field_name, field_name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    ));
+    block.set_post_block(";".to_string());
+    block
+}
 
-    let mut new_block = Block::new_with_pre_block(fn_pre_header, context.indent_level + 1);
-    for expr in &func.body {
-        process_expression(&mut new_block, expr);
+fn generate_call(context: &mut Block, call: &MethodCallExpr) -> Option<String> {
+    if let Some(receiver) = &call.receiver {
+        return generate_method(context, receiver, call);
     }
-    context.add_block(new_block);
+    let uses = match call.method_name.value.as_str() {
+        "sort" | "sort_by" | "dedup" | "reverse" | "shuffle" => vec![Use::Written],
+        name => callee_params(name, &call.args)
+            .iter()
+            .map(|param| match param {
+                Type::Ref(_) => Use::Written,
+                _ => Use::Read,
+            })
+            .collect(),
+    };
+    let args = generate_operands(context, &call.args.iter().collect::<Vec<_>>(), &uses)?;
+    match call.method_name.value.as_str() {
+        // stoll and stod stop at the first character they can't read, so a
+        // `try` only falls back on a string that isn't wholly a number
+        "int" | "float" if IN_TRY.with(Cell::get) && expr_type(&call.args[0]) == Type::String => {
+            helpers::require(Helper::Parse);
+            let parse = match call.method_name.value.as_str() {
+                "int" => "bbl_parse_int",
+                _ => "bbl_parse_float",
+            };
+            Some(format!("{}({}).value()", parse, args[0]))
+        }
+        "int" => match expr_type(&call.args[0]) {
+            Type::String => Some(format!("stoll({})", args[0])),
+            _ => Some(format!("(ll)({})", args[0])),
+        },
+        "float" => match expr_type(&call.args[0]) {
+            Type::String => Some(format!("stod({})", args[0])),
+            _ => Some(format!("(double)({})", args[0])),
+        },
+        "str" => match expr_type(&call.args[0]) {
+            Type::String => Some(args[0].clone()),
+            Type::Bool => Some(format!("string({} ? \"true\" : \"false\")", args[0])),
+            Type::ModInt(_) => Some(format!("to_string((ll)({}))", args[0])),
+            Type::I128 => Some(format!("bbl_i128_to_string({})", args[0])),
+            _ => Some(format!("to_string({})", args[0])),
+        },
+        "format" => {
+            let Expr::String(template) = &call.args[0] else {
+                unreachable!()
+            };
+            let stream = generate_format_stream(&template.value, &args[1..])?;
+            Some(format!(
+                "[&]() {{ ostringstream _fs; _fs{}; return _fs.str(); }}()",
+                stream
+            ))
+        }
+        "parse_int" | "parse_float" => {
+            helpers::require(Helper::Parse);
+            Some(format!("bbl_{}({})", call.method_name.value, args[0]))
+        }
+        "some" => Some(format!("make_optional({})", args[0])),
+        "unwrap" => Some(format!("{}.value()", args[0])),
+        // signed, so `len(xs) - 1` can't wrap around
+        "len" => match &call.args[0] {
+            // a char array has no .size()
+            arg if is_char_array(arg) => Some(format!("(ll)string({}).size()", args[0])),
+            _ => Some(format!("(ll){}.size()", args[0])),
+        },
+        "sort" | "sort_by" => {
+            let list = &args[0];
+            let range = format!("{}.begin(), {}.end()", list, list);
+            match (call.method_name.value.as_str(), args.get(1)) {
+                ("sort", Some(key)) => {
+                    let elem = match expr_type(&call.args[0]) {
+                        Type::List(elem) => get_type_string(&elem),
+                        _ => "auto".to_string(),
+                    };
+                    context.add_line(format!(
+                        "sort({}, [&](const {}& a, const {}& b) {{ return {}(a) < {}(b); }});",
+                        range, elem, elem, key, key
+                    ))
+                }
+                (_, Some(cmp)) => context.add_line(format!("sort({}, {});", range, cmp)),
+                (_, None) => context.add_line(format!("sort({});", range)),
+            }
+            None
+        }
+        "dedup" => {
+            let list = &args[0];
+            context.add_line(format!("sort({}.begin(), {}.end());", list, list));
+            context.add_line(format!(
+                "{}.erase(unique({}.begin(), {}.end()), {}.end());",
+                list, list, list, list
+            ));
+            None
+        }
+        // strings keep each character's UTF-8 bytes in order
+        "reverse" if expr_type(&call.args[0]) == Type::String => {
+            helpers::require(Helper::ReverseText);
+            context.add_line(format!("bbl_reverse_text({});", args[0]));
+            None
+        }
+        "reverse" => {
+            context.add_line(format!("reverse({}.begin(), {}.end());", args[0], args[0]));
+            None
+        }
+        "reversed" if expr_type(&call.args[0]) == Type::String => {
+            helpers::require(Helper::ReverseText);
+            Some(format!("bbl_reversed_text({})", args[0]))
+        }
+        // a copy built from reverse iterators, which must come from one object
+        "reversed" => {
+            let seq_type = get_type_string(&expr_type(&call.args[0]));
+            let seq = evaluate_once(context, &call.args[0], &args[0]);
+            Some(format!("{}({}.rbegin(), {}.rend())", seq_type, seq, seq))
+        }
+        "pow" if matches!(expr_type(&call.args[0]), Type::ModInt(_)) => {
+            Some(format!("{}.pow({})", args[0], args[1]))
+        }
+        "pow" | "modpow" => {
+            helpers::require(if call.method_name.value == "pow" {
+                Helper::Pow
+            } else {
+                Helper::ModPow
+            });
+            Some(format!(
+                "bbl_{}({})",
+                call.method_name.value,
+                args.join(", ")
+            ))
+        }
+        "sqrt" | "log" => Some(format!("{}((double)({}))", call.method_name.value, args[0])),
+        "floor" | "ceil" => Some(format!("(ll){}({})", call.method_name.value, args[0])),
+        "round" => Some(format!("llround({})", args[0])),
+        "isqrt" => {
+            helpers::require(Helper::Isqrt);
+            Some(format!("bbl_isqrt({})", args[0]))
+        }
+        "join" => {
+            helpers::require(Helper::Join);
+            Some(format!("bbl_join({}, {})", args[0], args[1]))
+        }
+        "elapsed_ms" => {
+            helpers::require(Helper::Clock);
+            Some("bbl_elapsed_ms()".to_string())
+        }
+        "rand_int" => {
+            helpers::require(Helper::Random);
+            Some(format!("bbl_rand_int({}, {})", args[0], args[1]))
+        }
+        "shuffle" => {
+            helpers::require(Helper::Random);
+            context.add_line(format!(
+                "shuffle({}.begin(), {}.end(), bbl_rng);",
+                args[0], args[0]
+            ));
+            None
+        }
+        // plain abs() could pick the int overload and truncate
+        "abs" => match expr_type(&call.args[0]) {
+            Type::Float => Some(format!("fabs({})", args[0])),
+            _ => Some(format!("llabs({})", args[0])),
+        },
+        "min" | "max" if args.len() == 1 => {
+            let list = evaluate_once(context, &call.args[0], &args[0]);
+            Some(format!(
+                "*{}_element({}.begin(), {}.end())",
+                call.method_name.value, list, list
+            ))
+        }
+        "lower_bound" | "upper_bound" => {
+            let list = evaluate_once(context, &call.args[0], &args[0]);
+            Some(format!(
+                "(ll)({}({}.begin(), {}.end(), {}) - {}.begin())",
+                call.method_name.value, list, list, args[1], list
+            ))
+        }
+        "bitset" => {
+            helpers::require(Helper::Bitset);
+            Some(format!("bbl_bitset({})", args[0]))
+        }
+        // spelled out so a string literal becomes a string
+        "pair" => Some(format!(
+            "{}({})",
+            get_type_string(&expr_type(&Expr::MethodCallExpr(call.clone()))),
+            args.join(", ")
+        )),
+        "count" => {
+            let list = evaluate_once(context, &call.args[0], &args[0]);
+            Some(format!(
+                "(ll)count({}.begin(), {}.end(), {})",
+                list, list, args[1]
+            ))
+        }
+        "filter" | "map" => {
+            let list = evaluate_once(context, &call.args[0], &args[0]);
+            let result = generate_variable_name();
+            context.add_line(format!(
+                "{} {};",
+                get_type_string(&expr_type(&Expr::MethodCallExpr(call.clone()))),
+                result
+            ));
+            let algorithm = if call.method_name.value == "filter" {
+                "copy_if"
+            } else {
+                "transform"
+            };
+            context.add_line(format!(
+                "{}({}.begin(), {}.end(), back_inserter({}), {});",
+                algorithm, list, list, result, args[1]
+            ));
+            Some(result)
+        }
+        "prefix_sums" => {
+            let list_type = get_type_string(&expr_type(&call.args[0]));
+            let list = evaluate_once(context, &call.args[0], &args[0]);
+            let sums = generate_variable_name();
+            context.add_line(format!("{} {}({}.size() + 1);", list_type, sums, list));
+            context.add_line(format!(
+                "partial_sum({}.begin(), {}.end(), {}.begin() + 1);",
+                list, list, sums
+            ));
+            Some(sums)
+        }
+        "bsearch" => {
+            helpers::require(Helper::Bsearch);
+            Some(format!("bbl_bsearch({})", args.join(", ")))
+        }
+        "read_int" => Some(generate_token_read(context, "ll")),
+        "read_str" => Some(generate_token_read(context, "string")),
+        "read_list" => {
+            helpers::require(Helper::Input);
+            let list = generate_variable_name();
+            let elem = generate_variable_name();
+            context.add_line(format!("vector<ll> {}({});", list, args[0]));
+            context.add_line(format!("for (ll& {} : {}) cin >> {};", elem, list, elem));
+            context.add_line("bbl_mid_line = true;".to_string());
+            Some(list)
+        }
+        "read_line" => {
+            helpers::require(Helper::Input);
+            helpers::require(Helper::ReadLine);
+            Some("bbl_read_line()".to_string())
+        }
+        "panic" => {
+            context.add_line(format!("cerr << {} << '\\n';", args[0]));
+            context.add_line("exit(1);".to_string());
+            None
+        }
+        // cout is unsynced from stdio, so it is flushed by hand first
+        "flush" => {
+            context.add_line("cout.flush();".to_string());
+            None
+        }
+        "exit" => {
+            context.add_line("cout.flush();".to_string());
+            context.add_line(format!("exit({});", args[0]));
+            None
+        }
+        name => Some(format!(
+            "{}({})",
+            callee_name(name, &call.args),
+            args.join(", ")
+        )),
+    }
+}
 
+// List methods map onto vector's; positions are offsets from begin()
+fn generate_method(context: &mut Block, receiver: &Expr, call: &MethodCallExpr) -> Option<String> {
+    let mut operands = vec![receiver];
+    operands.extend(&call.args);
+    let mutates = method_signatures(&expr_type(receiver), &call.method_name.value)
+        .iter()
+        .any(|signature| signature.mutates);
+    let receiver_use = if mutates { Use::Written } else { Use::Bound };
+    let mut args = generate_operands(context, &operands, &[receiver_use])?;
+    let list = args.remove(0);
+    match expr_type(receiver) {
+        Type::Map(..) => return generate_map_method(context, &list, call, &args),
+        Type::Set(_) => return generate_set_method(context, &list, call, &args),
+        Type::Heap(..) => return generate_heap_method(context, &list, call, &args),
+        container @ (Type::Stack(_) | Type::Queue(_) | Type::Deque(_)) => {
+            return generate_container_method(context, &list, &container, call, &args)
+        }
+        Type::Bitset => {
+            return match call.method_name.value.as_str() {
+                "set" | "reset" | "flip" => {
+                    context.add_line(format!("{}.{}({});", list, call.method_name.value, args[0]));
+                    None
+                }
+                "test" => Some(format!("{}.test({})", list, args[0])),
+                "count" => Some(format!("{}.count()", list)),
+                "size" => Some(format!("{}.n", list)),
+                _ => None,
+            };
+        }
+        Type::Grid(_) => {
+            let grid = evaluate_once(context, receiver, &list);
+            return match call.method_name.value.as_str() {
+                "in_bounds" => {
+                    helpers::require(Helper::Grid);
+                    Some(format!("bbl_in_bounds({}, {}, {})", grid, args[0], args[1]))
+                }
+                "rows" => Some(format!("(ll){}.size()", grid)),
+                "cols" => Some(format!("({}.empty() ? 0LL : (ll){}[0].size())", grid, grid)),
+                _ => None,
+            };
+        }
+        _ => {}
+    }
+    if expr_type(receiver) == Type::String {
+        // a literal is a char array, without string's members
+        let string = if is_char_array(receiver) {
+            format!("string({})", list)
+        } else {
+            list
+        };
+        return generate_string_method(&string, call, &args);
+    }
+    match call.method_name.value.as_str() {
+        "len" => return Some(format!("(ll){}.size()", list)),
+        "push" => context.add_line(format!("{}.push_back({});", list, args[0])),
+        // pop_back() returns nothing, so the last element is saved first
+        "pop" => {
+            let var = generate_variable_name();
+            context.add_line(format!("auto {} = {}.back();", var, list));
+            context.add_line(format!("{}.pop_back();", list));
+            return Some(var);
+        }
+        "insert" => context.add_line(format!(
+            "{}.insert({}.begin() + {}, {});",
+            list, list, args[0], args[1]
+        )),
+        "remove" => context.add_line(format!("{}.erase({}.begin() + {});", list, list, args[0])),
+        _ => {}
+    }
     None
 }
 
+fn generate_map_method(
+    context: &mut Block,
+    map: &str,
+    call: &MethodCallExpr,
+    args: &[String],
+) -> Option<String> {
+    match call.method_name.value.as_str() {
+        "get" => {
+            helpers::require(Helper::MapGet);
+            Some(format!("bbl_map_get({}, {})", map, args[0]))
+        }
+        "insert" => {
+            context.add_line(format!("{}[{}] = {};", map, args[0], args[1]));
+            None
+        }
+        "contains" => Some(format!("({}.count({}) > 0)", map, args[0])),
+        "keys" | "values" => {
+            helpers::require(Helper::MapItems);
+            Some(format!("bbl_{}({})", call.method_name.value, map))
+        }
+        _ => None,
+    }
+}
+
+fn generate_set_method(
+    context: &mut Block,
+    set: &str,
+    call: &MethodCallExpr,
+    args: &[String],
+) -> Option<String> {
+    match call.method_name.value.as_str() {
+        "insert" | "erase" => {
+            context.add_line(format!("{}.{}({});", set, call.method_name.value, args[0]));
+            None
+        }
+        "contains" => Some(format!("({}.count({}) > 0)", set, args[0])),
+        "size" => Some(format!("(ll){}.size()", set)),
+        _ => None,
+    }
+}
+
+fn generate_heap_method(
+    context: &mut Block,
+    heap: &str,
+    call: &MethodCallExpr,
+    args: &[String],
+) -> Option<String> {
+    match call.method_name.value.as_str() {
+        "push" => {
+            context.add_line(format!("{}.push({});", heap, args[0]));
+            None
+        }
+        "pop" => {
+            let var = generate_variable_name();
+            context.add_line(format!("auto {} = {}.top();", var, heap));
+            context.add_line(format!("{}.pop();", heap));
+            Some(var)
+        }
+        "top" => Some(format!("{}.top()", heap)),
+        "size" => Some(format!("(ll){}.size()", heap)),
+        _ => None,
+    }
+}
+
+// pop/pop_back/pop_front read the element they remove first, as the STL
+// versions return nothing
+fn generate_container_method(
+    context: &mut Block,
+    container: &str,
+    container_type: &Type,
+    call: &MethodCallExpr,
+    args: &[String],
+) -> Option<String> {
+    let method = call.method_name.value.as_str();
+    let removed = match method {
+        "pop" if matches!(container_type, Type::Stack(_)) => "top",
+        "pop" | "pop_front" => "front",
+        "pop_back" => "back",
+        "size" => return Some(format!("(ll){}.size()", container)),
+        "top" | "front" | "back" => return Some(format!("{}.{}()", container, method)),
+        _ => {
+            context.add_line(format!("{}.{}({});", container, method, args[0]));
+            return None;
+        }
+    };
+    let var = generate_variable_name();
+    context.add_line(format!("auto {} = {}.{}();", var, container, removed));
+    context.add_line(format!("{}.{}();", container, method));
+    Some(var)
+}
+
+fn generate_string_method(string: &str, call: &MethodCallExpr, args: &[String]) -> Option<String> {
+    match call.method_name.value.as_str() {
+        "len" => Some(format!("(ll){}.size()", string)),
+        "split" if args.is_empty() => {
+            helpers::require(Helper::SplitWhitespace);
+            Some(format!("bbl_split_ws({})", string))
+        }
+        "split" => {
+            helpers::require(Helper::Split);
+            Some(format!("bbl_split({}, {})", string, args[0]))
+        }
+        "to_upper" | "to_lower" => {
+            helpers::require(Helper::Case);
+            Some(format!("bbl_{}({})", call.method_name.value, string))
+        }
+        "trim" => {
+            helpers::require(Helper::Trim);
+            Some(format!("bbl_trim({})", string))
+        }
+        // npos becomes -1 as a signed value
+        "find" => Some(format!("(ll){}.find({})", string, args[0])),
+        "contains" => Some(format!("({}.find({}) != string::npos)", string, args[0])),
+        "replace" => {
+            helpers::require(Helper::Replace);
+            Some(format!("bbl_replace({}, {}, {})", string, args[0], args[1]))
+        }
+        _ => None,
+    }
+}
+
+// A name for the value of `expr` that can be used several times: the name
+// itself for a variable, otherwise a reference to it bound just before
+fn evaluate_once(context: &mut Block, expr: &Expr, value: &str) -> String {
+    if let Expr::Identifier(_) = expr {
+        return value.to_string();
+    }
+    let var = generate_variable_name();
+    context.add_line(format!(
+        "const {}& {} = {};",
+        get_type_string(&expr_type(expr)),
+        var,
+        value
+    ));
+    var
+}
+
+// Reads a token into a temporary declared just before the expression. The
+// flag tells a later read_line() that the rest of this line is still unread.
+fn generate_token_read(context: &mut Block, cpp_type: &str) -> String {
+    helpers::require(Helper::Input);
+    let var = generate_variable_name();
+    context.add_line(format!("{} {};", cpp_type, var));
+    context.add_line(format!("cin >> {};", var));
+    context.add_line("bbl_mid_line = true;".to_string());
+    var
+}
+
+// Emits a constant with the value the typechecker folded; call after declare()
+fn generate_const_def(def: &ConstDef) -> Option<String> {
+    let name = &def.target.value.value;
+    let value = match TYPES.with(|types| types.borrow().constants.get(name).cloned())? {
+        // -2^63 has no literal form: the minus applies to a positive literal
+        ConstValue::Int(i64::MIN) => "(-9223372036854775807LL - 1)".to_string(),
+        ConstValue::Int(i) => format!("{}LL", i),
+        ConstValue::Float(f) => format!("{:?}", f),
+        ConstValue::Bool(b) => b.to_string(),
+        ConstValue::String(s) => cpp_string_literal(&s),
+    };
+    // std::string is not a literal type, so string constants can't be constexpr
+    let qualifier = match def.target.associated_type {
+        Type::String => "const",
+        _ => "constexpr",
+    };
+    Some(format!(
+        "{} {} {} = {};",
+        qualifier,
+        get_type_string(&def.target.associated_type),
+        ascii_name(name),
+        value
+    ))
+}
+
 fn generate_assignment(context: &mut Block, assign: &AssignmentExpr) -> Option<String> {
+    // an unannotated variable takes the type the checker inferred for it
+    let var_type = match &assign.annotation {
+        Some(annotation) => annotation,
+        None => assign
+            .inferred
+            .get()
+            .expect("the checker infers the type of every unannotated variable"),
+    };
     let val_result = process_expression(context, &assign.value)?;
-    let var_type = get_type_string(&assign.target.associated_type);
-    let var_name = &assign.target.value.value;
+    let var_type = get_type_string(var_type);
+    let var_name = bind_name(&assign.target.value);
     let const_header = if assign.const_var { "const " } else { "" };
     context.add_line(format!(
         "{}{} {} = {};",
@@ -131,42 +1353,190 @@ fn generate_assignment(context: &mut Block, assign: &AssignmentExpr) -> Option<S
 }
 
 fn generate_reassignment(context: &mut Block, assign: &ReassignmentExpr) -> Option<String> {
+    // `s = s + t` appends in place rather than copying s, so building a
+    // string piece by piece stays linear
+    if let (None, Expr::BinOp(binop)) = (&assign.op, assign.value.as_ref()) {
+        if binop.op == BinOp::Add
+            && expr_type(&assign.target) == Type::String
+            && same_place(&assign.target, &binop.left)
+        {
+            let appended = ReassignmentExpr::new(
+                (*assign.target).clone(),
+                (*binop.right).clone(),
+                Some(BinOp::Add),
+            );
+            return generate_reassignment(context, &appended);
+        }
+    }
     let val_result = process_expression(context, &assign.value)?;
-    let var_name = &assign.target.value;
-    context.add_line(format!("{} = {};", var_name, val_result));
+    let var_name = generate_place(context, &assign.target)?;
+    let op = match assign.op {
+        Some(BinOp::IntDiv) => "/",
+        Some(op) => op.symbol(),
+        None => "",
+    };
+    context.add_line(format!("{} {}= {};", var_name, op, val_result));
     None
 }
 
+// Whether two assignment targets name the same variable, element or field
+fn same_place(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (Expr::Identifier(a), Expr::Identifier(b)) => a.value == b.value,
+        (Expr::FieldAccessExpr(a), Expr::FieldAccessExpr(b)) => {
+            a.field.value == b.field.value && same_place(&a.object, &b.object)
+        }
+        (Expr::IndexExpr(a), Expr::IndexExpr(b)) => {
+            same_place(&a.list, &b.list) && same_place(&a.index, &b.index)
+        }
+        (Expr::Integer(a), Expr::Integer(b)) => a.value == b.value,
+        _ => false,
+    }
+}
+
+// The `<< part` chain writing an f-string to a stream
+fn generate_fstring_stream(context: &mut Block, fstring: &FStringExpr) -> Option<String> {
+    fstring
+        .parts
+        .iter()
+        .map(|part| match part {
+            FStringPart::Text(text) => Some(format!(" << {}", cpp_string_literal(text))),
+            FStringPart::Expr(e) => Some(format!(" << {}", process_expression(context, e)?)),
+        })
+        .collect()
+}
+
+// The `<< part` chain writing a `format` template filled in with `args`. A
+// precision switches the stream to fixed notation, and the default is
+// restored before the next plain placeholder.
+fn generate_format_stream(template: &str, args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    let mut fixed = false;
+    let mut stream = String::new();
+    for part in parse_format(template).ok()? {
+        match part {
+            FormatPart::Text(text) => {
+                stream += &format!(" << {}", cpp_string_literal(&text));
+            }
+            FormatPart::Arg {
+                precision: Some(precision),
+            } => {
+                stream += &format!(
+                    " << fixed << setprecision({}) << (double)({})",
+                    precision,
+                    args.next()?
+                );
+                fixed = true;
+            }
+            FormatPart::Arg { precision: None } => {
+                if fixed {
+                    stream += " << defaultfloat << setprecision(6)";
+                    fixed = false;
+                }
+                stream += &format!(" << {}", args.next()?);
+            }
+        }
+    }
+    Some(stream)
+}
+
 fn generate_print(context: &mut Block, print: &PrintExpr) -> Option<String> {
+    let end = if INTERACTIVE.with(Cell::get) {
+        "endl"
+    } else {
+        "'\\n'"
+    };
+    // f-strings print straight to cout without building a temporary string
+    if let Expr::FString(fstring) = print.arg.as_ref() {
+        let stream = generate_fstring_stream(context, fstring)?;
+        context.add_line(format!("cout{} << {};", stream, end));
+        return None;
+    }
     let val_result = process_expression(context, &print.arg)?;
-    context.add_line(format!("cout << {} << '\\n';", val_result));
+    context.add_line(format!("cout << {} << {};", val_result, end));
+    None
+}
+
+// Compiled only with -DLOCAL, so a submission neither prints nor evaluates
+// the values, e.g. `cerr << "x = " << x << ", y + 1 = " << (y + 1) << '\n';`
+fn generate_debug(context: &mut Block, debug: &DebugExpr) -> Option<String> {
+    context.add_line("#ifdef LOCAL".to_string());
+    let mut stream = String::new();
+    for (i, (text, arg)) in debug.args.iter().enumerate() {
+        let Some(value) = process_expression(context, arg) else {
+            break;
+        };
+        let separator = if i == 0 { "" } else { ", " };
+        let label = cpp_string_literal(&format!("{}{} = ", separator, text));
+        stream += &format!(" << {} << {}", label, value);
+    }
+    context.add_line(format!("cerr{} << '\\n';", stream));
+    context.add_line("#endif".to_string());
     None
 }
 
 fn generate_if(context: &mut Block, if_expr: &IfExpr) -> Option<String> {
     let condition = process_expression(context, &if_expr.condition)?;
+    let narrowing = TYPES.with(|types| types.borrow().narrowing(&if_expr.condition));
+    let narrowed = |in_then: bool| narrowing.as_ref().filter(|n| n.in_then == in_then);
 
     let mut new_block = Block::new_with_pre_block(
         "if (".to_string() + &condition + ") ",
         context.indent_level + 1,
     );
-
-    for expr in &if_expr.then_block {
-        process_expression(&mut new_block, expr);
-    }
-
+    generate_branch(&mut new_block, &if_expr.then_block, narrowed(true));
     context.add_block(new_block);
+
     if let Some(else_block) = &if_expr.else_block {
         let mut new_block =
             Block::new_with_pre_block("else ".to_string(), context.indent_level + 1);
-        for expr in else_block {
-            process_expression(&mut new_block, expr);
-        }
+        generate_branch(&mut new_block, else_block, narrowed(false));
         context.add_block(new_block);
     }
     None
 }
 
+// A narrowed option is shadowed by a reference to its value for the branch,
+// e.g. `const auto& bbl_v3_x = *x;`
+fn generate_branch(block: &mut Block, body: &[Expr], narrowing: Option<&Narrowing>) {
+    in_scope(|| {
+        if let Some(narrowing) = narrowing {
+            let option = cpp_name(&narrowing.name);
+            let value = declare_local(&narrowing.name, narrowing.inner.clone());
+            block.add_line(format!("const auto& {} = *{};", value, option));
+        }
+        process_statements(block, body);
+    });
+}
+
+// Any exception thrown while computing the value (stoll on bad input,
+// value() of an empty optional, ...) switches to the fallback
+fn generate_try(context: &mut Block, try_expr: &TryExpr) -> Option<String> {
+    let result_type = get_type_string(&expr_type(&Expr::TryExpr(try_expr.clone())));
+    let indent = context.indent_level + 1;
+    let mut lambda = Block::new_with_pre_block(format!("[&]() -> {} ", result_type), indent);
+    let mut try_block = Block::new_with_pre_block("try ".to_string(), indent + 1);
+    let outer = IN_TRY.with(|in_try| in_try.replace(true));
+    let value = process_expression(&mut try_block, &try_expr.value);
+    IN_TRY.with(|in_try| in_try.set(outer));
+    let value = value?;
+    try_block.add_line(format!("return {};", value));
+    let mut catch_block = Block::new_with_pre_block("catch (...) ".to_string(), indent + 1);
+    if let Some(fallback) = process_expression(&mut catch_block, &try_expr.fallback) {
+        catch_block.add_line(format!("return {};", fallback));
+    }
+    lambda.add_block(try_block);
+    lambda.add_block(catch_block);
+    Some(format!("{}()", lambda.to_string()))
+}
+
+fn generate_ternary(context: &mut Block, ternary: &TernaryExpr) -> Option<String> {
+    let condition = process_expression(context, &ternary.condition)?;
+    let then_value = conditionally(context, &ternary.then_value)?;
+    let else_value = conditionally(context, &ternary.else_value)?;
+    Some(format!("({} ? {} : {})", condition, then_value, else_value))
+}
+
 fn generate_rep(context: &mut Block, rep: &RepExpr) -> Option<String> {
     let count = process_expression(context, &rep.num_iterations)?;
     let new_var_name = generate_variable_name();
@@ -177,29 +1547,393 @@ fn generate_rep(context: &mut Block, rep: &RepExpr) -> Option<String> {
         ),
         context.indent_level + 1,
     );
-    for expr in &rep.body {
-        process_expression(&mut new_block, expr);
-    }
+    in_scope(|| {
+        process_statements(&mut new_block, &rep.body);
+    });
     context.add_block(new_block);
     None
 }
 
-fn generate_list_expr(context: &mut Block, list: &ListExpr) -> Option<String> {
-    let joined_string = "vector {".to_owned()
-        + &list
-            .elems
+fn generate_range(context: &mut Block, range: &RangeExpr) -> Option<String> {
+    let values = generate_in_order(context, &[&range.start, &range.end])?;
+    let (start, end) = (&values[0], &values[1]);
+    helpers::require(Helper::Range);
+    if range.inclusive {
+        Some(format!("bbl_range{{{}, {} + 1}}", start, end))
+    } else {
+        Some(format!("bbl_range{{{}, {}}}", start, end))
+    }
+}
+
+fn generate_for(context: &mut Block, for_expr: &ForExpr) -> Option<String> {
+    let elem_type =
+        TYPES.with(|types| checked(types.borrow_mut().loop_element_type(&for_expr.iterable)));
+    let targets = for_expr
+        .targets
+        .iter()
+        .map(|t| t.value.clone())
+        .collect::<Vec<_>>();
+    let target_types = match (targets.len(), &elem_type) {
+        (1, _) => vec![elem_type.clone()],
+        (_, Type::Tuple(elems)) => elems.clone(),
+        (_, Type::Pair(first, second)) => vec![(**first).clone(), (**second).clone()],
+        _ => return None,
+    };
+    in_scope(|| {
+        // loop variables are declared after the iterable is evaluated, which
+        // still sees any outer variable they shadow
+        let bind_targets = || {
+            targets
+                .iter()
+                .zip(&target_types)
+                .map(|(target, t)| declare_local(target, t.clone()))
+                .collect::<Vec<_>>()
+        };
+        // lines binding the loop variables at the top of the body
+        let mut bindings = Vec::new();
+        let header = match for_expr.iterable.as_ref() {
+            // a literal range becomes plain loop bounds, with the end evaluated once
+            Expr::RangeExpr(range) => {
+                let start = process_expression(context, &range.start)?;
+                let end = process_expression(context, &range.end)?;
+                let var = bind_targets().remove(0);
+                let end_var = generate_variable_name();
+                let cmp = if range.inclusive { "<=" } else { "<" };
+                format!(
+                    "for (ll {} = {}, {} = {}; {} {} {}; {}++) ",
+                    var, start, end_var, end, var, cmp, end_var, var
+                )
+            }
+            // enumerate and zip walk their lists by index, copying each element
+            Expr::MethodCallExpr(call)
+                if matches!(call.method_name.value.as_str(), "enumerate" | "zip") =>
+            {
+                let lists = call
+                    .args
+                    .iter()
+                    .map(|arg| {
+                        let value = process_expression(context, arg)?;
+                        let list = generate_variable_name();
+                        context.add_line(format!("auto&& {} = {};", list, value));
+                        Some(list)
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                let names = bind_targets();
+                let (index, elem_targets) = if call.method_name.value == "enumerate" {
+                    (names[0].clone(), &names[1..])
+                } else {
+                    (generate_variable_name(), &names[..])
+                };
+                let elem_types = &target_types[target_types.len() - elem_targets.len()..];
+                for ((target, t), list) in elem_targets.iter().zip(elem_types).zip(&lists) {
+                    bindings.push(format!(
+                        "const {} {} = {}[{}];",
+                        get_type_string(t),
+                        target,
+                        list,
+                        index
+                    ));
+                }
+                // as for a plain list, the loop stops at the shortest starting
+                // length, or sooner if a list shrinks
+                let size = generate_variable_name();
+                let length = match lists.as_slice() {
+                    [list] => format!("{}.size()", list),
+                    lists => format!(
+                        "min({{{}}})",
+                        lists
+                            .iter()
+                            .map(|l| format!("{}.size()", l))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                };
+                context.add_line(format!("const ll {} = {};", size, length));
+                let in_bounds = lists
+                    .iter()
+                    .map(|l| format!(" && {} < (ll){}.size()", index, l))
+                    .collect::<String>();
+                format!(
+                    "for (ll {} = 0; {} < {}{}; {}++) ",
+                    index, index, size, in_bounds, index
+                )
+            }
+            // each in-bounds cell next to (r, c), by offsets from a table
+            Expr::MethodCallExpr(call)
+                if call.receiver.is_some()
+                    && matches!(call.method_name.value.as_str(), "neighbors4" | "neighbors8") =>
+            {
+                helpers::require(Helper::Grid);
+                let receiver = call.receiver.as_deref()?;
+                let value = process_expression(context, receiver)?;
+                let grid = evaluate_once(context, receiver, &value);
+                let r = process_expression(context, &call.args[0])?;
+                let c = process_expression(context, &call.args[1])?;
+                let (r_var, c_var) = (generate_variable_name(), generate_variable_name());
+                context.add_line(format!("const ll {} = {}, {} = {};", r_var, r, c_var, c));
+                let names = bind_targets();
+                let (row, col) = match names.as_slice() {
+                    [row, col] => (row.clone(), col.clone()),
+                    _ => (generate_variable_name(), generate_variable_name()),
+                };
+                let dir = generate_variable_name();
+                bindings.push(format!(
+                    "const ll {} = {} + bbl_dr[{}], {} = {} + bbl_dc[{}];",
+                    row, r_var, dir, col, c_var, dir
+                ));
+                bindings.push(format!(
+                    "if (!bbl_in_bounds({}, {}, {})) continue;",
+                    grid, row, col
+                ));
+                if let [cell] = names.as_slice() {
+                    bindings.push(format!("const tuple<ll, ll> {}({}, {});", cell, row, col));
+                }
+                let count = &call.method_name.value["neighbors".len()..];
+                format!("for (ll {} = 0; {} < {}; {}++) ", dir, dir, count, dir)
+            }
+            // tokens are read straight into the loop variable until input runs out
+            Expr::MethodCallExpr(call)
+                if call.receiver.is_none()
+                    && matches!(
+                        call.method_name.value.as_str(),
+                        "input_ints" | "input_floats" | "input_strs"
+                    ) =>
+            {
+                helpers::require(Helper::Input);
+                let var = bind_targets().remove(0);
+                bindings.push("bbl_mid_line = true;".to_string());
+                format!(
+                    "for ({} {}; cin >> {};) ",
+                    get_type_string(&elem_type),
+                    var,
+                    var
+                )
+            }
+            // a list is walked by index, with each element copied, so the body
+            // may push to, pop from or reassign it: the loop stops at the
+            // starting length, or sooner if the list shrinks
+            iterable if matches!(expr_type(iterable), Type::List(_)) => {
+                let value = process_expression(context, iterable)?;
+                let list = generate_variable_name();
+                let size = generate_variable_name();
+                context.add_line(format!("auto&& {} = {};", list, value));
+                context.add_line(format!("const ll {} = {}.size();", size, list));
+                let index = generate_variable_name();
+                match bind_targets().as_slice() {
+                    [var] => bindings.push(format!(
+                        "const {} {} = {}[{}];",
+                        get_type_string(&elem_type),
+                        var,
+                        list,
+                        index
+                    )),
+                    names => bindings.push(format!(
+                        "const auto [{}] = {}[{}];",
+                        names.join(", "),
+                        list,
+                        index
+                    )),
+                }
+                format!(
+                    "for (ll {} = 0; {} < {} && {} < (ll){}.size(); {}++) ",
+                    index, index, size, index, list, index
+                )
+            }
+            // elements are read in place, not copied
+            iterable => {
+                let iterable = process_expression(context, iterable)?;
+                match bind_targets().as_slice() {
+                    [var] => format!(
+                        "for (const {}& {} : {}) ",
+                        get_type_string(&elem_type),
+                        var,
+                        iterable
+                    ),
+                    names => format!("for (const auto& [{}] : {}) ", names.join(", "), iterable),
+                }
+            }
+        };
+        let mut new_block = Block::new_with_pre_block(header, context.indent_level + 1);
+        for binding in bindings {
+            new_block.add_line(binding);
+        }
+        process_statements(&mut new_block, &for_expr.body);
+        context.add_block(new_block);
+        Some(())
+    });
+    None
+}
+
+// Declares each input variable and reads it. A list is sized up front and
+// filled by nested loops, e.g. `vector<vector<ll>> g(n, vector<ll>(m));`.
+fn generate_input_header(context: &mut Block, header: &InputHeader) -> Option<String> {
+    helpers::require(Helper::Input);
+    for field in &header.fields {
+        let dims = field
+            .dims
             .iter()
-            .map(|e| process_expression(context, e))
-            .collect::<Option<Vec<_>>>()?
-            .join(", ")
-        + "}";
-    Some(joined_string.to_string())
+            .map(|dim| process_expression(context, dim))
+            .collect::<Option<Vec<_>>>()?;
+        let field_type = &field.target.associated_type;
+        let name = declare_local(&field.target.value.value, field_type.clone());
+        let mut layer_types = Vec::new();
+        let mut layer = field_type;
+        for _ in &dims {
+            layer_types.push(get_type_string(layer));
+            if let Type::List(inner) = layer {
+                layer = inner;
+            }
+        }
+        // constructor arguments, built from the innermost layer out
+        let mut size_args = dims.last().cloned().unwrap_or_default();
+        for i in (0..dims.len().saturating_sub(1)).rev() {
+            size_args = format!("{}, {}({})", dims[i], layer_types[i + 1], size_args);
+        }
+        if dims.is_empty() {
+            context.add_line(format!("{} {};", get_type_string(field_type), name));
+        } else {
+            context.add_line(format!(
+                "{} {}({});",
+                get_type_string(field_type),
+                name,
+                size_args
+            ));
+        }
+        let mut target = name;
+        let mut loops = String::new();
+        for _ in &dims {
+            let elem = generate_variable_name();
+            loops.push_str(&format!("for (auto& {} : {}) ", elem, target));
+            target = elem;
+        }
+        context.add_line(format!("{}cin >> {};", loops, target));
+    }
+    context.add_line("bbl_mid_line = true;".to_string());
+    None
+}
+
+// `list<list<int>>(n, m, 0)` becomes `vector<vector<ll>>(n, vector<ll>(m, 0LL))`
+fn generate_list_init(context: &mut Block, init: &ListInitExpr) -> Option<String> {
+    let mut operands = init.dims.iter().collect::<Vec<_>>();
+    operands.push(&init.fill);
+    let mut dims = generate_in_order(context, &operands)?;
+    let mut value = dims.pop()?;
+    // the list type at each nesting level, outermost first
+    let mut layer_types = Vec::new();
+    let mut layer = &init.list_type;
+    for _ in &dims {
+        layer_types.push(layer);
+        if let Type::List(inner) = layer {
+            layer = inner;
+        }
+    }
+    for (dim, layer_type) in dims.iter().zip(layer_types).rev() {
+        value = format!("{}({}, {})", get_type_string(layer_type), dim, value);
+    }
+    Some(value)
+}
+
+// The element type is spelled out, or string literals would make a
+// `vector<const char*>` no string function takes
+fn generate_list_expr(context: &mut Block, list: &ListExpr, list_type: &Type) -> Option<String> {
+    let elems = list
+        .elems
+        .iter()
+        .map(|e| process_expression(context, e))
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!(
+        "{}{{{}}}",
+        get_type_string(list_type),
+        elems.join(", ")
+    ))
+}
+
+// Spelling out the type keeps a string literal from being taken for a
+// `const char*`, which compares by address
+fn generate_tuple_expr(
+    context: &mut Block,
+    tuple: &TupleExpr,
+    tuple_type: &Type,
+) -> Option<String> {
+    let elems = generate_in_order(context, &tuple.elems.iter().collect::<Vec<_>>())?;
+    Some(format!(
+        "{}{{{}}}",
+        get_type_string(tuple_type),
+        elems.join(", ")
+    ))
 }
 
 fn generate_binop(context: &mut Block, binop: &BinOpExpr) -> Option<String> {
-    let left_result = process_expression(context, &binop.left)?;
-    let right_result = process_expression(context, &binop.right)?;
-    Some(format!("{} {} {}", left_result, binop.op, right_result))
+    if binop.op == BinOp::Coalesce {
+        return generate_coalesce(context, binop);
+    }
+    if let Type::Set(_) = expr_type(&binop.left) {
+        let values = generate_in_order(context, &[&binop.left, &binop.right])?;
+        let (left, right) = (&values[0], &values[1]);
+        let algebra = match binop.op {
+            BinOp::BitOr => "set_union",
+            BinOp::BitAnd => "set_intersection",
+            _ => "set_difference",
+        };
+        helpers::require(Helper::SetAlgebra);
+        return Some(format!("bbl_{}({}, {})", algebra, left, right));
+    }
+    let (mut left_result, mut right_result) = match binop.op {
+        // C++ already runs the left side first, and the right one only if needed
+        BinOp::And | BinOp::Or => (
+            process_expression(context, &binop.left)?,
+            conditionally(context, &binop.right)?,
+        ),
+        _ => {
+            let mut values = generate_in_order(context, &[&binop.left, &binop.right])?;
+            let right = values.pop()?;
+            (values.pop()?, right)
+        }
+    };
+    // two literals are char arrays, which can't be added
+    if is_char_array(&binop.left) {
+        left_result = format!("string({})", left_result);
+    }
+    // an int mixed with a float is promoted
+    match (expr_type(&binop.left), expr_type(&binop.right)) {
+        (Type::Int, Type::Float) => left_result = format!("(double){}", left_result),
+        (Type::Float, Type::Int) => right_result = format!("(double){}", right_result),
+        _ => {}
+    }
+    // Always parenthesize so the C++ keeps the grouping of the AST: BBL's
+    // precedence differs from C++ (bitwise above comparisons) and shifts bind
+    // looser than `<<` on streams.
+    match binop.op {
+        // true division: promote int operands so C++ doesn't truncate
+        BinOp::Div
+            if is_integer(&expr_type(&binop.left)) && is_integer(&expr_type(&binop.right)) =>
+        {
+            Some(format!("((double){} / {})", left_result, right_result))
+        }
+        BinOp::IntDiv => Some(format!("({} / {})", left_result, right_result)),
+        op => Some(format!("({} {} {})", left_result, op, right_result)),
+    }
+}
+
+// `x ?? default`: the option is read twice, so anything but a plain name is
+// evaluated once into a temporary first
+fn generate_coalesce(context: &mut Block, binop: &BinOpExpr) -> Option<String> {
+    let mut option = process_expression(context, &binop.left)?;
+    if !matches!(*binop.left, Expr::Identifier(_)) {
+        let temp = generate_variable_name();
+        context.add_line(format!("auto {} = {};", temp, option));
+        option = temp;
+    }
+    let fallback = conditionally(context, &binop.right)?;
+    // an optional default keeps the result optional
+    let value = match expr_type(&binop.right) {
+        Type::Option(_) => option.clone(),
+        _ => format!("*{}", option),
+    };
+    Some(format!(
+        "({}.has_value() ? {} : {})",
+        option, value, fallback
+    ))
 }
 
 fn generate_unop(context: &mut Block, unop: &UnOpExpr) -> Option<String> {
@@ -0,0 +1,444 @@
+#[cfg(test)]
+mod tests {
+    use crate::codegen::generate;
+    use bbl_frontend::parser::parse_program;
+    use bbl_frontend::typeck::TypeChecker;
+    use std::fs;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // Compiles `src` with g++ and runs it on `stdin`, returning its output
+    fn run(test_name: &str, src: &str, stdin: &str) -> String {
+        let program = parse_program(src).unwrap().Program().unwrap();
+        TypeChecker::new().check_program(&program).unwrap();
+        let dir = std::env::temp_dir().join(format!("bbl_{}_{}", test_name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.cpp");
+        let binary = dir.join("main");
+        fs::write(&source, generate(&program)).unwrap();
+        let compiled = Command::new("g++")
+            .args(["-std=c++17", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .output()
+            .expect("g++ must be installed to run the codegen tests");
+        assert!(
+            compiled.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_evaluation_order() {
+        // a pure operand is read before a later one changes what it reads
+        assert_eq!(
+            run(
+                "order_pure",
+                "var xs = [1, 2, 3]\nprint(len(xs) + xs.pop())\n",
+                ""
+            ),
+            "6\n"
+        );
+        assert_eq!(
+            run(
+                "order_reads",
+                "def f(a: int, b: int) -> int {\nreturn a * 10 + b\n}\nprint(f(read_int(), read_int()))\n",
+                "1 2"
+            ),
+            "12\n"
+        );
+        // a variable or element is read before a later operand assigns it,
+        // while a receiver or `ref` argument stays the place it names
+        let src = r#"var x = 1
+def bump() -> int {
+x = 100
+return 1
+}
+print(x + bump())
+var xs = [1, 2, 3]
+def mutate_xs() -> int {
+xs[0] = 50
+return 0
+}
+print(xs[0] + mutate_xs())
+var flags = [1 > 0]
+def flip() -> bool {
+flags[0] = 0 > 1
+return 0 > 1
+}
+print(flags[0] == flip())
+def grow(ref ys: list<int>, n: int) {
+ys.push(n)
+}
+grow(xs, len(xs) + bump())
+xs.push(mutate_xs())
+print(f"{len(xs)} {xs[0]}")
+"#;
+        assert_eq!(run("order_places", src, ""), "2\n1\n0\n5 50\n");
+        // an operand that doesn't run reads nothing
+        let src = r#"val n = read_int()
+val o: option<int> = some(3)
+val y = o ?? read_int()
+val z = 1 if n > 100 else read_int()
+val w = n > 100 && read_int() > 0
+val v = n > 0 || read_int() > 0
+print(f"{y} {z} {w} {v} {read_int()}")
+"#;
+        assert_eq!(run("order_conditional", src, "5 7 8"), "3 7 0 1 8\n");
+    }
+
+    #[test]
+    fn test_overload_names() {
+        // overloads and redefinitions get C++ names no bbl name can take
+        let src = r#"def show(x: int) -> int {
+return 1
+}
+def show(s: string) -> int {
+return 2
+}
+def show(x: int) -> int {
+return 3
+}
+def show_int(x: int) -> int {
+return 4
+}
+def show_string(x: int) -> int {
+return 5
+}
+print(show(0) + show("") + show_int(0) + show_string(0))
+"#;
+        assert_eq!(run("overload_names", src, ""), "14\n");
+    }
+
+    #[test]
+    fn test_unicode_names() {
+        // a spelled-out name can't meet one written in ASCII, and raw C++
+        // keeps its digit separators
+        let src = r#"val größe = 3
+val gr_u00f6_u00dfe = 4
+struct Punkt { x: int, ÿ: int }
+val p = Punkt(1, größe)
+print(p.ÿ + größe + gr_u00f6_u00dfe)
+cpp {
+    cout << 1'000 << ' ' << bbl_ugr_u00f6_u00dfe << "\n";
+}
+"#;
+        assert_eq!(run("unicode_names", src, ""), "10\n1000 3\n");
+    }
+
+    #[test]
+    fn test_unicode_reverse() {
+        // characters are reversed, not their bytes; len still counts bytes
+        let src = r#"print(reversed("héllo 🎉"))
+var t = "añb"
+reverse(t)
+print(f"{t} {len(t)}")
+"#;
+        assert_eq!(run("unicode_reverse", src, ""), "🎉 olléh\nbña 4\n");
+    }
+
+    #[test]
+    fn test_tuples() {
+        // string elements compare as strings, not by address
+        let src = r#"print((1, "zz") < (1, "aa"))
+val ps = [(2, "b"), (2, "a")]
+print(ps[1] < ps[0])
+print((1, "a") == (1, "a"))
+"#;
+        assert_eq!(run("tuples", src, ""), "0\n1\n1\n");
+    }
+
+    #[test]
+    fn test_lists() {
+        let src = r#"print(join(["a", "b"], ","))
+val words = ["b", "a"]
+print(words[1] < words[0])
+"#;
+        assert_eq!(run("lists", src, ""), "a,b\n1\n");
+    }
+
+    #[test]
+    fn test_map_index() {
+        // reading a key leaves the map alone, so a `val` map can be read;
+        // writing one makes it
+        let src = r#"var m: map<string, int> = map<string, int>()
+m["a"] = 1
+m["a"] += 2
+val frozen = m
+print(frozen["a"] + frozen["zz"])
+def lookup(counts: map<string, int>, k: string) -> int {
+return counts[k]
+}
+print(lookup(m, "a") + lookup(m, "b"))
+var adj: map<int, list<int>> = map<int, list<int>>()
+adj[1] = [2]
+adj[5].push(7)
+print(adj[1][0] + adj[5][0] + len(adj[9]))
+print(f"{len(m)} {len(adj)}")
+"#;
+        assert_eq!(run("map_index", src, ""), "3\n3\n9\n1 2\n");
+    }
+
+    #[test]
+    fn test_char_arrays() {
+        // a choice between literals is a char array too
+        let src = r#"val c = read_int() > 0
+print(("a" if c else "b") + "c")
+print(("b" if c else "a") == "b")
+print(len("xy" if c else "z"))
+print(("hey" if c else "ho").to_upper())
+"#;
+        assert_eq!(run("char_arrays", src, "1"), "ac\n1\n2\nHEY\n");
+    }
+
+    #[test]
+    fn test_try() {
+        // an index out of range or a string only partly a number fails
+        let src = r#"val xs = [1, 2, 3]
+val g = grid<int>(2, 2, 7)
+print(f"{try xs[5] else -2} {try xs[1] else -2} {try g[1][3] else -3}")
+print(f"{try int("12abc") else -4} {try int("12") else -4}")
+print(try float("1.5x") else -1.0)
+"#;
+        assert_eq!(run("try", src, ""), "-2 2 -3\n-4 12\n-1\n");
+    }
+
+    #[test]
+    fn test_loop_mutates_list() {
+        // the loop sees the elements the list started with
+        let src = r#"var a = [1, 2, 3]
+for x in a {
+a.push(x * 10)
+}
+print(len(a))
+for x in a {
+a.pop()
+print(x)
+}
+var d = [5, 6, 7]
+var e = [1, 2]
+for (u, v) in zip(d, e) {
+d.push(u)
+e.pop()
+e.pop()
+print(u + v)
+}
+"#;
+        assert_eq!(run("loop_mutates_list", src, ""), "6\n1\n2\n3\n6\n");
+    }
+
+    #[test]
+    fn test_list_helpers() {
+        // dedup sorts ascending whatever order sort_by left
+        let src = r#"def neg(x: int) -> int {
+return -x
+}
+def show(x: int) -> string {
+return str(x)
+}
+def big(x: int) -> bool {
+return x * x >= 50
+}
+def later(a: int, b: int) -> bool {
+return a > b
+}
+var xs = [5, 3, 5, 1]
+xs.insert(1, 9)
+xs.remove(0)
+print(xs.pop() + len(xs))
+sort(xs, neg)
+print(join(map(xs, show), " "))
+sort_by(xs, later)
+dedup(xs)
+reverse(xs)
+print(f"{xs[0]} {len(xs)} {count([1, 1, 2], 1)} {min(xs)} {max([4, 8])}")
+val sorted_xs = [1, 3, 3, 7]
+print(f"{lower_bound(sorted_xs, 3)} {upper_bound(sorted_xs, 3)} {bsearch(0, 100, big)}")
+print(prefix_sums(sorted_xs)[4])
+print(len(filter(sorted_xs, big)))
+print(reversed(sorted_xs)[0])
+print(f"{pow(3, 4)} {modpow(2, 10, 1000)} {isqrt(99)} {abs(-4)}")
+"#;
+        assert_eq!(
+            run("list_helpers", src, ""),
+            "4\n9 5 3\n9 3 2 3 8\n1 3 8\n14\n0\n7\n81 24 9 4\n"
+        );
+    }
+
+    #[test]
+    fn test_string_helpers() {
+        // splitting on a separator keeps empty pieces, on whitespace it does not
+        let src = r#"val parts = "a,,b".split(",")
+print(len(parts))
+print(join("x  y z".split(), "-"))
+print(" Hi There ".trim().to_lower().replace("there", "all"))
+print(f"{"abc".find("c")} {"abc".contains("d")}")
+print(parse_int("42") ?? 0)
+print(parse_float("x") ?? 1.5)
+print(format("{:.2} {}", 3.14159, 7))
+"#;
+        assert_eq!(
+            run("string_helpers", src, ""),
+            "3\nx-y-z\nhi all\n2 0\n42\n1.5\n3.14 7\n"
+        );
+    }
+
+    #[test]
+    fn test_map_methods() {
+        // map and set methods, and looping over them in key order
+        let src = r#"var m = map<string, int>()
+m.insert("b", 2)
+m.insert("a", 1)
+print(m.get("a") ?? 0)
+print(m.get("z") ?? -1)
+print(m.contains("b"))
+print(join(m.keys(), ","))
+var total = 0
+for v in m.values() {
+total += v
+}
+for (k, v) in m {
+print(f"{k}={v}")
+}
+print(total)
+var s = set<int>()
+s.insert(3)
+s.insert(1)
+val t = s | set<int>()
+s.erase(3)
+print(f"{s.size()} {t.size()} {s.contains(1)}")
+"#;
+        assert_eq!(
+            run("map_methods", src, ""),
+            "1\n-1\n1\na,b\na=1\nb=2\n3\n1 2 1\n"
+        );
+    }
+
+    #[test]
+    fn test_heaps() {
+        // a heap pops its largest element and a min-heap its smallest
+        let src = r#"var h = heap<int>()
+h.push(3)
+h.push(9)
+h.push(1)
+print(h.top())
+print(h.pop() + h.pop())
+var m = min_heap<tuple<int, string>>()
+m.push((2, "b"))
+m.push((1, "a"))
+print(m.pop().1)
+print(f"{h.size()} {m.size()}")
+"#;
+        assert_eq!(run("heaps", src, ""), "9\n12\na\n1 1\n");
+    }
+
+    #[test]
+    fn test_stacks_queues_deques() {
+        // each pop returns the element it removes
+        let src = r#"var s = stack<int>()
+s.push(1)
+s.push(2)
+print(s.pop() * 10 + s.top())
+var q = queue<string>()
+q.push("x")
+q.push("y")
+print(q.front() + q.back())
+print(q.pop())
+var d = deque<int>()
+d.push_back(5)
+d.push_front(4)
+d.push_back(6)
+print(d[0] + d[2])
+print(d.pop_front() * d.pop_back())
+print(f"{s.size()} {q.size()} {d.size()}")
+"#;
+        assert_eq!(
+            run("stacks_queues_deques", src, ""),
+            "21\nxy\nx\n10\n24\n1 1 1\n"
+        );
+    }
+
+    #[test]
+    fn test_grids() {
+        // neighbors only visit cells inside the grid
+        let src = r#"var g = grid<int>(2, 3, 1)
+g[1][2] = 5
+var total = 0
+for (r, c) in g.neighbors4(1, 1) {
+total += g[r][c]
+}
+var cells = 0
+for (r, c) in g.neighbors8(0, 0) {
+cells += 1
+}
+print(f"{total} {cells} {g.rows()} {g.cols()} {g.in_bounds(2, 0)}")
+"#;
+        assert_eq!(run("grids", src, ""), "7 3 2 3 0\n");
+    }
+
+    #[test]
+    fn test_mint() {
+        // results stay reduced, and an int on either side converts
+        let src = r#"val a = mint<998244353>(998244352)
+val b = a + 2
+print(b)
+print(a * a)
+print(int(mint<7>(3) / 2))
+print(pow(mint<1000000007>(2), 40))
+print(1 - mint<5>(3))
+"#;
+        assert_eq!(run("mint", src, ""), "1\n1\n5\n511620083\n3\n");
+    }
+
+    #[test]
+    fn test_i128() {
+        // values past 64 bits print in full
+        let src = r#"val big = 170141183460469231731687303715884105727
+print(big)
+val x = i128(4000000000) * 4000000000
+print(x)
+print(-x + 1)
+print(int(x / 4000000000))
+print(x > 1)
+"#;
+        assert_eq!(run("i128", src, ""), "170141183460469231731687303715884105727\n16000000000000000000\n-15999999999999999999\n4000000000\n1\n");
+    }
+
+    #[test]
+    fn test_bitsets() {
+        // subset sums of 2 and 3 by shifting; sizes and indices are checked
+        let src = r#"var b = bitset(10)
+b.set(1)
+b.set(3)
+b.flip(4)
+b.reset(3)
+print(f"{b.count()} {b.test(1)} {b.test(3)} {b.size()}")
+var dp = bitset(10)
+dp.set(0)
+for w in [2, 3] {
+dp |= dp << w
+}
+print(f"{dp.test(5)} {dp.test(4)} {dp.count()}")
+val c = (dp & b) | (dp ^ b)
+print(c.count())
+print((dp >> 2).test(3))
+val wide = bitset(70)
+print(f"{try (dp | wide).count() else -1} {try dp.test(10) else 1 > 0} {dp == wide}")
+"#;
+        assert_eq!(run("bitsets", src, ""), "2 1 0 10\n1 0 4\n6\n1\n-1 1 0\n");
+    }
+}
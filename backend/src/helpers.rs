@@ -0,0 +1,465 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+// C++ support code emitted above solve() only when the program uses it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Helper {
+    I128,
+    Range,
+    Input,
+    ReadLine,
+    TestCount,
+    Pow,
+    ModPow,
+    Isqrt,
+    Random,
+    Clock,
+    Bsearch,
+    Split,
+    SplitWhitespace,
+    Join,
+    Replace,
+    Case,
+    Trim,
+    ReverseText,
+    Parse,
+    MapGet,
+    MapItems,
+    SetAlgebra,
+    Grid,
+    ModInt,
+    Bitset,
+}
+
+impl Helper {
+    fn source(self) -> &'static str {
+        match self {
+            Helper::I128 => I128,
+            Helper::Range => RANGE,
+            Helper::Input => INPUT,
+            Helper::ReadLine => READ_LINE,
+            Helper::TestCount => TEST_COUNT,
+            Helper::Pow => POW,
+            Helper::ModPow => MOD_POW,
+            Helper::Isqrt => ISQRT,
+            Helper::Random => RANDOM,
+            Helper::Clock => CLOCK,
+            Helper::Bsearch => BSEARCH,
+            Helper::Split => SPLIT,
+            Helper::SplitWhitespace => SPLIT_WHITESPACE,
+            Helper::Join => JOIN,
+            Helper::Replace => REPLACE,
+            Helper::Case => CASE,
+            Helper::Trim => TRIM,
+            Helper::ReverseText => REVERSE_TEXT,
+            Helper::Parse => PARSE,
+            Helper::MapGet => MAP_GET,
+            Helper::MapItems => MAP_ITEMS,
+            Helper::SetAlgebra => SET_ALGEBRA,
+            Helper::Grid => GRID,
+            Helper::ModInt => MOD_INT,
+            Helper::Bitset => BITSET,
+        }
+    }
+}
+
+thread_local! {
+    static REQUIRED: RefCell<BTreeSet<Helper>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+pub fn require(helper: Helper) {
+    REQUIRED.with(|required| required.borrow_mut().insert(helper));
+}
+
+// Source of every helper required since the last call, in declaration order
+pub fn take_required() -> Vec<&'static str> {
+    REQUIRED.with(|required| {
+        std::mem::take(&mut *required.borrow_mut())
+            .into_iter()
+            .map(Helper::source)
+            .collect()
+    })
+}
+
+// Streams have no operator<< for __int128. Digits are taken from the value
+// as is, since negating the smallest value would overflow.
+const I128: &str = "string bbl_i128_to_string(__int128 x) {
+    if (x == 0) return \"0\";
+    bool negative = x < 0;
+    string digits;
+    while (x != 0) {
+        int digit = (int)(x % 10);
+        digits += (char)('0' + (negative ? -digit : digit));
+        x /= 10;
+    }
+    if (negative) digits += '-';
+    reverse(digits.begin(), digits.end());
+    return digits;
+}
+ostream& operator<<(ostream& out, __int128 x) { return out << bbl_i128_to_string(x); }";
+
+// Half-open integer range [lo, hi) usable in range-based for loops
+const RANGE: &str = "struct bbl_range {
+    ll lo, hi;
+    struct iterator {
+        ll i;
+        ll operator*() const { return i; }
+        iterator& operator++() { ++i; return *this; }
+        bool operator!=(const iterator& o) const { return i != o.i; }
+    };
+    iterator begin() const { return {lo}; }
+    iterator end() const { return {max(lo, hi)}; }
+};";
+
+// Set by token reads (`cin >> x`), which stop before the end of their line
+const INPUT: &str = "bool bbl_mid_line = false;";
+
+// After a token read, getline would return the rest of that line, usually
+// empty. Finish that line first unless something other than spaces is left.
+const READ_LINE: &str = "string bbl_read_line() {
+    if (bbl_mid_line) {
+        while (cin.peek() == ' ' || cin.peek() == '\\t' || cin.peek() == '\\r') cin.get();
+        if (cin.peek() == '\\n') cin.get();
+        bbl_mid_line = false;
+    }
+    string line;
+    getline(cin, line);
+    if (!line.empty() && line.back() == '\\r') line.pop_back();
+    return line;
+}";
+
+// The test count heading the input of a multi-test program
+const TEST_COUNT: &str = "ll bbl_read_test_count() {
+    ll t;
+    cin >> t;
+    bbl_mid_line = true;
+    return t;
+}";
+
+// Integer power by repeated squaring; base is only squared while bits of exp
+// remain, so it can't overflow past the result. Negative exponents give 1.
+const POW: &str = "ll bbl_pow(ll base, ll exp) {
+    ll result = 1;
+    while (exp > 0) {
+        if (exp & 1) result *= base;
+        exp >>= 1;
+        if (exp > 0) base *= base;
+    }
+    return result;
+}";
+
+// base^exp mod m in [0, m), with 128-bit products so any 64-bit m works
+const MOD_POW: &str = "ll bbl_modpow(ll base, ll exp, ll mod) {
+    ll result = 1 % mod;
+    base %= mod;
+    if (base < 0) base += mod;
+    while (exp > 0) {
+        if (exp & 1) result = (__int128)result * base % mod;
+        base = (__int128)base * base % mod;
+        exp >>= 1;
+    }
+    return result;
+}";
+
+// Floor of the square root of n >= 0. The floating-point estimate can be off
+// by one for large n, so it is corrected with exact 128-bit squares.
+const ISQRT: &str = "ll bbl_isqrt(ll n) {
+    if (n <= 0) return 0;
+    ll r = sqrtl((long double)n);
+    while ((__int128)r * r > n) r--;
+    while ((__int128)(r + 1) * (r + 1) <= n) r++;
+    return r;
+}";
+
+// Seeded from the clock so every run differs; rand_int's bounds are inclusive
+const RANDOM: &str = "mt19937_64 bbl_rng(chrono::steady_clock::now().time_since_epoch().count());
+ll bbl_rand_int(ll lo, ll hi) {
+    return uniform_int_distribution<ll>(lo, hi)(bbl_rng);
+}";
+
+// Globals are initialized before main runs, so this is the program's start
+const CLOCK: &str = "const auto bbl_start_time = chrono::steady_clock::now();
+ll bbl_elapsed_ms() {
+    return chrono::duration_cast<chrono::milliseconds>(chrono::steady_clock::now() - bbl_start_time).count();
+}";
+
+// Smallest x in [lo, hi) with pred(x), or hi, for pred false then true. The
+// midpoint is computed unsigned so lo and hi may span the whole ll range.
+const BSEARCH: &str = "template <class F>
+ll bbl_bsearch(ll lo, ll hi, F pred) {
+    while (lo < hi) {
+        ll mid = lo + (ll)(((unsigned long long)hi - (unsigned long long)lo) / 2);
+        if (pred(mid)) hi = mid;
+        else lo = mid + 1;
+    }
+    return lo;
+}";
+
+// Like Python's str.split(sep): empty pieces between adjacent separators are
+// kept, and an empty separator is an error
+const SPLIT: &str = "vector<string> bbl_split(const string& s, const string& sep) {
+    if (sep.empty()) throw invalid_argument(\"split: empty separator\");
+    vector<string> parts;
+    size_t start = 0, end;
+    while ((end = s.find(sep, start)) != string::npos) {
+        parts.push_back(s.substr(start, end - start));
+        start = end + sep.size();
+    }
+    parts.push_back(s.substr(start));
+    return parts;
+}";
+
+// Like Python's str.split(): words between runs of whitespace
+const SPLIT_WHITESPACE: &str = "vector<string> bbl_split_ws(const string& s) {
+    vector<string> parts;
+    istringstream words(s);
+    string word;
+    while (words >> word) parts.push_back(word);
+    return parts;
+}";
+
+const JOIN: &str = "string bbl_join(const vector<string>& parts, const string& sep) {
+    string joined;
+    for (size_t i = 0; i < parts.size(); i++) {
+        if (i > 0) joined += sep;
+        joined += parts[i];
+    }
+    return joined;
+}";
+
+// Every non-overlapping occurrence, left to right; an empty pattern matches
+// nothing
+const REPLACE: &str = "string bbl_replace(const string& s, const string& from, const string& to) {
+    if (from.empty()) return s;
+    string result;
+    size_t start = 0, end;
+    while ((end = s.find(from, start)) != string::npos) {
+        result.append(s, start, end - start);
+        result += to;
+        start = end + from.size();
+    }
+    result.append(s, start, string::npos);
+    return result;
+}";
+
+// toupper/tolower take an int that must fit in unsigned char
+const CASE: &str = "string bbl_to_upper(string s) {
+    transform(s.begin(), s.end(), s.begin(), [](unsigned char c) { return toupper(c); });
+    return s;
+}
+string bbl_to_lower(string s) {
+    transform(s.begin(), s.end(), s.begin(), [](unsigned char c) { return tolower(c); });
+    return s;
+}";
+
+const TRIM: &str = "string bbl_trim(const string& s) {
+    size_t start = s.find_first_not_of(\" \\t\\r\\n\\v\\f\");
+    if (start == string::npos) return \"\";
+    size_t end = s.find_last_not_of(\" \\t\\r\\n\\v\\f\");
+    return s.substr(start, end - start + 1);
+}";
+
+// Reverses by code point rather than by byte: each UTF-8 sequence is flipped
+// first, so flipping the whole string puts its bytes back in order
+const REVERSE_TEXT: &str = "void bbl_reverse_text(string& s) {
+    for (size_t i = 0; i < s.size();) {
+        size_t j = i + 1;
+        while (j < s.size() && ((unsigned char)s[j] & 0xC0) == 0x80) j++;
+        reverse(s.begin() + i, s.begin() + j);
+        i = j;
+    }
+    reverse(s.begin(), s.end());
+}
+string bbl_reversed_text(string s) {
+    bbl_reverse_text(s);
+    return s;
+}";
+
+// stoll/stod accept a numeric prefix ("12abc") and throw on garbage; these
+// give nullopt instead unless only whitespace follows the number
+const PARSE: &str = "bool bbl_parsed_all(const string& s, size_t end) {
+    while (end < s.size() && isspace((unsigned char)s[end])) end++;
+    return end == s.size();
+}
+optional<ll> bbl_parse_int(const string& s) {
+    try {
+        size_t end;
+        ll value = stoll(s, &end);
+        if (bbl_parsed_all(s, end)) return value;
+    } catch (...) {}
+    return nullopt;
+}
+optional<double> bbl_parse_float(const string& s) {
+    try {
+        size_t end;
+        double value = stod(s, &end);
+        if (bbl_parsed_all(s, end)) return value;
+    } catch (...) {}
+    return nullopt;
+}";
+
+// Looks a key up without inserting it, unlike operator[]
+const MAP_GET: &str = "template <class M, class K>
+optional<typename M::mapped_type> bbl_map_get(const M& m, const K& key) {
+    auto it = m.find(key);
+    if (it == m.end()) return nullopt;
+    return it->second;
+}
+// m[key] without inserting the key, so it also reads a const map
+template <class M, class K>
+const typename M::mapped_type& bbl_map_read(const M& m, const K& key) {
+    static const typename M::mapped_type missing{};
+    auto it = m.find(key);
+    return it == m.end() ? missing : it->second;
+}";
+
+const MAP_ITEMS: &str = "template <class K, class V>
+vector<K> bbl_keys(const map<K, V>& m) {
+    vector<K> keys;
+    for (const auto& [key, value] : m) keys.push_back(key);
+    return keys;
+}
+template <class K, class V>
+vector<V> bbl_values(const map<K, V>& m) {
+    vector<V> values;
+    for (const auto& [key, value] : m) values.push_back(value);
+    return values;
+}";
+
+// `a | b`, `a & b` and `a - b` on sets, each building a new set
+const SET_ALGEBRA: &str = "template <class T>
+set<T> bbl_set_union(const set<T>& a, const set<T>& b) {
+    set<T> result = a;
+    result.insert(b.begin(), b.end());
+    return result;
+}
+template <class T>
+set<T> bbl_set_intersection(const set<T>& a, const set<T>& b) {
+    set<T> result;
+    for (const T& x : a) if (b.count(x)) result.insert(x);
+    return result;
+}
+template <class T>
+set<T> bbl_set_difference(const set<T>& a, const set<T>& b) {
+    set<T> result;
+    for (const T& x : a) if (!b.count(x)) result.insert(x);
+    return result;
+}";
+
+// Offsets to the neighbors of a cell: up, right, down and left, then the
+// diagonals
+const GRID: &str = "const ll bbl_dr[] = {-1, 0, 1, 0, -1, -1, 1, 1};
+const ll bbl_dc[] = {0, 1, 0, -1, -1, 1, 1, -1};
+template <class T>
+bool bbl_in_bounds(const vector<vector<T>>& g, ll r, ll c) {
+    return r >= 0 && r < (ll)g.size() && c >= 0 && c < (ll)g[r].size();
+}";
+
+// Integers mod MOD, kept in [0, MOD). Plain ints convert implicitly, so
+// `m + 1` works either way round. Division multiplies by the inverse from
+// Fermat's little theorem, so MOD must be prime.
+const MOD_INT: &str = "template <ll MOD>
+struct bbl_mint {
+    ll v;
+    bbl_mint(ll x = 0) : v(x % MOD) {
+        if (v < 0) v += MOD;
+    }
+    bbl_mint pow(ll exp) const {
+        bbl_mint result = 1, base = *this;
+        while (exp > 0) {
+            if (exp & 1) result *= base;
+            base *= base;
+            exp >>= 1;
+        }
+        return result;
+    }
+    bbl_mint& operator+=(bbl_mint o) {
+        v += o.v;
+        if (v >= MOD) v -= MOD;
+        return *this;
+    }
+    bbl_mint& operator-=(bbl_mint o) {
+        v -= o.v;
+        if (v < 0) v += MOD;
+        return *this;
+    }
+    bbl_mint& operator*=(bbl_mint o) {
+        v = (__int128)v * o.v % MOD;
+        return *this;
+    }
+    bbl_mint& operator/=(bbl_mint o) { return *this *= o.pow(MOD - 2); }
+    bbl_mint operator-() const { return bbl_mint() - *this; }
+    friend bbl_mint operator+(bbl_mint a, bbl_mint b) { return a += b; }
+    friend bbl_mint operator-(bbl_mint a, bbl_mint b) { return a -= b; }
+    friend bbl_mint operator*(bbl_mint a, bbl_mint b) { return a *= b; }
+    friend bbl_mint operator/(bbl_mint a, bbl_mint b) { return a /= b; }
+    friend bool operator==(bbl_mint a, bbl_mint b) { return a.v == b.v; }
+    friend bool operator!=(bbl_mint a, bbl_mint b) { return a.v != b.v; }
+    explicit operator ll() const { return v; }
+    friend ostream& operator<<(ostream& out, bbl_mint m) { return out << m.v; }
+};";
+
+// Runtime-sized bitset in 64-bit words. Bits past n stay clear, so count and
+// == can look at whole words. Sizes are only known at run time, so an index
+// out of range or a binary operator on bitsets of different sizes throws.
+const BITSET: &str = "struct bbl_bitset {
+    ll n;
+    vector<unsigned long long> words;
+    bbl_bitset(ll n = 0) : n(n), words((n + 63) / 64) {}
+    void check(ll i) const {
+        if (i < 0 || i >= n) throw out_of_range(\"bitset index \" + to_string(i) + \" out of range for size \" + to_string(n));
+    }
+    void check_size(const bbl_bitset& o) const {
+        if (n != o.n) throw invalid_argument(\"bitsets of sizes \" + to_string(n) + \" and \" + to_string(o.n) + \" can't be combined\");
+    }
+    bool test(ll i) const { check(i); return words[i >> 6] >> (i & 63) & 1; }
+    void set(ll i) { check(i); words[i >> 6] |= 1ULL << (i & 63); }
+    void reset(ll i) { check(i); words[i >> 6] &= ~(1ULL << (i & 63)); }
+    void flip(ll i) { check(i); words[i >> 6] ^= 1ULL << (i & 63); }
+    ll count() const {
+        ll total = 0;
+        for (unsigned long long w : words) total += __builtin_popcountll(w);
+        return total;
+    }
+    bbl_bitset operator<<(ll k) const {
+        bbl_bitset result(n);
+        ll shift = k >> 6, bits = k & 63;
+        for (ll i = (ll)words.size() - 1; i >= shift; i--) {
+            result.words[i] = words[i - shift] << bits;
+            if (bits && i - shift > 0) result.words[i] |= words[i - shift - 1] >> (64 - bits);
+        }
+        if (n & 63) result.words.back() &= (1ULL << (n & 63)) - 1;
+        return result;
+    }
+    bbl_bitset operator>>(ll k) const {
+        bbl_bitset result(n);
+        ll shift = k >> 6, bits = k & 63, size = words.size();
+        for (ll i = 0; i + shift < size; i++) {
+            result.words[i] = words[i + shift] >> bits;
+            if (bits && i + shift + 1 < size) result.words[i] |= words[i + shift + 1] << (64 - bits);
+        }
+        return result;
+    }
+    bbl_bitset& operator<<=(ll k) { return *this = *this << k; }
+    bbl_bitset& operator>>=(ll k) { return *this = *this >> k; }
+    bbl_bitset& operator&=(const bbl_bitset& o) {
+        check_size(o);
+        for (size_t i = 0; i < words.size(); i++) words[i] &= o.words[i];
+        return *this;
+    }
+    bbl_bitset& operator|=(const bbl_bitset& o) {
+        check_size(o);
+        for (size_t i = 0; i < words.size(); i++) words[i] |= o.words[i];
+        return *this;
+    }
+    bbl_bitset& operator^=(const bbl_bitset& o) {
+        check_size(o);
+        for (size_t i = 0; i < words.size(); i++) words[i] ^= o.words[i];
+        return *this;
+    }
+    friend bbl_bitset operator&(bbl_bitset a, const bbl_bitset& b) { return a &= b; }
+    friend bbl_bitset operator|(bbl_bitset a, const bbl_bitset& b) { return a |= b; }
+    friend bbl_bitset operator^(bbl_bitset a, const bbl_bitset& b) { return a ^= b; }
+    friend bool operator==(const bbl_bitset& a, const bbl_bitset& b) { return a.n == b.n && a.words == b.words; }
+    friend bool operator!=(const bbl_bitset& a, const bbl_bitset& b) { return !(a == b); }
+};";
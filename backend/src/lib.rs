@@ -1,2 +1,5 @@
 pub mod codegen;
+#[cfg(test)]
+mod codegen_test;
+mod helpers;
 pub use codegen::*;
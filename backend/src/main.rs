@@ -1,21 +1,31 @@
 mod codegen;
+mod helpers;
 
 use bbl_frontend::ast::{Expr, IntegerLiteral, PrintExpr, Program};
-use bbl_frontend::parser::parse_program;
+use bbl_frontend::resolve::load_program;
+use bbl_frontend::typeck::TypeChecker;
 use codegen::generate;
-use std::{env, fs};
+use std::env;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    // Create a simple test program that prints a number
     let file = env::args().nth(1).expect("No file provided");
-    let src = fs::read_to_string(file).expect("Failed to read file");
-
-    let src = if src.ends_with('\n') { src } else { src + "\n" };
-
-    let prog = parse_program(&src)
-        .expect("Failed to parse program")
-        .Program()
-        .unwrap();
+    // parse the file together with everything it imports
+    let prog = match load_program(Path::new(&file)) {
+        Ok(prog) => prog,
+        Err((errors, sources)) => {
+            for error in &errors {
+                eprintln!("{}", error.render(&sources));
+            }
+            process::exit(1);
+        }
+    };
+    // codegen relies on the program being well typed
+    if let Err(e) = TypeChecker::new().check_program(&prog) {
+        eprintln!("{}", e.render(&prog.sources));
+        process::exit(1);
+    }
 
     // Create and run the processor
     let program = generate(&prog);
@@ -6,16 +6,30 @@ pub enum Line {
 }
 
 pub struct Program {
+    // declarations emitted above solve(), e.g. structs
+    pub globals: Vec<Line>,
     pub solve_block: Block,
+    // C++ expression reading the number of test cases; when set, main()
+    // calls solve() once per case
+    pub test_count: Option<String>,
+    // keeps cin tied to cout, so output is flushed before every read
+    pub interactive: bool,
 }
 
 impl Program {
     pub fn new() -> Self {
         Self {
+            globals: Vec::new(),
             solve_block: Block::new_with_pre_block("void solve() ".to_string(), 0),
+            test_count: None,
+            interactive: false,
         }
     }
 
+    pub fn add_global(&mut self, line: Line) {
+        self.globals.push(line);
+    }
+
     pub fn to_string(&self) -> String {
         let mut header = r#"
 #include <bits/stdc++.h>
@@ -23,21 +37,43 @@ using namespace std;
 using ll = long long;
 "#;
 
+        let globals = self
+            .globals
+            .iter()
+            .map(|g| match g {
+                Line::Block(b) => b.to_string() + "\n",
+                Line::Statement(s) => s.to_string() + "\n",
+            })
+            .collect::<String>();
+
         let solve_fn = self.solve_block.to_string();
 
-        let main_fn = r#"
-int main() {
-    cin.tie(0)->sync_with_stdio(false);
-    solve();
+        let run_solve = match &self.test_count {
+            Some(count) => format!("ll t = {};\n    while (t--) solve();", count),
+            None => "solve();".to_string(),
+        };
+        let stream = if self.interactive {
+            "ios::"
+        } else {
+            "cin.tie(0)->"
+        };
+        let main_fn = format!(
+            r#"
+int main() {{
+    {}sync_with_stdio(false);
+    {}
     return 0;
-}"#;
+}}"#,
+            stream, run_solve
+        );
 
-        header.to_string() + &solve_fn + &main_fn
+        header.to_string() + &globals + &solve_fn + &main_fn
     }
 }
 
 pub struct Block {
     pre_block: Option<String>,
+    post_block: Option<String>,
     statements: Vec<Line>,
     pub indent_level: usize,
 }
@@ -47,6 +83,7 @@ impl Block {
         Self {
             statements: Vec::new(),
             pre_block: None,
+            post_block: None,
             indent_level: 0,
         }
     }
@@ -54,10 +91,16 @@ impl Block {
         Self {
             statements: Vec::new(),
             pre_block: Some(pre_block),
+            post_block: None,
             indent_level: indent_level,
         }
     }
 
+    // text emitted right after the closing brace, e.g. the `;` of a struct
+    pub fn set_post_block(&mut self, post_block: String) {
+        self.post_block = Some(post_block);
+    }
+
     pub fn add_statement(&mut self, stmt: Line) {
         self.statements.push(stmt);
     }
@@ -73,6 +116,10 @@ impl Block {
         self.statements.push(Line::Block(block));
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
     pub fn to_string(&self) -> String {
         let mut res = String::new();
         if let Some(ref pre_block) = self.pre_block {
@@ -97,6 +144,9 @@ impl Block {
             .collect::<Vec<_>>()
             .join("\n");
         res += &format!("\n{}}}", " ".repeat(4 * self.indent_level));
+        if let Some(ref post_block) = self.post_block {
+            res += post_block;
+        }
         res
     }
 }
@@ -117,4 +167,41 @@ mod tests {
 }"#;
         assert_eq!(block.to_string(), expected);
     }
+
+    #[test]
+    fn test_globals() {
+        let mut program = Program::new();
+        let mut point = Block::new_with_pre_block("struct Point ".to_string(), 0);
+        point.add_line_s("ll x;");
+        point.set_post_block(";".to_string());
+        program.add_global(Line::Block(point));
+
+        let output = program.to_string();
+        let struct_pos = output.find("struct Point {\n    ll x;\n};").unwrap();
+        assert!(struct_pos < output.find("void solve()").unwrap());
+    }
+
+    #[test]
+    fn test_multi_test() {
+        let mut program = Program::new();
+        assert!(program.to_string().contains("\n    solve();\n"));
+
+        program.test_count = Some("read_count()".to_string());
+        assert!(program
+            .to_string()
+            .contains("ll t = read_count();\n    while (t--) solve();\n"));
+    }
+
+    #[test]
+    fn test_interactive() {
+        let mut program = Program::new();
+        assert!(program
+            .to_string()
+            .contains("cin.tie(0)->sync_with_stdio(false);"));
+
+        program.interactive = true;
+        let output = program.to_string();
+        assert!(output.contains("ios::sync_with_stdio(false);"));
+        assert!(!output.contains("cin.tie(0)"));
+    }
 }
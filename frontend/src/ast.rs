@@ -1,6 +1,18 @@
+use crate::diagnostic::{SourceFile, Span};
+use std::cell::OnceCell;
+use std::fmt;
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub expressions: Vec<Expr>,
+    // set by a `tests` line: the input starts with a test count and the
+    // program runs once per test case
+    pub multi_test: bool,
+    // set by an `interactive` line: every print is flushed right away
+    pub interactive: bool,
+    // the text of every file the program was loaded from, for pointing
+    // diagnostics at their spans
+    pub sources: Vec<SourceFile>,
 }
 
 // ast next!
@@ -9,22 +21,147 @@ pub struct Program {
 pub struct FunctionDef {
     pub name: Identifier,
     pub args: Vec<TypedIdentifier>,
+    // None for functions that don't return a value
+    pub return_type: Option<Type>,
     pub body: Vec<Expr>,
+    pub span: Option<Span>,
+}
+
+impl FunctionDef {
+    pub fn get_type(&self) -> Type {
+        Type::FunctionType(
+            self.args
+                .iter()
+                .map(|a| a.associated_type.clone())
+                .collect(),
+            Box::new(self.return_type.clone()),
+        )
+    }
+}
+
+// `extern fn name(args) -> R`: a C++ function BBL code may call
+#[derive(Debug, Clone)]
+pub struct ExternDef {
+    pub name: Identifier,
+    pub args: Vec<TypedIdentifier>,
+    pub return_type: Option<Type>,
+    pub span: Option<Span>,
+}
+
+impl ExternDef {
+    pub fn get_type(&self) -> Type {
+        Type::FunctionType(
+            self.args
+                .iter()
+                .map(|a| a.associated_type.clone())
+                .collect(),
+            Box::new(self.return_type.clone()),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDef {
+    pub name: Identifier,
+    pub fields: Vec<TypedIdentifier>,
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Int,
+    // 128-bit integer, for values past 64 bits
+    I128,
     Float,
     String,
     Bool,
     List(Box<Type>),
-    Tuple(Box<Type>),
+    Tuple(Vec<Type>),
+    // two values, read as `.first` and `.second`
+    Pair(Box<Type>, Box<Type>),
+    // user-defined struct, by name
+    Struct(String),
+    Option(Box<Type>),
+    // ordered map from keys to values
+    Map(Box<Type>, Box<Type>),
+    // ordered set of distinct values
+    Set(Box<Type>),
+    // priority queue whose top is the largest element, or the smallest when
+    // the flag is set (`min_heap<T>`)
+    Heap(Box<Type>, bool),
+    // last in, first out
+    Stack(Box<Type>),
+    // first in, first out
+    Queue(Box<Type>),
+    // double-ended queue, also indexable
+    Deque(Box<Type>),
+    // rows of cells, read as `g[r][c]`
+    Grid(Box<Type>),
+    // integer modulo the given modulus, `mint<998244353>`
+    ModInt(i64),
+    // fixed number of bits, sized when created
+    Bitset,
     // type of parameters, return type
     FunctionType(Vec<Type>, Box<Option<Type>>),
+    // integer range `a..b`
+    Range,
+    // a `ref` parameter; only appears in function signatures
+    Ref(Box<Type>),
     None,
 }
 
+impl Type {
+    // The type a parameter has inside the function body
+    pub fn without_ref(&self) -> Type {
+        match self {
+            Type::Ref(inner) => *inner.clone(),
+            other => other.clone(),
+        }
+    }
+}
+
+// Types as they are written in bbl, e.g. `map<string, list<int>>`
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |types: &[Type]| {
+            types
+                .iter()
+                .map(Type::to_string)
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::I128 => write!(f, "i128"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::List(elem) => write!(f, "list<{}>", elem),
+            Type::Tuple(elems) => write!(f, "tuple<{}>", list(elems)),
+            Type::Pair(first, second) => write!(f, "pair<{}, {}>", first, second),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Option(inner) => write!(f, "option<{}>", inner),
+            Type::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+            Type::Set(elem) => write!(f, "set<{}>", elem),
+            Type::Heap(elem, false) => write!(f, "heap<{}>", elem),
+            Type::Heap(elem, true) => write!(f, "min_heap<{}>", elem),
+            Type::Stack(elem) => write!(f, "stack<{}>", elem),
+            Type::Queue(elem) => write!(f, "queue<{}>", elem),
+            Type::Deque(elem) => write!(f, "deque<{}>", elem),
+            Type::Grid(elem) => write!(f, "grid<{}>", elem),
+            Type::ModInt(modulus) => write!(f, "mint<{}>", modulus),
+            Type::Bitset => write!(f, "bitset"),
+            Type::FunctionType(params, return_type) => match return_type.as_ref() {
+                Some(t) => write!(f, "fn({}) -> {}", list(params), t),
+                None => write!(f, "fn({})", list(params)),
+            },
+            Type::Range => write!(f, "range"),
+            Type::Ref(inner) => write!(f, "ref {}", inner),
+            Type::None => write!(f, "none"),
+        }
+    }
+}
+
 trait ExprNode {
     fn get_type(&self) -> Type;
 }
@@ -47,86 +184,190 @@ impl TypedIdentifier {
 #[derive(Debug, Clone)]
 pub struct IntegerLiteral {
     pub value: i128,
+    // the source text of a literal too big even for an i128, whose value
+    // is then meaningless
+    pub too_large: Option<String>,
+    pub span: Option<Span>,
 }
 
 impl IntegerLiteral {
     pub fn new(value: i128) -> IntegerLiteral {
-        IntegerLiteral { value }
+        IntegerLiteral {
+            span: None,
+            value,
+            too_large: None,
+        }
+    }
+
+    // a literal too big for 64 bits is an i128
+    pub fn get_type(&self) -> Type {
+        if i64::try_from(self.value).is_ok() {
+            Type::Int
+        } else {
+            Type::I128
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct StringLiteral {
     pub value: String,
+    pub span: Option<Span>,
 }
 
 impl StringLiteral {
     pub fn new(value: String) -> StringLiteral {
-        StringLiteral { value }
+        StringLiteral { span: None, value }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FStringPart {
+    Text(String),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub struct FStringExpr {
+    pub parts: Vec<FStringPart>,
+    pub span: Option<Span>,
+}
+
+impl FStringExpr {
+    pub fn new(parts: Vec<FStringPart>) -> FStringExpr {
+        FStringExpr { span: None, parts }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct FloatLiteral {
     pub value: f64,
+    pub span: Option<Span>,
 }
 
 impl FloatLiteral {
     pub fn new(value: f64) -> FloatLiteral {
-        FloatLiteral { value }
+        FloatLiteral { span: None, value }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct BooleanLiteral {
     pub value: bool,
+    pub span: Option<Span>,
 }
 
 impl BooleanLiteral {
     pub fn new(value: bool) -> BooleanLiteral {
-        BooleanLiteral { value }
+        BooleanLiteral { span: None, value }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Identifier {
     pub value: String,
+    pub span: Option<Span>,
 }
 
 impl Identifier {
     pub fn new(value: String) -> Identifier {
-        Identifier { value }
+        Identifier { value, span: None }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct AssignmentExpr {
-    pub target: TypedIdentifier,
+    pub target: Identifier,
+    // None when the type is left to be inferred from the value
+    pub annotation: Option<Type>,
+    // the type the checker infers when there is no annotation, recorded
+    // while checking so codegen emits the same one
+    pub inferred: OnceCell<Type>,
     pub value: Box<Expr>,
     pub const_var: bool,
+    pub span: Option<Span>,
 }
 
 impl AssignmentExpr {
-    pub fn new(target: TypedIdentifier, value: Expr, const_var: bool) -> AssignmentExpr {
+    pub fn new(
+        target: Identifier,
+        annotation: Option<Type>,
+        value: Expr,
+        const_var: bool,
+    ) -> AssignmentExpr {
         AssignmentExpr {
+            span: None,
             target,
+            annotation,
+            inferred: OnceCell::new(),
+            value: Box::new(value),
+            const_var,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConstDef {
+    pub target: TypedIdentifier,
+    pub value: Box<Expr>,
+    pub span: Option<Span>,
+}
+
+impl ConstDef {
+    pub fn new(target: TypedIdentifier, value: Expr) -> ConstDef {
+        ConstDef {
+            span: None,
+            target,
+            value: Box::new(value),
+        }
+    }
+}
+
+// `val (a, b) = t` declares one variable per element of the tuple `t`;
+// `val [x, y] = xs` takes the first elements of the list `xs`
+#[derive(Debug, Clone)]
+pub struct DestructuringAssignmentExpr {
+    pub targets: Vec<Identifier>,
+    pub value: Box<Expr>,
+    pub const_var: bool,
+    pub list_pattern: bool,
+    pub span: Option<Span>,
+}
+
+impl DestructuringAssignmentExpr {
+    pub fn new(
+        targets: Vec<Identifier>,
+        value: Expr,
+        const_var: bool,
+        list_pattern: bool,
+    ) -> DestructuringAssignmentExpr {
+        DestructuringAssignmentExpr {
+            span: None,
+            targets,
             value: Box::new(value),
             const_var,
+            list_pattern,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ReassignmentExpr {
-    pub target: Identifier,
+    // a variable, or an element / field of one (`xs[i]`, `p.x`)
+    pub target: Box<Expr>,
     pub value: Box<Expr>,
+    // binary operator of a compound assignment (`+=` is `Some(BinOp::Add)`)
+    pub op: Option<BinOp>,
+    pub span: Option<Span>,
 }
 
 impl ReassignmentExpr {
-    pub fn new(target: Identifier, value: Expr) -> ReassignmentExpr {
+    pub fn new(target: Expr, value: Expr, op: Option<BinOp>) -> ReassignmentExpr {
         ReassignmentExpr {
-            target,
+            span: None,
+            target: Box::new(target),
             value: Box::new(value),
+            op,
         }
     }
 }
@@ -135,44 +376,107 @@ impl ReassignmentExpr {
 pub struct MethodCallExpr {
     pub method_name: Identifier,
     pub args: Vec<Expr>,
+    // `xs` in `xs.push(v)`; None for a plain call
+    pub receiver: Option<Box<Expr>>,
+    pub span: Option<Span>,
 }
 
 impl MethodCallExpr {
     pub fn new(method_name: Identifier, args: Vec<Expr>) -> MethodCallExpr {
-        MethodCallExpr { method_name, args }
+        MethodCallExpr {
+            span: None,
+            method_name,
+            args,
+            receiver: None,
+        }
+    }
+
+    pub fn with_receiver(
+        receiver: Expr,
+        method_name: Identifier,
+        args: Vec<Expr>,
+    ) -> MethodCallExpr {
+        MethodCallExpr {
+            span: None,
+            method_name,
+            args,
+            receiver: Some(Box::new(receiver)),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct PrintExpr {
     pub arg: Box<Expr>,
+    pub span: Option<Span>,
 }
 
 impl PrintExpr {
     pub fn new(arg: Expr) -> PrintExpr {
-        PrintExpr { arg: Box::new(arg) }
+        PrintExpr {
+            span: None,
+            arg: Box::new(arg),
+        }
     }
 }
 
+// `debug(x, y + 1)`; each value is kept with its source text, e.g. "y + 1"
 #[derive(Debug, Clone)]
-pub struct NoneExpr;
+pub struct DebugExpr {
+    pub args: Vec<(String, Expr)>,
+    pub span: Option<Span>,
+}
+
+impl DebugExpr {
+    pub fn new(args: Vec<(String, Expr)>) -> DebugExpr {
+        DebugExpr { span: None, args }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NoneExpr {
+    pub span: Option<Span>,
+}
 
 impl NoneExpr {
     pub fn new() -> NoneExpr {
-        NoneExpr
+        NoneExpr { span: None }
     }
 }
 
+// The `none` value of an option type
+#[derive(Debug, Clone)]
+pub struct NoneLiteral {
+    pub span: Option<Span>,
+}
+
+// Raw C++ emitted verbatim, inside solve() or above it when `global`
+#[derive(Debug, Clone)]
+pub struct CppBlock {
+    pub code: String,
+    pub global: bool,
+    pub span: Option<Span>,
+}
+
+// `import utils` pulls in the file utils.bbl next to the importing file
+#[derive(Debug, Clone)]
+pub struct ImportExpr {
+    pub module: Identifier,
+    pub span: Option<Span>,
+}
+
 #[derive(Debug, Clone)]
 pub struct IfExpr {
     pub condition: Box<Expr>,
     pub then_block: Vec<Expr>,
     pub else_block: Option<Vec<Expr>>,
+    pub span: Option<Span>,
 }
 
 impl IfExpr {
     pub fn new(condition: Expr, then_block: Vec<Expr>, else_block: Option<Vec<Expr>>) -> IfExpr {
         IfExpr {
+            span: None,
             condition: Box::new(condition),
             then_block,
             else_block,
@@ -180,29 +484,117 @@ impl IfExpr {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct TernaryExpr {
+    pub condition: Box<Expr>,
+    pub then_value: Box<Expr>,
+    pub else_value: Box<Expr>,
+    pub span: Option<Span>,
+}
+
+impl TernaryExpr {
+    pub fn new(condition: Expr, then_value: Expr, else_value: Expr) -> TernaryExpr {
+        TernaryExpr {
+            span: None,
+            condition: Box::new(condition),
+            then_value: Box::new(then_value),
+            else_value: Box::new(else_value),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RepExpr {
     pub num_iterations: Box<Expr>,
     pub body: Vec<Expr>,
+    pub span: Option<Span>,
 }
 
 impl RepExpr {
     pub fn new(num_iterations: Expr) -> RepExpr {
         RepExpr {
+            span: None,
             num_iterations: Box::new(num_iterations),
             body: vec![],
         }
     }
 }
 
+// `try value else fallback`
+#[derive(Debug, Clone)]
+pub struct TryExpr {
+    pub value: Box<Expr>,
+    pub fallback: Box<Expr>,
+    pub span: Option<Span>,
+}
+
+impl TryExpr {
+    pub fn new(value: Expr, fallback: Expr) -> TryExpr {
+        TryExpr {
+            span: None,
+            value: Box::new(value),
+            fallback: Box::new(fallback),
+        }
+    }
+
+    // `try x else panic(..)` aborts instead of producing a fallback value
+    pub fn fallback_panics(&self) -> bool {
+        matches!(self.fallback.as_ref(), Expr::MethodCallExpr(call) if call.method_name.value == "panic")
+    }
+}
+
+// `start..end`, or `start..=end` when `inclusive`
+#[derive(Debug, Clone)]
+pub struct RangeExpr {
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub inclusive: bool,
+    pub span: Option<Span>,
+}
+
+impl RangeExpr {
+    pub fn new(start: Expr, end: Expr, inclusive: bool) -> RangeExpr {
+        RangeExpr {
+            span: None,
+            start: Box::new(start),
+            end: Box::new(end),
+            inclusive,
+        }
+    }
+}
+
+// `for x in iterable { body }` over a range or a list's elements, or
+// `for (a, b) in iterable` destructuring tuple elements, e.g. from
+// `enumerate(xs)` or `zip(xs, ys)`
+#[derive(Debug, Clone)]
+pub struct ForExpr {
+    pub targets: Vec<Identifier>,
+    pub iterable: Box<Expr>,
+    pub body: Vec<Expr>,
+    pub span: Option<Span>,
+}
+
+impl ForExpr {
+    pub fn new(targets: Vec<Identifier>, iterable: Expr, body: Vec<Expr>) -> ForExpr {
+        ForExpr {
+            span: None,
+            targets,
+            iterable: Box::new(iterable),
+            body,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ReturnExpr {
     pub value: Box<Expr>,
+    pub span: Option<Span>,
 }
 
 impl ReturnExpr {
     pub fn new(value: Expr) -> ReturnExpr {
         ReturnExpr {
+            span: None,
             value: Box::new(value),
         }
     }
@@ -211,40 +603,326 @@ impl ReturnExpr {
 #[derive(Debug, Clone)]
 pub struct ListExpr {
     pub elems: Vec<Expr>,
+    pub span: Option<Span>,
 }
 
 impl ListExpr {
     pub fn new(elems: Vec<Expr>) -> ListExpr {
-        ListExpr { elems }
+        ListExpr { span: None, elems }
+    }
+}
+
+// `list<T>(d1, .., dk, fill)`: nested lists of the given sizes, each
+// innermost element set to `fill`
+#[derive(Debug, Clone)]
+pub struct ListInitExpr {
+    pub list_type: Type,
+    pub dims: Vec<Expr>,
+    pub fill: Box<Expr>,
+    pub span: Option<Span>,
+}
+
+impl ListInitExpr {
+    pub fn new(list_type: Type, dims: Vec<Expr>, fill: Expr) -> ListInitExpr {
+        ListInitExpr {
+            span: None,
+            list_type,
+            dims,
+            fill: Box::new(fill),
+        }
+    }
+}
+
+// `input { n: int, a: list<int>(n) }`: variables read from stdin at the
+// start of the program, in order
+#[derive(Debug, Clone)]
+pub struct InputHeader {
+    pub fields: Vec<InputField>,
+    pub span: Option<Span>,
+}
+
+// One input variable; a list gives a size per nesting level
+#[derive(Debug, Clone)]
+pub struct InputField {
+    pub target: TypedIdentifier,
+    pub dims: Vec<Expr>,
+}
+
+// `grid<T>(rows, cols)`, with every cell set to `fill` if given, else to
+// T's default value
+#[derive(Debug, Clone)]
+pub struct GridInitExpr {
+    pub elem_type: Type,
+    pub rows: Box<Expr>,
+    pub cols: Box<Expr>,
+    pub fill: Option<Box<Expr>>,
+    pub span: Option<Span>,
+}
+
+// `mint<P>(x)` or `i128(x)`: `x` converted to `target_type`
+#[derive(Debug, Clone)]
+pub struct ConversionExpr {
+    pub target_type: Type,
+    pub value: Box<Expr>,
+    pub span: Option<Span>,
+}
+
+// `map<K, V>()`, `set<T>()`, `stack<T>()`, ...: a new, empty collection
+#[derive(Debug, Clone)]
+pub struct EmptyCollectionExpr {
+    pub collection_type: Type,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleExpr {
+    pub elems: Vec<Expr>,
+    pub span: Option<Span>,
+}
+
+impl TupleExpr {
+    pub fn new(elems: Vec<Expr>) -> TupleExpr {
+        TupleExpr { span: None, elems }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleAccessExpr {
+    pub tuple: Box<Expr>,
+    pub index: usize,
+    pub span: Option<Span>,
+}
+
+impl TupleAccessExpr {
+    pub fn new(tuple: Expr, index: usize) -> TupleAccessExpr {
+        TupleAccessExpr {
+            span: None,
+            tuple: Box::new(tuple),
+            index,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexExpr {
+    pub list: Box<Expr>,
+    pub index: Box<Expr>,
+    pub span: Option<Span>,
+}
+
+impl IndexExpr {
+    pub fn new(list: Expr, index: Expr) -> IndexExpr {
+        IndexExpr {
+            span: None,
+            list: Box::new(list),
+            index: Box::new(index),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldAccessExpr {
+    pub object: Box<Expr>,
+    pub field: Identifier,
+    pub span: Option<Span>,
+}
+
+impl FieldAccessExpr {
+    pub fn new(object: Expr, field: Identifier) -> FieldAccessExpr {
+        FieldAccessExpr {
+            span: None,
+            object: Box::new(object),
+            field,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Coalesce,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IntDiv,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+impl BinOp {
+    pub const ALL: [BinOp; 20] = [
+        BinOp::Or,
+        BinOp::And,
+        BinOp::Eq,
+        BinOp::Ne,
+        BinOp::Lt,
+        BinOp::Gt,
+        BinOp::Le,
+        BinOp::Ge,
+        BinOp::Coalesce,
+        BinOp::BitOr,
+        BinOp::BitXor,
+        BinOp::BitAnd,
+        BinOp::Shl,
+        BinOp::Shr,
+        BinOp::Add,
+        BinOp::Sub,
+        BinOp::Mul,
+        BinOp::Div,
+        BinOp::IntDiv,
+        BinOp::Rem,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Or => "||",
+            BinOp::And => "&&",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Coalesce => "??",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::BitAnd => "&",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::IntDiv => "//",
+            BinOp::Rem => "%",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<BinOp> {
+        BinOp::ALL.into_iter().find(|op| op.symbol() == symbol)
+    }
+
+    // Binding power, higher binding tighter. Comparisons sit below the
+    // bitwise operators as in Python, so `x & 1 == 0` reads `(x & 1) == 0`.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => 3,
+            BinOp::Coalesce => 4,
+            BinOp::BitOr => 5,
+            BinOp::BitXor => 6,
+            BinOp::BitAnd => 7,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Mul | BinOp::Div | BinOp::IntDiv | BinOp::Rem => 10,
+        }
+    }
+
+    // `a ?? b ?? c` tries each option in turn; the rest group to the left
+    pub fn associativity(self) -> Associativity {
+        match self {
+            BinOp::Coalesce => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        self.precedence() == BinOp::Eq.precedence()
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+impl UnOp {
+    pub const ALL: [UnOp; 3] = [UnOp::Not, UnOp::Neg, UnOp::BitNot];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnOp::Not => "!",
+            UnOp::Neg => "-",
+            UnOp::BitNot => "~",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<UnOp> {
+        UnOp::ALL.into_iter().find(|op| op.symbol() == symbol)
+    }
+
+    // prefix operators bind tighter than any binary one: `-a * b` is `(-a) * b`
+    pub fn precedence(self) -> u8 {
+        11
+    }
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct BinOpExpr {
     pub left: Box<Expr>,
-    pub op: String,
+    pub op: BinOp,
     pub right: Box<Expr>,
+    // the operator's position
+    pub op_span: Option<Span>,
+    pub span: Option<Span>,
 }
 
 impl BinOpExpr {
-    pub fn new(left: Expr, op: String, right: Expr) -> BinOpExpr {
+    pub fn new(left: Expr, op: BinOp, right: Expr) -> BinOpExpr {
         BinOpExpr {
             left: Box::new(left),
             op,
             right: Box::new(right),
+            op_span: None,
+            span: None,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct UnOpExpr {
-    pub op: String,
+    pub op: UnOp,
     pub arg: Box<Expr>,
+    pub span: Option<Span>,
 }
 
 impl UnOpExpr {
-    pub fn new(op: String, arg: Expr) -> UnOpExpr {
+    pub fn new(op: UnOp, arg: Expr) -> UnOpExpr {
         UnOpExpr {
+            span: None,
             op,
             arg: Box::new(arg),
         }
@@ -257,18 +935,40 @@ pub enum Expr {
     Float(FloatLiteral),
     Boolean(BooleanLiteral),
     String(StringLiteral),
+    FString(FStringExpr),
     Identifier(Identifier),
     AssignmentExpr(AssignmentExpr),
+    ConstDef(ConstDef),
+    DestructuringAssignmentExpr(DestructuringAssignmentExpr),
     ReassignmentExpr(ReassignmentExpr),
     MethodCallExpr(MethodCallExpr),
     PrintExpr(PrintExpr),
+    DebugExpr(DebugExpr),
     IfExpr(IfExpr),
+    TernaryExpr(TernaryExpr),
     RepExpr(RepExpr),
     ListExpr(ListExpr),
+    TupleExpr(TupleExpr),
+    TupleAccessExpr(TupleAccessExpr),
+    FieldAccessExpr(FieldAccessExpr),
+    IndexExpr(IndexExpr),
     BinOp(BinOpExpr),
     UnOp(UnOpExpr),
     FunctionDef(FunctionDef),
+    StructDef(StructDef),
     NoneExpr(NoneExpr),
+    NoneLiteral(NoneLiteral),
+    ImportExpr(ImportExpr),
+    CppBlock(CppBlock),
+    ExternDef(ExternDef),
+    RangeExpr(RangeExpr),
+    TryExpr(TryExpr),
+    ListInitExpr(ListInitExpr),
+    InputHeader(InputHeader),
+    EmptyCollectionExpr(EmptyCollectionExpr),
+    GridInitExpr(GridInitExpr),
+    ConversionExpr(ConversionExpr),
+    ForExpr(ForExpr),
     ReturnExpr(ReturnExpr),
 }
 
@@ -395,25 +1095,131 @@ impl Expr {
 }
 
 impl Expr {
-    pub fn get_type(&self) -> Type {
+    // Where in the source the expression is, as far as it is recorded: the
+    // name a statement declares or assigns, a call's name, or an operator
+    pub fn span(&self) -> Option<Span> {
         match self {
-            Expr::Integer(_) => Type::Int,
-            Expr::Float(_) => Type::Float,
-            Expr::String(_) => Type::String,
-            Expr::Boolean(_) => Type::Bool,
-            Expr::Identifier(_) => todo!(),
-            Expr::ReturnExpr(_) => todo!(),
-            Expr::AssignmentExpr(_) => todo!(),
-            Expr::ReassignmentExpr(_) => todo!(),
-            Expr::MethodCallExpr(_) => todo!(),
-            Expr::PrintExpr(_) => todo!(),
-            Expr::IfExpr(_) => todo!(),
-            Expr::RepExpr(_) => todo!(),
-            Expr::ListExpr(_) => todo!(),
-            Expr::BinOp(_) => todo!(),
-            Expr::UnOp(expr) => expr.arg.get_type(),
-            Expr::FunctionDef(fd) => todo!(),
-            Expr::NoneExpr(_) => Type::None,
+            Expr::Identifier(id) => id.span,
+            Expr::AssignmentExpr(assign) => assign.target.span,
+            Expr::ConstDef(def) => def.target.value.span,
+            Expr::DestructuringAssignmentExpr(assign) => assign.targets.first()?.span,
+            Expr::ReassignmentExpr(assign) => assign.target.span(),
+            Expr::MethodCallExpr(call) => call.method_name.span,
+            Expr::FunctionDef(def) => def.name.span,
+            Expr::StructDef(def) => def.name.span,
+            Expr::ExternDef(def) => def.name.span,
+            Expr::FieldAccessExpr(access) => access.field.span,
+            Expr::TupleAccessExpr(access) => access.tuple.span(),
+            Expr::IndexExpr(index) => index.list.span(),
+            Expr::BinOp(binop) => binop.op_span,
+            Expr::UnOp(unop) => unop.arg.span(),
+            Expr::ForExpr(for_expr) => for_expr.targets.first()?.span,
+            Expr::PrintExpr(print) => print.arg.span(),
+            Expr::ReturnExpr(ret) => ret.value.span(),
+            Expr::IfExpr(if_expr) => if_expr.condition.span(),
+            Expr::RepExpr(rep) => rep.num_iterations.span(),
+            _ => self.extent(),
+        }
+    }
+
+    // All of the expression's source text, as recorded by the parser
+    pub fn extent(&self) -> Option<Span> {
+        match self {
+            Expr::Integer(node) => node.span,
+            Expr::Float(node) => node.span,
+            Expr::Boolean(node) => node.span,
+            Expr::String(node) => node.span,
+            Expr::FString(node) => node.span,
+            Expr::Identifier(node) => node.span,
+            Expr::AssignmentExpr(node) => node.span,
+            Expr::ConstDef(node) => node.span,
+            Expr::DestructuringAssignmentExpr(node) => node.span,
+            Expr::ReassignmentExpr(node) => node.span,
+            Expr::MethodCallExpr(node) => node.span,
+            Expr::PrintExpr(node) => node.span,
+            Expr::DebugExpr(node) => node.span,
+            Expr::IfExpr(node) => node.span,
+            Expr::TernaryExpr(node) => node.span,
+            Expr::RepExpr(node) => node.span,
+            Expr::ListExpr(node) => node.span,
+            Expr::TupleExpr(node) => node.span,
+            Expr::TupleAccessExpr(node) => node.span,
+            Expr::FieldAccessExpr(node) => node.span,
+            Expr::IndexExpr(node) => node.span,
+            Expr::BinOp(node) => node.span,
+            Expr::UnOp(node) => node.span,
+            Expr::FunctionDef(node) => node.span,
+            Expr::StructDef(node) => node.span,
+            Expr::NoneExpr(node) => node.span,
+            Expr::NoneLiteral(node) => node.span,
+            Expr::ImportExpr(node) => node.span,
+            Expr::CppBlock(node) => node.span,
+            Expr::ExternDef(node) => node.span,
+            Expr::RangeExpr(node) => node.span,
+            Expr::TryExpr(node) => node.span,
+            Expr::ListInitExpr(node) => node.span,
+            Expr::InputHeader(node) => node.span,
+            Expr::EmptyCollectionExpr(node) => node.span,
+            Expr::GridInitExpr(node) => node.span,
+            Expr::ConversionExpr(node) => node.span,
+            Expr::ForExpr(node) => node.span,
+            Expr::ReturnExpr(node) => node.span,
+        }
+    }
+
+    pub fn extent_mut(&mut self) -> &mut Option<Span> {
+        match self {
+            Expr::Integer(node) => &mut node.span,
+            Expr::Float(node) => &mut node.span,
+            Expr::Boolean(node) => &mut node.span,
+            Expr::String(node) => &mut node.span,
+            Expr::FString(node) => &mut node.span,
+            Expr::Identifier(node) => &mut node.span,
+            Expr::AssignmentExpr(node) => &mut node.span,
+            Expr::ConstDef(node) => &mut node.span,
+            Expr::DestructuringAssignmentExpr(node) => &mut node.span,
+            Expr::ReassignmentExpr(node) => &mut node.span,
+            Expr::MethodCallExpr(node) => &mut node.span,
+            Expr::PrintExpr(node) => &mut node.span,
+            Expr::DebugExpr(node) => &mut node.span,
+            Expr::IfExpr(node) => &mut node.span,
+            Expr::TernaryExpr(node) => &mut node.span,
+            Expr::RepExpr(node) => &mut node.span,
+            Expr::ListExpr(node) => &mut node.span,
+            Expr::TupleExpr(node) => &mut node.span,
+            Expr::TupleAccessExpr(node) => &mut node.span,
+            Expr::FieldAccessExpr(node) => &mut node.span,
+            Expr::IndexExpr(node) => &mut node.span,
+            Expr::BinOp(node) => &mut node.span,
+            Expr::UnOp(node) => &mut node.span,
+            Expr::FunctionDef(node) => &mut node.span,
+            Expr::StructDef(node) => &mut node.span,
+            Expr::NoneExpr(node) => &mut node.span,
+            Expr::NoneLiteral(node) => &mut node.span,
+            Expr::ImportExpr(node) => &mut node.span,
+            Expr::CppBlock(node) => &mut node.span,
+            Expr::ExternDef(node) => &mut node.span,
+            Expr::RangeExpr(node) => &mut node.span,
+            Expr::TryExpr(node) => &mut node.span,
+            Expr::ListInitExpr(node) => &mut node.span,
+            Expr::InputHeader(node) => &mut node.span,
+            Expr::EmptyCollectionExpr(node) => &mut node.span,
+            Expr::GridInitExpr(node) => &mut node.span,
+            Expr::ConversionExpr(node) => &mut node.span,
+            Expr::ForExpr(node) => &mut node.span,
+            Expr::ReturnExpr(node) => &mut node.span,
+        }
+    }
+
+    // The variable an assignment target ultimately writes to: `xs` for
+    // `xs[i].y = v`. None if the expression is not assignable.
+    pub fn root_identifier(&self) -> Option<&Identifier> {
+        match self {
+            Expr::Identifier(id) => Some(id),
+            Expr::IndexExpr(index) => index.list.root_identifier(),
+            Expr::FieldAccessExpr(access) => access.object.root_identifier(),
+            Expr::TupleAccessExpr(access) => access.tuple.root_identifier(),
+            _ => None,
         }
     }
 }
@@ -428,20 +1234,6 @@ pub enum AstNode {
 }
 
 impl AstNode {
-    pub fn get_type(&self) -> Type {
-        match self {
-            AstNode::Program(program) => program
-                .expressions
-                .last()
-                .unwrap_or(&Expr::NoneExpr(NoneExpr))
-                .get_type(),
-            AstNode::Expr(expr) => expr.get_type(),
-            AstNode::TypedIdentifier(identifier) => identifier.associated_type.clone(),
-            AstNode::Type(t) => t.clone(),
-            AstNode::VecExpr(exprs) => todo!(),
-        }
-    }
-
     pub fn Expr(self) -> Option<Expr> {
         if let AstNode::Expr(e) = self {
             Some(e)
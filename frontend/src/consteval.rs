@@ -0,0 +1,127 @@
+use crate::ast::{BinOp, Expr, UnOp};
+use std::collections::HashMap;
+
+// Value of a `const` initializer, folded at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+// Evaluates a constant expression (see TypeChecker::is_constant_expr) that has
+// already been typechecked. `constants` holds the folded earlier constants.
+pub fn eval(expr: &Expr, constants: &HashMap<String, ConstValue>) -> Result<ConstValue, String> {
+    match expr {
+        Expr::Integer(i) => i64::try_from(i.value)
+            .map(ConstValue::Int)
+            .map_err(|_| format!("Integer literal {} does not fit in 64 bits", i.value)),
+        Expr::Float(f) => Ok(ConstValue::Float(f.value)),
+        Expr::Boolean(b) => Ok(ConstValue::Bool(b.value)),
+        Expr::String(s) => Ok(ConstValue::String(s.value.clone())),
+        Expr::Identifier(id) => constants
+            .get(&id.value)
+            .cloned()
+            .ok_or(format!("'{}' is not a constant", id.value)),
+        Expr::UnOp(unop) => match (unop.op, eval(&unop.arg, constants)?) {
+            (UnOp::Neg, ConstValue::Int(x)) => x
+                .checked_neg()
+                .map(ConstValue::Int)
+                .ok_or(format!("Integer overflow in -({})", x)),
+            (UnOp::Neg, ConstValue::Float(x)) => Ok(ConstValue::Float(-x)),
+            (UnOp::BitNot, ConstValue::Int(x)) => Ok(ConstValue::Int(!x)),
+            (UnOp::Not, ConstValue::Bool(x)) => Ok(ConstValue::Bool(!x)),
+            (op, value) => Err(format!("Cannot apply '{}' to {:?}", op, value)),
+        },
+        Expr::BinOp(binop) => {
+            let left = eval(&binop.left, constants)?;
+            let right = eval(&binop.right, constants)?;
+            eval_binop(binop.op, left, right)
+        }
+        Expr::TernaryExpr(ternary) => match eval(&ternary.condition, constants)? {
+            ConstValue::Bool(true) => eval(&ternary.then_value, constants),
+            ConstValue::Bool(false) => eval(&ternary.else_value, constants),
+            other => Err(format!("Condition must be boolean, got {:?}", other)),
+        },
+        _ => Err("Expression cannot be evaluated at compile time".to_string()),
+    }
+}
+
+fn eval_binop(op: BinOp, left: ConstValue, right: ConstValue) -> Result<ConstValue, String> {
+    use ConstValue::*;
+    let overflow = |l: &dyn std::fmt::Display, r: &dyn std::fmt::Display| {
+        format!("Integer overflow in {} {} {}", l, op, r)
+    };
+    match (left, right) {
+        (Int(l), Int(r)) => {
+            let result = match op {
+                BinOp::Add => l.checked_add(r),
+                BinOp::Sub => l.checked_sub(r),
+                BinOp::Mul => l.checked_mul(r),
+                BinOp::IntDiv | BinOp::Rem | BinOp::Div if r == 0 => {
+                    return Err("Division by zero".to_string())
+                }
+                BinOp::IntDiv => l.checked_div(r),
+                BinOp::Rem => l.checked_rem(r),
+                BinOp::Div => return Ok(Float(l as f64 / r as f64)),
+                BinOp::BitAnd => Some(l & r),
+                BinOp::BitOr => Some(l | r),
+                BinOp::BitXor => Some(l ^ r),
+                // shifting bits out (or into the sign) is overflow too
+                BinOp::Shl => u32::try_from(r)
+                    .ok()
+                    .and_then(|r| l.checked_shl(r))
+                    .filter(|shifted| shifted >> r == l),
+                BinOp::Shr => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                _ => return compare(op, l.cmp(&r)),
+            };
+            result.map(Int).ok_or(overflow(&l, &r))
+        }
+        (Float(l), Float(r)) => {
+            let result = match op {
+                BinOp::Add => l + r,
+                BinOp::Sub => l - r,
+                BinOp::Mul => l * r,
+                BinOp::Div if r == 0.0 => return Err("Division by zero".to_string()),
+                BinOp::Div => l / r,
+                _ => {
+                    return compare(
+                        op,
+                        l.partial_cmp(&r).ok_or("Comparison with NaN".to_string())?,
+                    )
+                }
+            };
+            if result.is_finite() {
+                Ok(Float(result))
+            } else {
+                Err(format!("Float overflow in {:?} {} {:?}", l, op, r))
+            }
+        }
+        // an int with a float is promoted, as in the typechecker
+        (Int(l), Float(r)) => eval_binop(op, Float(l as f64), Float(r)),
+        (Float(l), Int(r)) => eval_binop(op, Float(l), Float(r as f64)),
+        (Bool(l), Bool(r)) => compare(op, l.cmp(&r)),
+        (String(l), String(r)) => compare(op, l.cmp(&r)),
+        (l, r) => Err(format!("Cannot apply '{}' to {:?} and {:?}", op, l, r)),
+    }
+}
+
+fn compare(op: BinOp, ordering: std::cmp::Ordering) -> Result<ConstValue, String> {
+    use std::cmp::Ordering::*;
+    let result = match op {
+        BinOp::Eq => ordering == Equal,
+        BinOp::Ne => ordering != Equal,
+        BinOp::Lt => ordering == Less,
+        BinOp::Gt => ordering == Greater,
+        BinOp::Le => ordering != Greater,
+        BinOp::Ge => ordering != Less,
+        _ => {
+            return Err(format!(
+                "Operator '{}' cannot be evaluated at compile time",
+                op
+            ))
+        }
+    };
+    Ok(ConstValue::Bool(result))
+}
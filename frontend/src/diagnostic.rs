@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// Byte offsets into the source, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // From the start of this span to the end of `last`
+    pub fn to(self, last: Span) -> Span {
+        Span {
+            start: self.start,
+            end: last.end,
+        }
+    }
+}
+
+// A loaded file. Spans index into all loaded files laid end to end, with
+// this one starting at `start`.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: String,
+    pub text: String,
+    pub start: usize,
+}
+
+// Something the parser, typechecker or a lint has to say about a program.
+// `code` names the kind of problem, e.g. "unused-variable", so it can be
+// allowed or denied from the command line.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            span: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            code,
+            span: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Diagnostic {
+        Diagnostic {
+            span: Some(span),
+            ..self
+        }
+    }
+}
+
+impl Diagnostic {
+    // The diagnostic followed by the line its span is on and a caret under
+    // the span, e.g.
+    //   error[type-error]: Undefined variable 'y'
+    //     --> main.bbl:2:9
+    //      |
+    //    2 | val x = y + 1
+    //      |         ^
+    pub fn render(&self, sources: &[SourceFile]) -> String {
+        let Some(span) = self.span else {
+            return self.to_string();
+        };
+        let Some(file) = sources.iter().rev().find(|file| file.start <= span.start) else {
+            return self.to_string();
+        };
+        let text = &file.text;
+        let offset = (span.start - file.start).min(text.len());
+        let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+        let end = (span.end.max(span.start) - file.start).clamp(offset, line_end);
+        let line_number = text[..offset].matches('\n').count() + 1;
+        let column = text[line_start..offset].chars().count();
+        let width = text[offset..end].chars().count().max(1);
+        let gutter = " ".repeat(line_number.to_string().len());
+        format!(
+            "{}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self,
+            gutter,
+            file.path,
+            line_number,
+            column + 1,
+            gutter,
+            line_number,
+            &text[line_start..line_end],
+            gutter,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}[{}]: {}", severity, self.code, self.message)
+    }
+}
+
+// What to do with warnings: `--allow <code>` drops those with that code and
+// `--deny-warnings` turns the rest into errors
+#[derive(Debug, Default)]
+pub struct WarningPolicy {
+    pub deny_warnings: bool,
+    pub allowed: HashSet<String>,
+}
+
+impl WarningPolicy {
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error || !self.allowed.contains(d.code))
+            .map(|d| match d.severity {
+                Severity::Warning if self.deny_warnings => Diagnostic {
+                    severity: Severity::Error,
+                    ..d
+                },
+                _ => d,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,65 @@
+// A piece of a `format` template such as "{} = {:.6}"
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatPart {
+    Text(String),
+    // `{}`, or `{:.N}` for a float with N digits after the point
+    Arg { precision: Option<usize> },
+}
+
+// Splits a `format` template into text and placeholders; `{{` and `}}` are
+// literal braces
+pub fn parse_format(template: &str) -> Result<Vec<FormatPart>, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => {
+                            return Err(format!("Unclosed '{{' in format string {:?}", template))
+                        }
+                    }
+                }
+                if !text.is_empty() {
+                    parts.push(FormatPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(FormatPart::Arg {
+                    precision: parse_spec(&spec)?,
+                });
+            }
+            '}' => return Err(format!("Unmatched '}}' in format string {:?}", template)),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(FormatPart::Text(text));
+    }
+    Ok(parts)
+}
+
+fn parse_spec(spec: &str) -> Result<Option<usize>, String> {
+    if spec.is_empty() {
+        return Ok(None);
+    }
+    spec.strip_prefix(":.")
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&precision| precision <= 50)
+        .map(Some)
+        .ok_or(format!(
+            "Unsupported format specifier '{{{}}}', expected '{{}}' or '{{:.N}}'",
+            spec
+        ))
+}
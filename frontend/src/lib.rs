@@ -1,2 +1,8 @@
 pub mod ast;
-pub mod parser;
\ No newline at end of file
+pub mod consteval;
+pub mod diagnostic;
+pub mod format;
+pub mod lint;
+pub mod parser;
+pub mod resolve;
+pub mod typeck;
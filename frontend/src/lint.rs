@@ -0,0 +1,374 @@
+use crate::ast::{Expr, FStringPart, FunctionDef, Identifier, Program};
+use crate::diagnostic::{Diagnostic, Span};
+use std::collections::HashMap;
+
+// Warnings for variables that are declared but never read and functions that
+// are never called. Run it on a single file's program, not a loaded one: a
+// module's helpers are meant to go partly unused. Names starting with `_`,
+// parameters, loop variables and input fields are never reported.
+pub fn unused(program: &Program) -> Vec<Diagnostic> {
+    let mut usage = Usage {
+        scopes: vec![HashMap::new()],
+        ..Default::default()
+    };
+    usage.statements(&program.expressions);
+    usage
+        .decls
+        .into_iter()
+        .filter(|decl| !decl.read && decl.kind != Kind::Silent && !decl.name.starts_with('_'))
+        .map(|decl| {
+            let warning = match decl.kind {
+                Kind::Function => Diagnostic::warning(
+                    "unused-function",
+                    format!("function '{}' is never called", decl.name),
+                ),
+                _ => Diagnostic::warning(
+                    "unused-variable",
+                    format!("'{}' is assigned but never read", decl.name),
+                ),
+            };
+            match decl.span {
+                Some(span) => warning.with_span(span),
+                None => warning,
+            }
+        })
+        .collect()
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Variable,
+    Function,
+    // declared but not worth a warning: parameters, loop variables, input
+    Silent,
+}
+
+struct Decl {
+    name: String,
+    span: Option<Span>,
+    kind: Kind,
+    read: bool,
+    // the function this one overloads; which overload a call picks isn't
+    // known here, so a call counts for all of them
+    overloads: Option<usize>,
+}
+
+#[derive(Default)]
+struct Usage {
+    decls: Vec<Decl>,
+    // name -> index into `decls`, innermost scope last
+    scopes: Vec<HashMap<String, usize>>,
+    // functions whose bodies are being walked; recursive calls don't count
+    enclosing: Vec<usize>,
+}
+
+impl Usage {
+    fn declare(&mut self, name: &Identifier, kind: Kind) -> usize {
+        let overloads = match self.lookup(&name.value) {
+            Some(index) if kind == Kind::Function && self.decls[index].kind == Kind::Function => {
+                Some(index)
+            }
+            _ => None,
+        };
+        self.decls.push(Decl {
+            name: name.value.clone(),
+            span: name.span,
+            kind,
+            read: false,
+            overloads,
+        });
+        let index = self.decls.len() - 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.value.clone(), index);
+        index
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+    }
+
+    fn read(&mut self, name: &str) {
+        let mut found = self.lookup(name);
+        while let Some(index) = found {
+            if !self.enclosing.contains(&index) {
+                self.decls[index].read = true;
+            }
+            found = self.decls[index].overloads;
+        }
+    }
+
+    fn block(&mut self, body: &[Expr]) {
+        self.scopes.push(HashMap::new());
+        self.statements(body);
+        self.scopes.pop();
+    }
+
+    // Like the type checker, declares the later functions of a run of
+    // definitions at its start, so calls between them count
+    fn statements(&mut self, body: &[Expr]) {
+        let mut ahead = HashMap::new();
+        for (index, expr) in body.iter().enumerate() {
+            if index == 0 || !matches!(body[index - 1], Expr::FunctionDef(_)) {
+                let run: Vec<&FunctionDef> = body[index..]
+                    .iter()
+                    .map_while(|expr| match expr {
+                        Expr::FunctionDef(def) => Some(def),
+                        _ => None,
+                    })
+                    .collect();
+                for (offset, def) in run.iter().enumerate().skip(1) {
+                    let name = &def.name.value;
+                    let repeated = run.iter().filter(|other| &other.name.value == name).count() > 1;
+                    if !repeated && self.lookup(name).is_none() {
+                        ahead.insert(index + offset, self.declare(&def.name, Kind::Function));
+                    }
+                }
+            }
+            match (expr, ahead.get(&index)) {
+                (Expr::FunctionDef(def), Some(&decl)) => self.function(def, decl),
+                _ => self.walk(expr),
+            }
+        }
+    }
+
+    fn function(&mut self, def: &FunctionDef, index: usize) {
+        self.enclosing.push(index);
+        self.scopes.push(HashMap::new());
+        for arg in &def.args {
+            self.declare(&arg.value, Kind::Silent);
+        }
+        self.statements(&def.body);
+        self.scopes.pop();
+        self.enclosing.pop();
+    }
+
+    // The place a reassignment writes to: indices are read, the variable
+    // itself is not
+    fn target(&mut self, target: &Expr) {
+        match target {
+            Expr::Identifier(_) => {}
+            Expr::IndexExpr(index) => {
+                self.target(&index.list);
+                self.walk(&index.index);
+            }
+            Expr::FieldAccessExpr(access) => self.target(&access.object),
+            Expr::TupleAccessExpr(access) => self.target(&access.tuple),
+            other => self.walk(other),
+        }
+    }
+
+    fn walk(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(id) => self.read(&id.value),
+            Expr::AssignmentExpr(assign) => {
+                self.walk(&assign.value);
+                self.declare(&assign.target, Kind::Variable);
+            }
+            Expr::ConstDef(def) => {
+                self.walk(&def.value);
+                self.declare(&def.target.value, Kind::Variable);
+            }
+            Expr::DestructuringAssignmentExpr(assign) => {
+                self.walk(&assign.value);
+                for target in &assign.targets {
+                    self.declare(target, Kind::Variable);
+                }
+            }
+            Expr::ReassignmentExpr(assign) => {
+                self.target(&assign.target);
+                self.walk(&assign.value);
+            }
+            Expr::MethodCallExpr(call) => {
+                match &call.receiver {
+                    Some(receiver) => self.walk(receiver),
+                    None => self.read(&call.method_name.value),
+                }
+                for arg in &call.args {
+                    self.walk(arg);
+                }
+            }
+            Expr::FunctionDef(def) => {
+                let index = self.declare(&def.name, Kind::Function);
+                self.function(def, index);
+            }
+            Expr::ForExpr(for_expr) => {
+                self.walk(&for_expr.iterable);
+                self.scopes.push(HashMap::new());
+                for target in &for_expr.targets {
+                    self.declare(target, Kind::Silent);
+                }
+                self.statements(&for_expr.body);
+                self.scopes.pop();
+            }
+            Expr::InputHeader(header) => {
+                for field in &header.fields {
+                    for dim in &field.dims {
+                        self.walk(dim);
+                    }
+                    self.declare(&field.target.value, Kind::Silent);
+                }
+            }
+            // C++ is opaque, so any name declared so far that it mentions is read
+            Expr::CppBlock(block) => {
+                for decl in self.decls.iter_mut() {
+                    if block.code.contains(&decl.name) {
+                        decl.read = true;
+                    }
+                }
+            }
+            Expr::IfExpr(if_expr) => {
+                self.walk(&if_expr.condition);
+                self.block(&if_expr.then_block);
+                if let Some(else_block) = &if_expr.else_block {
+                    self.block(else_block);
+                }
+            }
+            Expr::RepExpr(rep) => {
+                self.walk(&rep.num_iterations);
+                self.block(&rep.body);
+            }
+            Expr::FString(fstring) => {
+                for part in &fstring.parts {
+                    if let FStringPart::Expr(expr) = part {
+                        self.walk(expr);
+                    }
+                }
+            }
+            Expr::PrintExpr(print) => self.walk(&print.arg),
+            Expr::DebugExpr(debug) => {
+                for (_, arg) in &debug.args {
+                    self.walk(arg);
+                }
+            }
+            Expr::TernaryExpr(ternary) => {
+                self.walk(&ternary.condition);
+                self.walk(&ternary.then_value);
+                self.walk(&ternary.else_value);
+            }
+            Expr::ListExpr(list) => list.elems.iter().for_each(|e| self.walk(e)),
+            Expr::TupleExpr(tuple) => tuple.elems.iter().for_each(|e| self.walk(e)),
+            Expr::TupleAccessExpr(access) => self.walk(&access.tuple),
+            Expr::FieldAccessExpr(access) => self.walk(&access.object),
+            Expr::IndexExpr(index) => {
+                self.walk(&index.list);
+                self.walk(&index.index);
+            }
+            Expr::BinOp(binop) => {
+                self.walk(&binop.left);
+                self.walk(&binop.right);
+            }
+            Expr::UnOp(unop) => self.walk(&unop.arg),
+            Expr::RangeExpr(range) => {
+                self.walk(&range.start);
+                self.walk(&range.end);
+            }
+            Expr::TryExpr(try_expr) => {
+                self.walk(&try_expr.value);
+                self.walk(&try_expr.fallback);
+            }
+            Expr::ListInitExpr(init) => {
+                init.dims.iter().for_each(|d| self.walk(d));
+                self.walk(&init.fill);
+            }
+            Expr::GridInitExpr(init) => {
+                self.walk(&init.rows);
+                self.walk(&init.cols);
+                if let Some(fill) = &init.fill {
+                    self.walk(fill);
+                }
+            }
+            Expr::ConversionExpr(conversion) => self.walk(&conversion.value),
+            Expr::ReturnExpr(ret) => self.walk(&ret.value),
+            Expr::Integer(_)
+            | Expr::Float(_)
+            | Expr::Boolean(_)
+            | Expr::String(_)
+            | Expr::StructDef(_)
+            | Expr::ExternDef(_)
+            | Expr::NoneExpr(_)
+            | Expr::NoneLiteral(_)
+            | Expr::ImportExpr(_)
+            | Expr::EmptyCollectionExpr(_) => {}
+        }
+    }
+}
+
+// Whether control never continues past `expr`: a `return`, an `exit` or
+// `panic` call, or an if/else whose branches both end that way
+pub fn terminates(expr: &Expr) -> bool {
+    match expr {
+        Expr::ReturnExpr(_) => true,
+        Expr::MethodCallExpr(call) => {
+            call.receiver.is_none() && matches!(call.method_name.value.as_str(), "exit" | "panic")
+        }
+        Expr::IfExpr(if_expr) => match &if_expr.else_block {
+            Some(else_block) => {
+                if_expr.then_block.iter().any(terminates) && else_block.iter().any(terminates)
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+// The statements of `body` that can run: everything up to and including the
+// first one that terminates
+pub fn reachable(body: &[Expr]) -> &[Expr] {
+    match body.iter().position(terminates) {
+        Some(last) => &body[..=last],
+        None => body,
+    }
+}
+
+// Warnings for statements that follow a terminating one in the same block
+pub fn unreachable(program: &Program) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    check_block(&program.expressions, None, &mut warnings);
+    warnings
+}
+
+fn check_block(body: &[Expr], function: Option<&str>, warnings: &mut Vec<Diagnostic>) {
+    let live = reachable(body);
+    if live.len() < body.len() {
+        let after = match live.last() {
+            Some(Expr::ReturnExpr(_)) => "return".to_string(),
+            Some(Expr::MethodCallExpr(call)) => format!("{}()", call.method_name.value),
+            _ => "an if/else where every branch returns or exits".to_string(),
+        };
+        let message = match function {
+            Some(name) => format!("unreachable code after {} in '{}'", after, name),
+            None => format!("unreachable code after {}", after),
+        };
+        let warning = Diagnostic::warning("unreachable-code", message);
+        warnings.push(match body[live.len()].span() {
+            Some(span) => warning.with_span(span),
+            None => warning,
+        });
+    }
+    for expr in body {
+        check_nested(expr, function, warnings);
+    }
+}
+
+fn check_nested(expr: &Expr, function: Option<&str>, warnings: &mut Vec<Diagnostic>) {
+    match expr {
+        Expr::FunctionDef(def) => check_block(&def.body, Some(&def.name.value), warnings),
+        Expr::IfExpr(if_expr) => {
+            check_block(&if_expr.then_block, function, warnings);
+            if let Some(else_block) = &if_expr.else_block {
+                check_block(else_block, function, warnings);
+            }
+        }
+        Expr::RepExpr(rep) => check_block(&rep.body, function, warnings),
+        Expr::ForExpr(for_expr) => check_block(&for_expr.body, function, warnings),
+        Expr::AssignmentExpr(assign) => check_nested(&assign.value, function, warnings),
+        Expr::ReassignmentExpr(assign) => check_nested(&assign.value, function, warnings),
+        _ => {}
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::{Severity, WarningPolicy};
+    use crate::lint::{unreachable, unused};
+    use crate::parser::parse_program;
+
+    fn warnings(input: &str) -> Vec<String> {
+        unused(&parse_program(input).unwrap().Program().unwrap())
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        assert_eq!(
+            warnings("val x: int = 1\nvar y = 2\nprint(y)\n"),
+            vec!["'x' is assigned but never read"]
+        );
+        // writing to a variable is not reading it
+        assert_eq!(
+            warnings("var total = 0\nfor i in 0..3 {\ntotal += i\n}\n"),
+            vec!["'total' is assigned but never read"]
+        );
+        assert_eq!(
+            warnings("var xs = [1, 2]\nxs[0] = 5\n"),
+            vec!["'xs' is assigned but never read"]
+        );
+        // an inner declaration shadowing an outer one is a separate variable
+        assert_eq!(
+            warnings("val a = 1\nif true {\nval a = 2\nprint(a)\n}\n"),
+            vec!["'a' is assigned but never read"]
+        );
+        assert_eq!(
+            warnings("val (p, q) = (1, 2)\nprint(p)\n"),
+            vec!["'q' is assigned but never read"]
+        );
+
+        // parameters, loop variables, `_` names and input are never reported
+        assert!(warnings("def f(a: int) {\n}\nf(1)\n").is_empty());
+        assert!(warnings("for i in 0..3 {\nprint(1)\n}\nval _skip = 4\n").is_empty());
+        assert!(warnings("input {\nn: int\n}\n").is_empty());
+        assert!(warnings("var xs = [1]\nxs[0] = xs[0] + 1\n").is_empty());
+        assert!(warnings("val x = 3\ncpp {\ncout << x;\n}\n").is_empty());
+    }
+
+    #[test]
+    fn test_unused_functions() {
+        assert_eq!(
+            warnings("def f() {\n}\ndef g() {\n}\ng()\n"),
+            vec!["function 'f' is never called"]
+        );
+        // calling itself doesn't make a function used
+        assert_eq!(
+            warnings("def f(n: int) -> int {\nreturn f(n - 1)\n}\n"),
+            vec!["function 'f' is never called"]
+        );
+        assert!(warnings(
+            "def f() -> int {\nreturn 1\n}\ndef g() -> int {\nreturn f()\n}\nprint(g())\n"
+        )
+        .is_empty());
+        // functions defined together may call one another before their definitions
+        assert!(warnings(
+            "def f() -> int {\nreturn g()\n}\ndef g() -> int {\nreturn 1\n}\nprint(f())\n"
+        )
+        .is_empty());
+        // a call may go to any overload, so it counts for all of them
+        assert!(warnings("def f(x: int) {\n}\ndef f(s: string) {\n}\nf(1)\n").is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let check = |input: &str| -> Vec<String> {
+            unreachable(&parse_program(input).unwrap().Program().unwrap())
+                .into_iter()
+                .map(|d| d.message)
+                .collect()
+        };
+        assert_eq!(
+            check("def f() -> int {\nreturn 1\nprint(2)\n}\n"),
+            vec!["unreachable code after return in 'f'"]
+        );
+        assert_eq!(
+            check("if true {\nexit(0)\nprint(1)\n}\n"),
+            vec!["unreachable code after exit()"]
+        );
+        assert_eq!(
+            check("def f(x: int) -> int {\nif x < 0 {\nreturn 0\n} else {\npanic(\"no\")\n}\nreturn x\n}\n"),
+            vec!["unreachable code after an if/else where every branch returns or exits in 'f'"]
+        );
+
+        // one branch returning still lets the code after the if run
+        assert!(check("def f(x: int) -> int {\nif x < 0 {\nreturn 0\n}\nreturn x\n}\n").is_empty());
+        assert!(check("def f() -> int {\nreturn 1\n}\nprint(f())\n").is_empty());
+    }
+
+    #[test]
+    fn test_warning_policy() {
+        let program = parse_program("val x = 1\ndef f() {\n}\n")
+            .unwrap()
+            .Program()
+            .unwrap();
+        let codes = |policy: &WarningPolicy| -> Vec<(&'static str, Severity)> {
+            policy
+                .apply(unused(&program))
+                .into_iter()
+                .map(|d| (d.code, d.severity))
+                .collect()
+        };
+
+        let mut policy = WarningPolicy::default();
+        assert_eq!(
+            codes(&policy),
+            vec![
+                ("unused-variable", Severity::Warning),
+                ("unused-function", Severity::Warning)
+            ]
+        );
+        policy.allowed.insert("unused-function".to_string());
+        assert_eq!(codes(&policy), vec![("unused-variable", Severity::Warning)]);
+        policy.deny_warnings = true;
+        assert_eq!(codes(&policy), vec![("unused-variable", Severity::Error)]);
+    }
+}
@@ -1,25 +1,72 @@
 pub mod ast;
+pub mod consteval;
+pub mod diagnostic;
+pub mod format;
+pub mod lint;
+#[cfg(test)]
+mod lint_test;
 pub mod parser;
 #[cfg(test)]
 mod parser_test;
+pub mod resolve;
+#[cfg(test)]
+mod resolve_test;
 pub mod typeck;
+#[cfg(test)]
+mod typeck_test;
 
+use diagnostic::{Diagnostic, Severity, SourceFile, WarningPolicy};
 use std::env;
-use std::fs;
+use std::path::Path;
+use std::process;
 
+// usage: bbl-frontend [--deny-warnings] [--allow <code>]... <file>
 fn main() {
-    let file = env::args().nth(1).expect("No file provided");
-    let mut src = fs::read_to_string(file).expect("Failed to read file");
-
-    if !src.ends_with('\n') {
-        src.push('\n');
+    let mut file = None;
+    let mut policy = WarningPolicy::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--deny-warnings" => policy.deny_warnings = true,
+            "--allow" => {
+                let code = args.next().expect("--allow needs a warning code");
+                policy.allowed.insert(code);
+            }
+            _ => file = Some(arg),
+        }
     }
+    let file = file.expect("No file provided");
 
-    let prog = parser::parse_program(&src).expect("Failed to parse program");
+    let (diagnostics, sources) = check(&file);
+    let diagnostics = policy.apply(diagnostics);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic.render(&sources));
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        process::exit(1);
+    }
+    println!("Type check passed!");
+}
 
-    let mut checker = typeck::TypeChecker::new();
-    match checker.check_program(&prog.Program().unwrap()) {
-        Ok(_) => println!("Type check passed!"),
-        Err(e) => println!("Type error: {}", e.message),
+// The program's diagnostics, and the files their spans point into
+fn check(file: &str) -> (Vec<Diagnostic>, Vec<SourceFile>) {
+    let prog = match resolve::load_program(Path::new(file)) {
+        Ok(prog) => prog,
+        Err((errors, sources)) => return (errors, sources),
+    };
+    if let Err(e) = typeck::TypeChecker::new().check_program(&prog) {
+        return (vec![e], prog.sources);
     }
+
+    // only the main file is linted; imported modules aren't
+    let src = std::fs::read_to_string(file).expect("Failed to read program");
+    let main_file = parser::parse_program(&src)
+        .ok()
+        .and_then(|node| node.Program());
+    let warnings = main_file
+        .iter()
+        .flat_map(|program| [lint::unused(program), lint::unreachable(program)])
+        .flatten()
+        .collect();
+    (warnings, prog.sources)
 }
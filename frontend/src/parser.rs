@@ -10,23 +10,137 @@ pub struct BblParser;
 
 // The Rule enum is automatically generated by pest_derive
 
-use crate::ast::Expr;
 use crate::ast::IfExpr;
 use crate::ast::IntegerLiteral;
 use crate::ast::ListExpr;
 use crate::ast::PrintExpr;
 use crate::ast::Program;
 use crate::ast::TypedIdentifier;
+use crate::ast::{Associativity, BinOp, Expr, UnOp};
+use crate::diagnostic::{Diagnostic, SourceFile, Span};
+use pest::error::{ErrorVariant, InputLocation};
 use pest::iterators::Pair;
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+use std::cell::Cell;
+use std::sync::OnceLock;
 
-fn build_ast_from_expr(pair: Pair<Rule>) -> Option<AstNode> {
+// Builds the node for `pair`; an expression's span covers the pair's text
+// unless building it already set a narrower one
+fn build_ast_from_expr(mut pair: Pair<Rule>) -> Option<AstNode> {
+    // step into rules wrapping a lone child instead of recursing, or each pair
+    // of parentheses would nest four frames
+    while matches!(
+        pair.as_rule(),
+        Rule::operator_expr | Rule::bin_op | Rule::postfix_expr | Rule::paren_expr
+    ) {
+        let mut inner = pair.clone().into_inner();
+        match (inner.next(), inner.next()) {
+            (Some(only), None) => pair = only,
+            _ => break,
+        }
+    }
+    let span = span_of(&pair);
+    let mut node = match pair.as_rule() {
+        Rule::method_call
+        | Rule::operator_expr
+        | Rule::paren_expr
+        | Rule::postfix_expr
+        | Rule::bin_op => AstNode::Expr(build_operand(pair)?),
+        _ => build_node(pair)?,
+    };
+    if let AstNode::Expr(expr) = &mut node {
+        expr.extent_mut().get_or_insert(span);
+    }
+    Some(node)
+}
+
+// The rules an expression nests through, kept out of build_node: its frame
+// is large, and every level of nesting would take one or more
+fn build_operand(pair: Pair<Rule>) -> Option<Expr> {
     match pair.as_rule() {
-        Rule::program => {
-            let nodes = pair
+        Rule::method_call => {
+            let mut inner = pair.into_inner();
+            let name = build_ast_from_expr(inner.next()?)?.Expr()?.Identifier()?;
+            let args = inner
+                .map(|arg| build_ast_from_expr(arg)?.Expr())
+                .collect::<Option<Vec<Expr>>>()?;
+            Some(Expr::MethodCallExpr(ast::MethodCallExpr::new(name, args)))
+        }
+        Rule::operator_expr => {
+            let mut inner_rules = pair.into_inner();
+            let operand = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            match inner_rules.next() {
+                Some(tail) => build_tail(operand, tail),
+                None => Some(operand),
+            }
+        }
+        Rule::paren_expr => {
+            let mut elements = pair
                 .into_inner()
+                .map(|element| build_ast_from_expr(element)?.Expr())
+                .collect::<Option<Vec<Expr>>>()?;
+            if elements.len() == 1 {
+                return elements.pop();
+            }
+            Some(Expr::TupleExpr(ast::TupleExpr::new(elements)))
+        }
+        Rule::postfix_expr => {
+            let mut inner = pair.into_inner();
+            let mut expr = build_ast_from_expr(inner.next()?)?.Expr()?;
+            for op in inner {
+                expr = match op.as_rule() {
+                    Rule::tuple_index => Expr::TupleAccessExpr(ast::TupleAccessExpr::new(
+                        expr,
+                        op.as_str().parse::<usize>().ok()?,
+                    )),
+                    Rule::index_op => Expr::IndexExpr(ast::IndexExpr::new(
+                        expr,
+                        build_ast_from_expr(op.into_inner().next()?)?.Expr()?,
+                    )),
+                    Rule::method_suffix => {
+                        let mut inner_rules = op.into_inner();
+                        let name = build_ast_from_expr(inner_rules.next()?)?
+                            .Expr()?
+                            .Identifier()?;
+                        let args = inner_rules
+                            .map(|arg| build_ast_from_expr(arg)?.Expr())
+                            .collect::<Option<Vec<Expr>>>()?;
+                        Expr::MethodCallExpr(ast::MethodCallExpr::with_receiver(expr, name, args))
+                    }
+                    Rule::identifier => Expr::FieldAccessExpr(ast::FieldAccessExpr::new(
+                        expr,
+                        ast::Identifier::new(op.as_str().to_string()),
+                    )),
+                    _ => return None,
+                };
+            }
+            Some(expr)
+        }
+        Rule::bin_op => build_operator_expr(pair),
+        _ => None,
+    }
+}
+
+fn build_node(pair: Pair<Rule>) -> Option<AstNode> {
+    match pair.as_rule() {
+        Rule::program => {
+            let (directives, body): (Vec<_>, Vec<_>) = pair.into_inner().partition(|pair| {
+                matches!(
+                    pair.as_rule(),
+                    Rule::tests_directive | Rule::interactive_directive
+                )
+            });
+            let has = |rule| directives.iter().any(|d| d.as_rule() == rule);
+            let nodes = body
+                .into_iter()
                 .filter_map(|pair| build_ast_from_expr(pair)?.Expr())
                 .collect::<Vec<Expr>>();
-            Some(AstNode::Program(Program { expressions: nodes }))
+            Some(AstNode::Program(Program {
+                expressions: nodes,
+                multi_test: has(Rule::tests_directive),
+                interactive: has(Rule::interactive_directive),
+                sources: Vec::new(),
+            }))
         }
         Rule::expression => build_ast_from_expr(pair.into_inner().next()?),
         Rule::typed_identifier => {
@@ -41,72 +155,216 @@ fn build_ast_from_expr(pair: Pair<Rule>) -> Option<AstNode> {
                 associated_type: type_of_id,
             }))
         }
-        Rule::type_annotation => {
-            let p_clone = pair.clone();
-            let mut inner = pair.into_inner();
-            let type_name = p_clone.as_str().to_string();
-            if (inner.len() >= 1) {
-                let inner_type = build_ast_from_expr(inner.next()?)?.Type()?;
-                if type_name.starts_with("list") {
-                    Some(AstNode::Type(ast::Type::List(Box::new(inner_type))))
-                } else if type_name.starts_with("tuple") {
-                    Some(AstNode::Type(ast::Type::Tuple(Box::new(inner_type))))
+        Rule::param | Rule::param_type => {
+            let mut inner_rules = pair.into_inner().collect::<Vec<Pair<Rule>>>();
+            let by_ref = inner_rules.first()?.as_rule() == Rule::ref_keyword;
+            let node = build_ast_from_expr(inner_rules.pop()?)?;
+            let wrap = |t: ast::Type| {
+                if by_ref {
+                    ast::Type::Ref(Box::new(t))
                 } else {
-                    None
+                    t
                 }
-            } else {
-                match type_name.as_str() {
+            };
+            match node {
+                AstNode::TypedIdentifier(arg) => Some(AstNode::TypedIdentifier(TypedIdentifier {
+                    value: arg.value,
+                    associated_type: wrap(arg.associated_type),
+                })),
+                AstNode::Type(t) => Some(AstNode::Type(wrap(t))),
+                _ => None,
+            }
+        }
+        Rule::type_annotation => {
+            let type_name = pair.as_str().to_string();
+            let inner = pair.into_inner().collect::<Vec<Pair<Rule>>>();
+            match inner.first()?.as_rule() {
+                Rule::primitive_type => match inner[0].as_str() {
                     "int" => Some(AstNode::Type(ast::Type::Int)),
+                    "i128" => Some(AstNode::Type(ast::Type::I128)),
                     "float" => Some(AstNode::Type(ast::Type::Float)),
                     "string" => Some(AstNode::Type(ast::Type::String)),
+                    "bool" => Some(AstNode::Type(ast::Type::Bool)),
+                    "range" => Some(AstNode::Type(ast::Type::Range)),
+                    "bitset" => Some(AstNode::Type(ast::Type::Bitset)),
                     _ => None,
+                },
+                Rule::struct_type => Some(AstNode::Type(ast::Type::Struct(
+                    inner[0].as_str().to_string(),
+                ))),
+                // the modulus of `mint<P>`
+                Rule::integer => Some(AstNode::Type(ast::Type::ModInt(
+                    i64::try_from(parse_integer_literal(inner[0].as_str())?).ok()?,
+                ))),
+                Rule::function_type => {
+                    let mut params = Vec::new();
+                    let mut return_type = None;
+                    for part in inner[0].clone().into_inner() {
+                        match part.as_rule() {
+                            Rule::function_return => {
+                                return_type =
+                                    Some(build_ast_from_expr(part.into_inner().next()?)?.Type()?)
+                            }
+                            _ => params.push(build_ast_from_expr(part)?.Type()?),
+                        }
+                    }
+                    Some(AstNode::Type(ast::Type::FunctionType(
+                        params,
+                        Box::new(return_type),
+                    )))
+                }
+                _ => {
+                    let mut inner_types = inner
+                        .into_iter()
+                        .map(|t| build_ast_from_expr(t)?.Type())
+                        .collect::<Option<Vec<ast::Type>>>()?;
+                    if type_name.starts_with("list") {
+                        Some(AstNode::Type(ast::Type::List(Box::new(inner_types.pop()?))))
+                    } else if type_name.starts_with("tuple") {
+                        Some(AstNode::Type(ast::Type::Tuple(inner_types)))
+                    } else if type_name.starts_with("pair") {
+                        let second = inner_types.pop()?;
+                        let first = inner_types.pop()?;
+                        Some(AstNode::Type(ast::Type::Pair(
+                            Box::new(first),
+                            Box::new(second),
+                        )))
+                    } else if type_name.starts_with("map") {
+                        let value = inner_types.pop()?;
+                        let key = inner_types.pop()?;
+                        Some(AstNode::Type(ast::Type::Map(
+                            Box::new(key),
+                            Box::new(value),
+                        )))
+                    } else if type_name.starts_with("heap") || type_name.starts_with("min_heap") {
+                        Some(AstNode::Type(ast::Type::Heap(
+                            Box::new(inner_types.pop()?),
+                            type_name.starts_with("min_heap"),
+                        )))
+                    } else if type_name.starts_with("grid") {
+                        Some(AstNode::Type(ast::Type::Grid(Box::new(inner_types.pop()?))))
+                    } else if type_name.starts_with("stack") {
+                        Some(AstNode::Type(ast::Type::Stack(Box::new(
+                            inner_types.pop()?,
+                        ))))
+                    } else if type_name.starts_with("queue") {
+                        Some(AstNode::Type(ast::Type::Queue(Box::new(
+                            inner_types.pop()?,
+                        ))))
+                    } else if type_name.starts_with("deque") {
+                        Some(AstNode::Type(ast::Type::Deque(Box::new(
+                            inner_types.pop()?,
+                        ))))
+                    } else if type_name.starts_with("set") {
+                        Some(AstNode::Type(ast::Type::Set(Box::new(inner_types.pop()?))))
+                    } else if type_name.starts_with("option") {
+                        Some(AstNode::Type(ast::Type::Option(Box::new(
+                            inner_types.pop()?,
+                        ))))
+                    } else {
+                        None
+                    }
                 }
             }
         }
+        Rule::struct_def => {
+            let mut inner = pair.into_inner();
+            let name = build_ast_from_expr(inner.next()?)?.Expr()?.Identifier()?;
+            let fields = inner
+                .map(|field| build_ast_from_expr(field)?.TypedIdentifier())
+                .collect::<Option<Vec<TypedIdentifier>>>()?;
+            Some(AstNode::Expr(Expr::StructDef(ast::StructDef {
+                span: None,
+                name,
+                fields,
+            })))
+        }
         Rule::identifier => Some(AstNode::Expr(Expr::Identifier(ast::Identifier {
             value: pair.as_str().to_string(),
+            span: Some(span_of(&pair)),
         }))),
         Rule::assignment => {
             let mut inner_rules = pair.into_inner().collect::<Vec<Pair<Rule>>>();
-            // typed identifier and expression
+            // var/val, the (typed) identifier and the expression
             assert!(inner_rules.len() == 3);
 
-            let expr = build_ast_from_expr(inner_rules.pop()?)?.Expr();
-            let identifier = build_ast_from_expr(inner_rules.pop()?)?.TypedIdentifier();
+            let expr = build_ast_from_expr(inner_rules.pop()?)?.Expr()?;
+            let (identifier, annotation) = match build_ast_from_expr(inner_rules.pop()?)? {
+                AstNode::TypedIdentifier(typed) => (typed.value, Some(typed.associated_type)),
+                node => (node.Expr()?.Identifier()?, None),
+            };
             let var_val = inner_rules.pop()?;
 
-            Some(AstNode::Expr(Expr::AssignmentExpr(ast::AssignmentExpr {
-                target: identifier?,
-                value: Box::new(expr?),
-                const_var: (var_val.as_str() == "val"),
+            Some(AstNode::Expr(Expr::AssignmentExpr(
+                ast::AssignmentExpr::new(identifier, annotation, expr, var_val.as_str() == "val"),
+            )))
+        }
+        Rule::destructuring_assignment => {
+            let mut inner_rules = pair.into_inner();
+            let const_var = inner_rules.next()?.as_str() == "val";
+            let pattern = inner_rules.next()?;
+            let list_pattern = pattern.as_rule() == Rule::list_pattern;
+            let targets = pattern
+                .into_inner()
+                .map(|rule| build_ast_from_expr(rule)?.Expr()?.Identifier())
+                .collect::<Option<Vec<ast::Identifier>>>()?;
+            let expr = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            Some(AstNode::Expr(Expr::DestructuringAssignmentExpr(
+                ast::DestructuringAssignmentExpr::new(targets, expr, const_var, list_pattern),
+            )))
+        }
+        Rule::cpp_block | Rule::cpp_global => {
+            let global = pair.as_rule() == Rule::cpp_global;
+            let code = pair.into_inner().next()?.as_str().to_string();
+            Some(AstNode::Expr(Expr::CppBlock(ast::CppBlock {
+                span: None,
+                code,
+                global,
             })))
         }
-        Rule::reassignment => {
-            let mut inner_rules = pair.into_inner().collect::<Vec<Pair<Rule>>>();
-            // typed identifier and expression
-            assert!(inner_rules.len() == 2);
-
-            let expr = build_ast_from_expr(inner_rules.pop()?)?.Expr()?;
-            let identifier = build_ast_from_expr(inner_rules.pop()?)?
+        Rule::import_expr => {
+            let module = build_ast_from_expr(pair.into_inner().next()?)?
                 .Expr()?
                 .Identifier()?;
-
-            Some(AstNode::Expr(Expr::ReassignmentExpr(
-                ast::ReassignmentExpr {
-                    target: identifier,
-                    value: Box::new(expr),
-                },
-            )))
+            Some(AstNode::Expr(Expr::ImportExpr(ast::ImportExpr {
+                span: None,
+                module,
+            })))
+        }
+        Rule::const_def => {
+            let mut inner = pair.into_inner();
+            let target = build_ast_from_expr(inner.next()?)?.TypedIdentifier()?;
+            let value = build_ast_from_expr(inner.next()?)?.Expr()?;
+            Some(AstNode::Expr(Expr::ConstDef(ast::ConstDef::new(
+                target, value,
+            ))))
         }
         Rule::print_expr => {
             let expr = build_ast_from_expr(pair.into_inner().next()?)?.Expr();
             let print_expr = PrintExpr::new(expr?);
             Some(AstNode::Expr(Expr::PrintExpr(print_expr)))
         }
-        Rule::integer => {
-            let int_value = pair.as_str().parse::<i128>().unwrap();
-            Some(AstNode::Expr(Expr::Integer(IntegerLiteral::new(int_value))))
+        Rule::debug_expr => {
+            let args = pair
+                .into_inner()
+                .map(|arg| {
+                    let text = arg.as_str().trim().to_string();
+                    Some((text, build_ast_from_expr(arg)?.Expr()?))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(AstNode::Expr(Expr::DebugExpr(ast::DebugExpr::new(args))))
         }
+        // the typechecker reports literals that don't fit
+        Rule::integer => Some(AstNode::Expr(Expr::Integer(
+            match parse_integer_literal(pair.as_str()) {
+                Some(int_value) => IntegerLiteral::new(int_value),
+                None => IntegerLiteral {
+                    span: None,
+                    value: 0,
+                    too_large: Some(pair.as_str().to_string()),
+                },
+            },
+        ))),
         Rule::list_expr => {
             let mut inner = pair.into_inner();
             let mut elements = Vec::new();
@@ -114,17 +372,42 @@ fn build_ast_from_expr(pair: Pair<Rule>) -> Option<AstNode> {
                 let element = build_ast_from_expr(element)?;
                 elements.push(element.Expr()?);
             }
-            Some(AstNode::Expr(Expr::ListExpr(ListExpr { elems: elements })))
+            Some(AstNode::Expr(Expr::ListExpr(ListExpr {
+                span: None,
+                elems: elements,
+            })))
+        }
+        Rule::none_literal => Some(AstNode::Expr(Expr::NoneLiteral(ast::NoneLiteral {
+            span: None,
+        }))),
+        Rule::fstring => {
+            let parts = pair
+                .into_inner()
+                .map(|part| match part.as_rule() {
+                    Rule::fstring_text => Some(ast::FStringPart::Text(unescape(
+                        &part.as_str().replace("{{", "{").replace("}}", "}"),
+                    ))),
+                    _ => Some(ast::FStringPart::Expr(
+                        build_ast_from_expr(part.into_inner().next()?)?.Expr()?,
+                    )),
+                })
+                .collect::<Option<Vec<ast::FStringPart>>>()?;
+            Some(AstNode::Expr(Expr::FString(ast::FStringExpr::new(parts))))
         }
         Rule::string_literal => {
-            let string_value = pair.as_str().trim_matches('"').to_string();
+            let string_value = unescape(pair.into_inner().next()?.as_str());
             Some(AstNode::Expr(Expr::String(ast::StringLiteral {
+                span: None,
                 value: string_value,
             })))
         }
         Rule::float => {
-            let float_value = pair.as_str().parse::<f64>().unwrap();
+            let float_value = pair.as_str().replace('_', "").parse::<f64>().ok()?;
+            if !float_value.is_finite() {
+                return None;
+            }
             Some(AstNode::Expr(Expr::Float(ast::FloatLiteral {
+                span: None,
                 value: float_value,
             })))
         }
@@ -137,39 +420,158 @@ fn build_ast_from_expr(pair: Pair<Rule>) -> Option<AstNode> {
                 .iter()
                 .filter_map(|rule| build_ast_from_expr(rule.clone())?.TypedIdentifier())
                 .collect::<Vec<TypedIdentifier>>();
+            let return_type = inner_rules
+                .iter()
+                .find(|rule| rule.as_rule() == Rule::type_annotation)
+                .and_then(|rule| build_ast_from_expr(rule.clone())?.Type());
             let body = build_ast_from_expr(inner_rules.last()?.clone())?.VecExpr()?;
             Some(AstNode::Expr(Expr::FunctionDef(ast::FunctionDef {
+                span: None,
                 name,
                 args,
+                return_type,
                 body,
             })))
         }
+        Rule::extern_def => {
+            let mut inner_rules = pair.into_inner();
+            let name = build_ast_from_expr(inner_rules.next()?)?
+                .Expr()?
+                .Identifier()?;
+            let mut args = Vec::new();
+            let mut return_type = None;
+            for rule in inner_rules {
+                match build_ast_from_expr(rule)? {
+                    AstNode::TypedIdentifier(arg) => args.push(arg),
+                    AstNode::Type(t) => return_type = Some(t),
+                    _ => return None,
+                }
+            }
+            Some(AstNode::Expr(Expr::ExternDef(ast::ExternDef {
+                span: None,
+                name,
+                args,
+                return_type,
+            })))
+        }
         Rule::rep_expr => {
             let inner_rules = pair.into_inner().collect::<Vec<Pair<Rule>>>();
             let expr = build_ast_from_expr(inner_rules.get(0)?.clone())?.Expr()?;
             let body = build_ast_from_expr(inner_rules.get(1)?.clone())?.VecExpr()?;
             Some(AstNode::Expr(Expr::RepExpr(ast::RepExpr {
+                span: None,
                 num_iterations: Box::new(expr),
                 body: body,
             })))
         }
+        Rule::empty_collection => {
+            let collection_type = build_ast_from_expr(pair.into_inner().next()?)?.Type()?;
+            Some(AstNode::Expr(Expr::EmptyCollectionExpr(
+                ast::EmptyCollectionExpr {
+                    span: None,
+                    collection_type,
+                },
+            )))
+        }
+        Rule::list_init => {
+            let mut inner_rules = pair.into_inner();
+            let elem_type = build_ast_from_expr(inner_rules.next()?)?.Type()?;
+            let mut args = inner_rules
+                .map(|rule| build_ast_from_expr(rule)?.Expr())
+                .collect::<Option<Vec<Expr>>>()?;
+            let fill = args.pop()?;
+            Some(AstNode::Expr(Expr::ListInitExpr(ast::ListInitExpr::new(
+                ast::Type::List(Box::new(elem_type)),
+                args,
+                fill,
+            ))))
+        }
+        Rule::conversion => {
+            let mut inner_rules = pair.into_inner();
+            let target_type = build_ast_from_expr(inner_rules.next()?)?.Type()?;
+            let value = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            Some(AstNode::Expr(Expr::ConversionExpr(ast::ConversionExpr {
+                span: None,
+                target_type,
+                value: Box::new(value),
+            })))
+        }
+        Rule::grid_init => {
+            let mut inner_rules = pair.into_inner();
+            let elem_type = build_ast_from_expr(inner_rules.next()?)?.Type()?;
+            let rows = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            let cols = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            let fill = match inner_rules.next() {
+                Some(fill) => Some(Box::new(build_ast_from_expr(fill)?.Expr()?)),
+                None => None,
+            };
+            Some(AstNode::Expr(Expr::GridInitExpr(ast::GridInitExpr {
+                span: None,
+                elem_type,
+                rows: Box::new(rows),
+                cols: Box::new(cols),
+                fill,
+            })))
+        }
+        Rule::input_header => {
+            let fields = pair
+                .into_inner()
+                .map(|field| {
+                    let mut inner_rules = field.into_inner();
+                    let target = build_ast_from_expr(inner_rules.next()?)?.TypedIdentifier()?;
+                    let dims = inner_rules
+                        .map(|rule| build_ast_from_expr(rule)?.Expr())
+                        .collect::<Option<Vec<Expr>>>()?;
+                    Some(ast::InputField { target, dims })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(AstNode::Expr(Expr::InputHeader(ast::InputHeader {
+                span: None,
+                fields,
+            })))
+        }
+        Rule::try_expr => {
+            let mut inner_rules = pair.into_inner();
+            let value = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            let fallback = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            Some(AstNode::Expr(Expr::TryExpr(ast::TryExpr::new(
+                value, fallback,
+            ))))
+        }
+        Rule::for_expr => {
+            let mut inner_rules = pair.into_inner();
+            let target = inner_rules.next()?;
+            let targets = match target.as_rule() {
+                Rule::tuple_pattern => target
+                    .into_inner()
+                    .map(|rule| build_ast_from_expr(rule)?.Expr()?.Identifier())
+                    .collect::<Option<Vec<ast::Identifier>>>()?,
+                _ => vec![build_ast_from_expr(target)?.Expr()?.Identifier()?],
+            };
+            let iterable = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            let body = build_ast_from_expr(inner_rules.next()?)?.VecExpr()?;
+            Some(AstNode::Expr(Expr::ForExpr(ast::ForExpr::new(
+                targets, iterable, body,
+            ))))
+        }
         Rule::return_expr => {
-            let expr = build_ast_from_expr(pair.into_inner().next()?)?.Expr()?;
+            let mut values = pair
+                .into_inner()
+                .skip(1)
+                .map(|value| build_ast_from_expr(value)?.Expr())
+                .collect::<Option<Vec<Expr>>>()?;
+            // `return a, b` returns the tuple `(a, b)`, and a bare `return`
+            // returns no value
+            let expr = match values.len() {
+                0 => Expr::NoneExpr(ast::NoneExpr { span: None }),
+                1 => values.pop()?,
+                _ => Expr::TupleExpr(ast::TupleExpr::new(values)),
+            };
             Some(AstNode::Expr(Expr::ReturnExpr(ast::ReturnExpr {
+                span: None,
                 value: Box::new(expr),
             })))
         }
-        Rule::bin_op => {
-            let mut inner_rules = pair.into_inner().collect::<Vec<Pair<Rule>>>();
-            let right = build_ast_from_expr(inner_rules.pop()?)?.Expr();
-            let op = inner_rules.pop()?.as_str().to_string();
-            let left = build_ast_from_expr(inner_rules.pop()?)?.Expr();
-            Some(AstNode::Expr(Expr::BinOp(ast::BinOpExpr {
-                left: Box::new(left?),
-                op,
-                right: Box::new(right?),
-            })))
-        }
         Rule::block => {
             let vec_rules = pair
                 .into_inner()
@@ -196,7 +598,215 @@ fn build_ast_from_expr(pair: Pair<Rule>) -> Option<AstNode> {
     }
 }
 
-pub fn parse_program(input: &str) -> Result<Box<AstNode>, String> {
+thread_local! {
+    // where the text being parsed starts among all loaded files
+    static SOURCE_START: Cell<usize> = const { Cell::new(0) };
+}
+
+fn span_of(pair: &Pair<Rule>) -> Span {
+    let start = SOURCE_START.with(Cell::get);
+    Span {
+        start: start + pair.as_span().start(),
+        end: start + pair.as_span().end(),
+    }
+}
+
+// The grammar rule of each operator
+const BINARY_OPERATORS: [(Rule, BinOp); 20] = [
+    (Rule::or, BinOp::Or),
+    (Rule::and, BinOp::And),
+    (Rule::eq, BinOp::Eq),
+    (Rule::ne, BinOp::Ne),
+    (Rule::lt, BinOp::Lt),
+    (Rule::gt, BinOp::Gt),
+    (Rule::le, BinOp::Le),
+    (Rule::ge, BinOp::Ge),
+    (Rule::coalesce, BinOp::Coalesce),
+    (Rule::bit_or, BinOp::BitOr),
+    (Rule::bit_xor, BinOp::BitXor),
+    (Rule::bit_and, BinOp::BitAnd),
+    (Rule::shl, BinOp::Shl),
+    (Rule::shr, BinOp::Shr),
+    (Rule::add, BinOp::Add),
+    (Rule::sub, BinOp::Sub),
+    (Rule::mul, BinOp::Mul),
+    (Rule::div, BinOp::Div),
+    (Rule::int_div, BinOp::IntDiv),
+    (Rule::rem, BinOp::Rem),
+];
+
+const UNARY_OPERATORS: [(Rule, UnOp); 3] = [
+    (Rule::not, UnOp::Not),
+    (Rule::neg, UnOp::Neg),
+    (Rule::bit_not, UnOp::BitNot),
+];
+
+// One level per precedence of BinOp / UnOp, loosest first
+fn pratt_parser() -> &'static PrattParser<Rule> {
+    static PRATT: OnceLock<PrattParser<Rule>> = OnceLock::new();
+    PRATT.get_or_init(|| {
+        let operators = || {
+            let infix = BINARY_OPERATORS.iter().map(|&(rule, op)| {
+                let assoc = match op.associativity() {
+                    Associativity::Left => Assoc::Left,
+                    Associativity::Right => Assoc::Right,
+                };
+                (op.precedence(), Op::infix(rule, assoc))
+            });
+            let prefix = UNARY_OPERATORS
+                .iter()
+                .map(|&(rule, op)| (op.precedence(), Op::prefix(rule)));
+            infix.chain(prefix)
+        };
+        let tightest = operators().map(|(level, _)| level).max().unwrap_or(0);
+        (0..=tightest).fold(PrattParser::new(), |pratt, level| {
+            let ops = operators()
+                .filter(|(precedence, _)| *precedence == level)
+                .map(|(_, op)| op)
+                .reduce(|ops, op| ops | op);
+            match ops {
+                Some(ops) => pratt.op(ops),
+                None => pratt,
+            }
+        })
+    })
+}
+
+// Folds a flat `bin_op` / `un_op` sequence into a BinOp / UnOp tree
+fn build_operator_expr(pair: Pair<Rule>) -> Option<Expr> {
+    pratt_parser()
+        .map_primary(|primary| build_ast_from_expr(primary)?.Expr())
+        // operators have no pair of their own, so they span their operands
+        .map_prefix(|op, arg| {
+            let arg = arg?;
+            let (_, unop) = UNARY_OPERATORS
+                .iter()
+                .find(|(rule, _)| *rule == op.as_rule())?;
+            // a negative literal is one number, so -2^63 still fits an int
+            if let (UnOp::Neg, Expr::Integer(literal)) = (unop, &arg) {
+                if literal.too_large.is_none() {
+                    let mut negated = IntegerLiteral::new(-literal.value);
+                    negated.span = arg.extent().map(|end| span_of(&op).to(end));
+                    return Some(Expr::Integer(negated));
+                }
+            }
+            Some(Expr::UnOp(ast::UnOpExpr {
+                op: *unop,
+                span: arg.extent().map(|end| span_of(&op).to(end)),
+                arg: Box::new(arg),
+            }))
+        })
+        .map_infix(|left, op, right| {
+            let (left, right) = (left?, right?);
+            let (_, binop) = BINARY_OPERATORS
+                .iter()
+                .find(|(rule, _)| *rule == op.as_rule())?;
+            Some(Expr::BinOp(ast::BinOpExpr {
+                span: left.extent().zip(right.extent()).map(|(l, r)| l.to(r)),
+                left: Box::new(left),
+                op: *binop,
+                right: Box::new(right),
+                op_span: Some(span_of(&op)),
+            }))
+        })
+        .parse(pair.into_inner())
+}
+
+// Applies the tail of an `operator_expr` to the operand before it
+fn build_tail(operand: Expr, tail: Pair<Rule>) -> Option<Expr> {
+    let rule = tail.as_rule();
+    let mut inner_rules = tail.into_inner();
+    match rule {
+        // `then if cond else otherwise`, where otherwise may have a tail too
+        Rule::ternary_tail => {
+            let cond = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            let mut else_value = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            if let Some(nested) = inner_rules.next() {
+                let span = else_value.extent().map(|start| start.to(span_of(&nested)));
+                else_value = build_tail(else_value, nested)?;
+                *else_value.extent_mut() = span;
+            }
+            Some(Expr::TernaryExpr(ast::TernaryExpr::new(
+                cond, operand, else_value,
+            )))
+        }
+        Rule::range_tail => {
+            let inclusive = inner_rules.next()?.as_str() == "..=";
+            let end = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            Some(Expr::RangeExpr(ast::RangeExpr::new(
+                operand, end, inclusive,
+            )))
+        }
+        Rule::assign_tail => {
+            // `=` on its own is a plain assignment
+            let assign_op = BinOp::from_symbol(inner_rules.next()?.as_str().trim_end_matches('='));
+            let value = build_ast_from_expr(inner_rules.next()?)?.Expr()?;
+            Some(Expr::ReassignmentExpr(ast::ReassignmentExpr::new(
+                operand, value, assign_op,
+            )))
+        }
+        _ => None,
+    }
+}
+
+// Decodes the escape sequences the grammar accepts in string literals
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+// Normalizes a decimal, hex (0x) or binary (0b) literal with optional `_`
+// digit separators
+fn parse_integer_literal(literal: &str) -> Option<i128> {
+    let digits = literal.replace('_', "");
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest.to_string()),
+        None => (false, digits),
+    };
+    let magnitude = if let Some(hex) = digits.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i128::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse::<i128>().ok()?
+    };
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+pub fn parse_program(input: &str) -> Result<Box<AstNode>, Vec<Diagnostic>> {
+    parse_source(input, "<input>", 0)
+}
+
+// Parses the file `path` holding `input`, numbering its spans from `start`
+// so they don't collide with those of files loaded before it. After a parse
+// error the malformed statement is blanked out and parsing starts over, so
+// every bad statement is reported, not just the first.
+pub fn parse_source(
+    input: &str,
+    path: &str,
+    start: usize,
+) -> Result<Box<AstNode>, Vec<Diagnostic>> {
+    SOURCE_START.with(|source_start| source_start.set(start));
+    let source = SourceFile {
+        path: path.to_string(),
+        text: input.to_string(),
+        start,
+    };
     // Add a newline if the input doesn't end with one
     let input_with_newline = if !input.ends_with('\n') {
         format!("{}\n", input)
@@ -204,19 +814,284 @@ pub fn parse_program(input: &str) -> Result<Box<AstNode>, String> {
         input.to_string()
     };
 
-    match BblParser::parse(Rule::program, &input_with_newline) {
-        Ok(parsed) => {
-            for pair in parsed {
-                let node = match build_ast_from_expr(pair) {
-                    Some(n) => n,
-                    None => return Err("Failed to build AST from expression".to_string()),
-                };
-                return Ok(Box::new(node));
+    let mut text = input_with_newline;
+    let mut errors = Vec::new();
+    let parsed = loop {
+        match BblParser::parse(Rule::program, &text) {
+            Ok(parsed) => break parsed,
+            Err(_) => {
+                let error = parse_error(&text, start);
+                let position = error.span.map_or(text.len(), |span| span.start - start);
+                errors.push(error);
+                match skip_statement(&text, position) {
+                    Some(rest) => text = rest,
+                    None => return Err(errors),
+                }
             }
         }
-        Err(e) => {
-            return Err(e.to_string());
+    };
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let Some(pair) = parsed.into_iter().next() else {
+        return Err(vec![Diagnostic::error(
+            "parse-error",
+            "Failed to parse program",
+        )]);
+    };
+    match build_ast_from_expr(pair) {
+        Some(AstNode::Program(program)) => Ok(Box::new(AstNode::Program(Program {
+            sources: vec![source],
+            ..program
+        }))),
+        Some(node) => Ok(Box::new(node)),
+        None => Err(vec![Diagnostic::error(
+            "parse-error",
+            "Failed to build AST from expression",
+        )]),
+    }
+}
+
+// Blanks out the statement holding `position`: from the start of its line to
+// the next newline outside braces, or up to the `}` closing the enclosing
+// block. Offsets and line numbers stay put, so later errors still point at
+// the right place. None once there is nothing left to skip.
+fn skip_statement(text: &str, position: usize) -> Option<String> {
+    let from = text[..position]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    let mut end = text.len();
+    let mut depth = 0;
+    let mut chars = text[from..].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // braces in strings and comments don't count
+            '"' => {
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c != '\n') {
+                    match c {
+                        '\\' => {
+                            chars.next_if(|&(_, c)| c != '\n');
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            // a line comment leaves its newline to end the statement; `//`
+            // is division
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '/' if chars.next_if(|&(_, c)| c == '*').is_some() => {
+                while let Some((_, c)) = chars.next() {
+                    if c == '*' && chars.next_if(|&(_, c)| c == '/').is_some() {
+                        break;
+                    }
+                }
+            }
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' | '\n' if depth == 0 => {
+                end = from + i;
+                break;
+            }
+            _ => {}
         }
     }
-    return Err("Failed to parse program".to_string());
+    // a stray `}` is a statement of its own
+    let mut range = from..end;
+    if text[range.clone()].trim().is_empty() {
+        let found = text[position..]
+            .chars()
+            .next()
+            .filter(|c| !c.is_whitespace())?;
+        range = position..position + found.len_utf8();
+    }
+    let mut blanked = text[..range.start].to_string();
+    for c in text[range.clone()].chars() {
+        match c {
+            '\n' => blanked.push('\n'),
+            _ => blanked.extend(std::iter::repeat_n(' ', c.len_utf8())),
+        }
+    }
+    blanked.push_str(&text[range.end..]);
+    Some(blanked)
+}
+
+// Points at where parsing stopped, saying what could have come next and what
+// came instead, e.g. "expected ')' or an operator, found end of line". The
+// input is parsed again with pest's token tracking on, which only pays off
+// once the input is known to be wrong.
+fn parse_error(input: &str, start: usize) -> Diagnostic {
+    pest::set_error_detail(true);
+    let result = BblParser::parse(Rule::program, input);
+    pest::set_error_detail(false);
+    let Err(error) = result else {
+        return Diagnostic::error("parse-error", "Failed to parse program");
+    };
+    let mut position = match error.location {
+        InputLocation::Pos(position) => position,
+        InputLocation::Span((position, _)) => position,
+    };
+    let mut rules = match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+        ErrorVariant::CustomError { .. } => Vec::new(),
+    };
+    // punctuation the grammar spells out in place, which pest only reports as
+    // tokens; the newline rule is silent, so it shows up here too. The tokens
+    // may have got further than the rules, which then no longer apply.
+    let mut tokens = Vec::new();
+    if let Some(attempts) = error.parse_attempts() {
+        if attempts.max_position > position {
+            position = attempts.max_position;
+            rules.clear();
+        }
+        tokens = attempts
+            .expected_tokens()
+            .iter()
+            .map(|token| token.to_string())
+            .collect();
+    }
+    let mut expected = Vec::new();
+    // `print` is only ever expected where a statement could start
+    let expression = rules.iter().any(|rule| EXPRESSION_RULES.contains(rule))
+        || tokens.iter().any(|token| token == "print");
+    // an assignment may follow any operand, and is then just an operator
+    let assigning = tokens.iter().any(|token| token == "+=");
+    for token in &tokens {
+        match token.as_str() {
+            "=" if assigning => {}
+            ")" | "]" | "}" | "{" | "," | ":" | "=" | "->" | "in" | "else" => {
+                expected.push(format!("'{}'", token))
+            }
+            // these also start an expression, which says enough then
+            "(" | "\"" if !expression => expected.push(format!("'{}'", token)),
+            _ => {}
+        }
+    }
+    if expression {
+        expected.push("an expression".to_string());
+    }
+    if rules.iter().any(|rule| OPERATOR_RULES.contains(rule)) {
+        expected.push("an operator".to_string());
+    }
+    for rule in rules {
+        let description = describe_rule(rule);
+        if !EXPRESSION_RULES.contains(&rule)
+            && !OPERATOR_RULES.contains(&rule)
+            && !expected.contains(&description)
+        {
+            expected.push(description);
+        }
+    }
+    // a line break may come before a closing bracket, but is never what's
+    // missing there
+    let closing = tokens.iter().any(|token| token == ")" || token == "]");
+    if !closing && tokens.iter().any(|token| token == "\n") {
+        expected.push("end of line".to_string());
+    }
+
+    let rest = &input[position..];
+    let found_len = match rest.chars().next() {
+        None | Some('\n') => 0,
+        Some(c) if c.is_alphanumeric() || c == '_' => rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len()),
+        Some(c) => c.len_utf8(),
+    };
+    let found = match rest.chars().next() {
+        None => "end of file".to_string(),
+        Some('\n') => "end of line".to_string(),
+        Some(_) => format!("'{}'", &rest[..found_len]),
+    };
+    let message = match expected.as_slice() {
+        // no rule takes a reserved name, so none of them is what's missing
+        _ if rest[..found_len].starts_with("bbl_") => format!(
+            "{} is reserved: names starting with `bbl_` belong to the generated C++",
+            found
+        ),
+        [] => format!("unexpected {}", found),
+        [only] => format!("expected {}, found {}", only, found),
+        [init @ .., last] => format!("expected {} or {}, found {}", init.join(", "), last, found),
+    };
+    Diagnostic::error("parse-error", message).with_span(Span {
+        start: start + position,
+        end: start + position + found_len,
+    })
+}
+
+// Rules that begin an expression, reported together as "an expression"
+const EXPRESSION_RULES: [Rule; 31] = [
+    Rule::cpp_block,
+    Rule::function_def,
+    Rule::struct_def,
+    Rule::identifier,
+    Rule::none_literal,
+    Rule::string_literal,
+    Rule::fstring,
+    Rule::integer,
+    Rule::float,
+    Rule::var_val,
+    Rule::print_expr,
+    Rule::debug_expr,
+    Rule::return_expr,
+    Rule::return_keyword,
+    Rule::if_expr,
+    Rule::try_expr,
+    Rule::rep_expr,
+    Rule::for_expr,
+    Rule::list_expr,
+    Rule::list_init,
+    Rule::grid_init,
+    Rule::paren_expr,
+    Rule::empty_collection,
+    Rule::conversion,
+    Rule::postfix_expr,
+    Rule::operator_expr,
+    Rule::bin_op,
+    Rule::method_call,
+    Rule::not,
+    Rule::neg,
+    Rule::bit_not,
+];
+
+// Rules that continue an expression, reported together as "an operator"
+const OPERATOR_RULES: [Rule; 27] = [
+    Rule::ternary_tail,
+    Rule::range_tail,
+    Rule::assign_tail,
+    Rule::range_operator,
+    Rule::index_op,
+    Rule::assign_operator,
+    Rule::coalesce,
+    Rule::shl,
+    Rule::shr,
+    Rule::le,
+    Rule::ge,
+    Rule::eq,
+    Rule::ne,
+    Rule::lt,
+    Rule::gt,
+    Rule::and,
+    Rule::or,
+    Rule::bit_and,
+    Rule::bit_or,
+    Rule::bit_xor,
+    Rule::add,
+    Rule::sub,
+    Rule::mul,
+    Rule::int_div,
+    Rule::div,
+    Rule::rem,
+    Rule::method_suffix,
+];
+
+fn describe_rule(rule: Rule) -> String {
+    match rule {
+        Rule::EOI => "end of file".to_string(),
+        Rule::param | Rule::typed_identifier => "a name and type, like `x: int`".to_string(),
+        Rule::type_annotation | Rule::primitive_type | Rule::struct_type => "a type".to_string(),
+        Rule::block => "'{'".to_string(),
+        Rule::input_field => "an input field, like `n: int`".to_string(),
+        other => format!("{:?}", other).replace('_', " "),
+    }
 }
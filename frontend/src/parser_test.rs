@@ -1,5 +1,6 @@
 #[cfg(test)]
 mod tests {
+    use crate::ast::Expr;
     use crate::parser::{BblParser, Rule};
     use pest::Parser;
 
@@ -7,21 +8,110 @@ mod tests {
         BblParser::parse(rule, input).is_ok()
     }
 
+    // Renders the first parsed expression of `src` fully parenthesized
+    fn parsed(src: &str) -> String {
+        fn shape(expr: &Expr) -> String {
+            match expr {
+                Expr::BinOp(b) => format!("({} {} {})", shape(&b.left), b.op, shape(&b.right)),
+                Expr::UnOp(u) => format!("{}{}", u.op, shape(&u.arg)),
+                Expr::Identifier(id) => id.value.clone(),
+                Expr::Integer(i) => i.value.to_string(),
+                Expr::MethodCallExpr(c) => {
+                    let args = c.args.iter().map(shape).collect::<Vec<_>>().join(", ");
+                    match &c.receiver {
+                        Some(r) => format!("{}.{}({})", shape(r), c.method_name.value, args),
+                        None => format!("{}({})", c.method_name.value, args),
+                    }
+                }
+                other => format!("{:?}", other),
+            }
+        }
+        let program = crate::parser::parse_program(&format!("{}\n", src))
+            .unwrap()
+            .Program()
+            .unwrap();
+        shape(&program.expressions[0])
+    }
+
+    // Names the AST node of the first expression of `src`, or "" if it
+    // doesn't parse
+    fn kind(src: &str) -> String {
+        let Ok(ast) = crate::parser::parse_program(&format!("{}\n", src)) else {
+            return String::new();
+        };
+        let program = ast.Program().unwrap();
+        let debug = format!("{:?}", program.expressions[0]);
+        debug.split('(').next().unwrap().to_string()
+    }
+
     #[test]
     fn test_basic_values() {
         // Integers
         assert!(parse("42", Rule::integer));
         assert!(parse("-42", Rule::integer));
 
+        // Hex, binary and separated integers
+        assert!(parse("0xFF", Rule::integer));
+        assert!(parse("0b1010", Rule::integer));
+        assert!(parse("1_000_000_007", Rule::integer));
+        assert!(parse("0xdead_beef", Rule::integer));
+        assert!(!parse("_1", Rule::integer));
+
         // Floats
         assert!(parse("3.14", Rule::float));
         assert!(parse("-3.14", Rule::float));
+        assert!(parse("1e9", Rule::float));
+        assert!(parse("2.5e-3", Rule::float));
+        assert!(parse("6.02E+23", Rule::float));
+        assert!(!parse("1e", Rule::float));
 
         // Identifiers
         assert!(parse("x", Rule::identifier));
         assert!(parse("variable_name", Rule::identifier));
         assert!(parse("camelCase", Rule::identifier));
         assert!(!parse("1variable", Rule::identifier)); // Should fail
+        assert!(!parse("bbl_pow", Rule::identifier)); // reserved for the C++
+        assert!(parse("bbl", Rule::identifier));
+
+        // Option values
+        assert!(parse("none", Rule::none_literal));
+        assert!(!parse("nonexistent", Rule::none_literal));
+        assert!(parse("some(3)", Rule::method_call));
+    }
+
+    #[test]
+    fn test_integer_literal_values() {
+        let value = |src: &str| match crate::parser::parse_program(src)
+            .unwrap()
+            .Program()
+            .unwrap()
+            .expressions
+            .pop()
+        {
+            Some(crate::ast::Expr::Integer(i)) => i.value,
+            other => panic!("expected an integer literal, got {:?}", other),
+        };
+        assert_eq!(value("0xFF"), 255);
+        assert_eq!(value("0b1010"), 10);
+        assert_eq!(value("1_000_000_007"), 1_000_000_007);
+        assert_eq!(value("0xdead_beef"), 0xdead_beef);
+    }
+
+    #[test]
+    fn test_float_literal_values() {
+        let value = |src: &str| match crate::parser::parse_program(src)
+            .unwrap()
+            .Program()
+            .unwrap()
+            .expressions
+            .pop()
+        {
+            Some(crate::ast::Expr::Float(f)) => f.value,
+            other => panic!("expected a float literal, got {:?}", other),
+        };
+        assert_eq!(value("1e9"), 1e9);
+        assert_eq!(value("2.5e-3"), 2.5e-3);
+        assert_eq!(value("3.14"), 3.14);
     }
 
     #[test]
@@ -29,6 +119,14 @@ mod tests {
         assert!(parse("x: int", Rule::typed_identifier));
         assert!(parse("nums: list<int>", Rule::typed_identifier));
         assert!(parse("point: tuple<float>", Rule::typed_identifier));
+        assert!(parse(
+            "edge: tuple<int, int, float>",
+            Rule::typed_identifier
+        ));
+        assert!(parse("best: option<int>", Rule::typed_identifier));
+        assert!(parse("cmp: fn(int, int) -> bool", Rule::typed_identifier));
+        assert!(parse("callback: fn()", Rule::typed_identifier));
+        assert!(parse("node: fnode", Rule::typed_identifier));
     }
 
     #[test]
@@ -36,7 +134,36 @@ mod tests {
         assert!(parse("x: int = 42", Rule::assignment));
         assert!(parse("y: float = 3.14", Rule::assignment));
         assert!(parse("nums: list<int> = method()", Rule::assignment));
-        assert!(parse("x = 3 + 4", Rule::reassignment));
+        assert_eq!(kind("x = 3 + 4"), "ReassignmentExpr");
+    }
+
+    #[test]
+    fn test_inferred_assignments() {
+        assert!(parse("val x = 42", Rule::assignment));
+        assert!(parse("var xs = [1, 2, 3]", Rule::assignment));
+        assert!(parse("val p = pair(1, \"a\")", Rule::assignment));
+        assert!(!parse("val = 3", Rule::assignment));
+        assert!(!parse("val x: = 3\n", Rule::program));
+    }
+
+    #[test]
+    fn test_compound_assignments() {
+        assert_eq!(kind("x += 1"), "ReassignmentExpr");
+        assert_eq!(kind("x -= y"), "ReassignmentExpr");
+        assert_eq!(kind("x *= 2"), "ReassignmentExpr");
+        assert_eq!(kind("x /= 2"), "ReassignmentExpr");
+        assert_eq!(kind("x //= 2"), "ReassignmentExpr");
+        assert_eq!(kind("x %= m"), "ReassignmentExpr");
+    }
+
+    #[test]
+    fn test_indexing() {
+        assert!(parse("xs[0]", Rule::postfix_expr));
+        assert!(parse("grid[i][j + 1]", Rule::postfix_expr));
+        assert!(parse("xs[i] + xs[i - 1]", Rule::bin_op));
+        assert_eq!(kind("xs[i] = 5"), "ReassignmentExpr");
+        assert_eq!(kind("grid[i][j] += 1"), "ReassignmentExpr");
+        assert_eq!(kind("p.x = 3"), "ReassignmentExpr");
     }
 
     #[test]
@@ -49,6 +176,98 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_return_types() {
+        assert!(parse(
+            "def fact(n: int) -> int {\n    return n\n}",
+            Rule::function_def
+        ));
+        assert!(parse(
+            "def pairs(n: int) -> list<tuple<int, int>> {\n}",
+            Rule::function_def
+        ));
+        assert!(!parse("def f() -> {\n}", Rule::function_def));
+    }
+
+    #[test]
+    fn test_ref_parameters() {
+        assert!(parse(
+            "def fill(ref xs: list<int>, v: int) {\n}",
+            Rule::function_def
+        ));
+        assert!(parse("refs: int", Rule::param));
+        assert!(parse(
+            "cb: fn(ref list<int>) -> int",
+            Rule::typed_identifier
+        ));
+        assert!(!parse("def f(ref) {\n}", Rule::function_def));
+    }
+
+    #[test]
+    fn test_multiple_returns() {
+        assert!(parse("return a, b", Rule::return_expr));
+        assert!(parse("return (a, b)", Rule::return_expr));
+        assert!(parse(
+            "val (q, r) = divmod(7, 2)",
+            Rule::destructuring_assignment
+        ));
+        assert!(parse(
+            "var (lo, mid, hi) = t",
+            Rule::destructuring_assignment
+        ));
+        assert!(!parse("val (q) = t", Rule::destructuring_assignment));
+        assert!(parse("val [x, y, z] = xs", Rule::destructuring_assignment));
+        assert!(parse("var [first] = xs", Rule::destructuring_assignment));
+        assert!(!parse("val [] = xs", Rule::destructuring_assignment));
+    }
+
+    #[test]
+    fn test_constants() {
+        assert!(parse("const MOD: int = 998244353", Rule::const_def));
+        assert!(parse("const N: int = MAXN + 5", Rule::const_def));
+        assert!(parse("const MOD: int = 7\nprint(MOD)\n", Rule::program));
+        // constants only live at file level
+        assert!(!parse("if x {\nconst M: int = 3\n}", Rule::if_expr));
+    }
+
+    #[test]
+    fn test_imports() {
+        assert!(parse("import utils", Rule::import_expr));
+        assert!(parse(
+            "import utils\nimport graph\nprint(1)\n",
+            Rule::program
+        ));
+        assert!(!parse("important", Rule::import_expr));
+        assert!(!parse("import", Rule::import_expr));
+    }
+
+    #[test]
+    fn test_cpp_blocks() {
+        assert!(parse("cpp { x++; }", Rule::cpp_block));
+        assert!(parse(
+            "cpp {\n    if (a) { b(); }\n    puts(\"}\");\n}",
+            Rule::cpp_block
+        ));
+        assert!(parse(
+            "cpp global {\nint f() { return 1; }\n}\n",
+            Rule::program
+        ));
+        assert!(!parse("cpp { unbalanced", Rule::cpp_block));
+        // helpers only live at file level
+        assert!(!parse("if x {\ncpp global { }\n}", Rule::if_expr));
+    }
+
+    #[test]
+    fn test_extern_functions() {
+        assert!(parse(
+            "extern fn __builtin_popcountll(x: int) -> int",
+            Rule::extern_def
+        ));
+        assert!(parse("extern fn srand(seed: int)", Rule::extern_def));
+        assert!(parse("extern fn f() -> int\nprint(f())\n", Rule::program));
+        assert!(!parse("extern fn f() { }", Rule::program));
+    }
+
     #[test]
     fn test_method_calls() {
         assert!(parse("print(42)", Rule::method_call));
@@ -62,6 +281,108 @@ mod tests {
         assert!(parse("if x { print(42) } else { print(0) }", Rule::if_expr));
     }
 
+    #[test]
+    fn test_ternary_expressions() {
+        assert_eq!(kind("a if x > 0 else b"), "TernaryExpr");
+        assert_eq!(kind("1 if c else 2 if d else 3"), "TernaryExpr");
+        assert!(parse("val y: int = a if c else -a", Rule::assignment));
+        assert_ne!(kind("a if c"), "TernaryExpr");
+    }
+
+    #[test]
+    fn test_list_init() {
+        assert!(parse("list<int>(n, 0)", Rule::list_init));
+        assert!(parse("list<list<int>>(n, m, -1)", Rule::list_init));
+        assert!(parse(
+            "var dp: list<list<int>> = list<list<int>>(n + 1, m, 0)",
+            Rule::assignment
+        ));
+        // a fill value is required
+        assert!(!parse("list<int>(n)", Rule::list_init));
+    }
+
+    #[test]
+    fn test_try_expressions() {
+        assert!(parse("try int(s) else 0", Rule::try_expr));
+        assert!(parse("try unwrap(x) + 1 else -1", Rule::try_expr));
+        assert!(parse(
+            "val n: int = try int(s) else panic(\"bad\")",
+            Rule::assignment
+        ));
+        assert!(!parse("try int(s)", Rule::try_expr));
+    }
+
+    #[test]
+    fn test_tuples() {
+        assert_eq!(kind("(1, 2)"), "TupleExpr");
+        assert_eq!(kind("(x, \"a\", 3.5)"), "TupleExpr");
+        assert_eq!(kind("(1)"), "Integer");
+        assert!(parse("t.0", Rule::postfix_expr));
+        assert!(parse("t.1.0", Rule::postfix_expr));
+        assert!(parse("t.0 + t.1", Rule::bin_op));
+    }
+
+    #[test]
+    fn test_structs() {
+        assert!(parse("struct Point { x: int, y: int }", Rule::struct_def));
+        assert!(parse(
+            "struct Edge {\n    to: int\n    weight: float,\n}",
+            Rule::struct_def
+        ));
+        assert!(!parse("struct Empty { }", Rule::struct_def));
+        assert!(parse("p: Point", Rule::typed_identifier));
+        assert!(parse("interval: Interval", Rule::typed_identifier));
+        assert!(parse("p.x", Rule::postfix_expr));
+        assert!(parse("Point(1, 2).y", Rule::postfix_expr));
+        assert!(parse("e.to + 1", Rule::bin_op));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert!(parse(r#""line\n""#, Rule::string_literal));
+        assert!(parse(r#""say \"hi\"""#, Rule::string_literal));
+        assert!(parse(r#""back\\slash""#, Rule::string_literal));
+        assert!(parse(r#"f"{x}\t{y}\n""#, Rule::fstring));
+        assert!(!parse(r#""bad \q escape""#, Rule::string_literal));
+        assert!(!parse(r#""unterminated \""#, Rule::string_literal));
+
+        let value = |src: &str| match crate::parser::parse_program(src)
+            .unwrap()
+            .Program()
+            .unwrap()
+            .expressions
+            .pop()
+        {
+            Some(Expr::String(s)) => s.value,
+            other => panic!("expected a string literal, got {:?}", other),
+        };
+        assert_eq!(value(r#""a\nb""#), "a\nb");
+        assert_eq!(value(r#""\"q\" \\ \t""#), "\"q\" \\ \t");
+        assert_eq!(value(r##""# not a comment""##), "# not a comment");
+    }
+
+    #[test]
+    fn test_fstrings() {
+        assert!(parse("f\"ans = {x}\"", Rule::fstring));
+        assert!(parse("f\"{a} + {b} = {a + b}\"", Rule::fstring));
+        assert!(parse("f\"{{literal}}\"", Rule::fstring));
+        assert!(parse("f\"\"", Rule::fstring));
+        assert!(!parse("f\"{unclosed\"", Rule::fstring));
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(kind("0..n"), "RangeExpr");
+        assert_eq!(kind("1..=n"), "RangeExpr");
+        assert_eq!(kind("a + 1..b * 2"), "RangeExpr");
+        assert!(parse("val r: range = 0..10", Rule::assignment));
+        assert!(parse("for i in 0..n {\n    print(i)\n}", Rule::for_expr));
+        assert!(parse("for i in r {\n}", Rule::for_expr));
+        assert_ne!(kind("0...n"), "RangeExpr");
+        assert!(parse("for (i, x) in enumerate(xs) {\n}", Rule::for_expr));
+        assert!(parse("for (a, b) in zip(xs, ys) {\n}", Rule::for_expr));
+    }
+
     #[test]
     fn test_rep_expressions() {
         assert!(parse("rep 5 { print(42) }", Rule::rep_expr));
@@ -74,6 +395,7 @@ mod tests {
         assert!(parse("x - y", Rule::bin_op));
         assert!(parse("10 * 20", Rule::bin_op));
         assert!(parse("a / b", Rule::bin_op));
+        assert!(parse("a // b", Rule::bin_op));
 
         // Comparisons
         assert!(parse("x == y", Rule::bin_op));
@@ -86,12 +408,52 @@ mod tests {
         // Logical
         assert!(parse("x && y", Rule::bin_op));
         assert!(parse("x || y", Rule::bin_op));
+
+        // Bitwise
+        assert!(parse("x & y", Rule::bin_op));
+        assert!(parse("x | y", Rule::bin_op));
+        assert!(parse("x ^ y", Rule::bin_op));
+        assert!(parse("1 << k", Rule::bin_op));
+        assert!(parse("mask >> 2", Rule::bin_op));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        assert_eq!(parsed("a + b * c < d"), "((a + (b * c)) < d)");
+        assert_eq!(parsed("a - b - c"), "((a - b) - c)");
+        assert_eq!(parsed("a * b + c * d"), "((a * b) + (c * d))");
+        assert_eq!(parsed("x & 1 == 0"), "((x & 1) == 0)");
+        assert_eq!(parsed("1 << k + 1"), "(1 << (k + 1))");
+        assert_eq!(parsed("a < b && c || d"), "(((a < b) && c) || d)");
+        assert_eq!(parsed("-a * b"), "(-a * b)");
+        assert_eq!(parsed("!a && !b"), "(!a && !b)");
+        assert_eq!(parsed("a % b // c"), "((a % b) // c)");
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert!(parse("x ?? 0", Rule::bin_op));
+        assert_eq!(parsed("a ?? b ?? c"), "(a ?? (b ?? c))");
+        assert_eq!(parsed("x ?? 0 + 1"), "(x ?? (0 + 1))");
+        assert_eq!(parsed("x ?? 0 == y"), "((x ?? 0) == y)");
+    }
+
+    #[test]
+    fn test_grouping() {
+        assert!(parse("(a + b) * c", Rule::bin_op));
+        assert!(parse("-(a - b)", Rule::bin_op));
+        assert_eq!(parsed("(a + b) * c"), "((a + b) * c)");
+        assert_eq!(parsed("a - (b - c)"), "(a - (b - c))");
+        assert_eq!(parsed("((a))"), "a");
+        assert_eq!(parsed("-(a + b)"), "-(a + b)");
+        assert_eq!(parsed("(x & 1) << (k - 1)"), "((x & 1) << (k - 1))");
     }
 
     #[test]
     fn test_unary_operations() {
-        assert!(parse("!true", Rule::un_op));
-        assert!(parse("-42", Rule::un_op));
+        assert!(parse("!true", Rule::bin_op));
+        assert!(parse("-42", Rule::bin_op));
+        assert!(parse("~mask", Rule::bin_op));
     }
 
     #[test]
@@ -114,6 +476,22 @@ mod tests {
         assert!(parse(program, Rule::if_expr));
     }
 
+    #[test]
+    fn test_comments() {
+        assert!(parse("# a comment\nprint(1)\n", Rule::program));
+        assert!(parse(
+            "val x: int = 1 # trailing comment\nprint(x)\n",
+            Rule::program
+        ));
+        assert!(parse("/* block\n   comment */\nprint(1)\n", Rule::program));
+        assert!(parse("val x: int = 1 /* inline */ + 2\n", Rule::program));
+        assert!(parse(
+            "def f() {\n    # inside a block\n    print(1)\n}\n",
+            Rule::program
+        ));
+        assert!(!parse("/* unterminated\nprint(1)\n", Rule::program));
+    }
+
     #[test]
     fn test_complete_programs() {
         let program = r#"
@@ -140,4 +518,373 @@ mod tests {
         "#;
         // assert!(parse(program_with_multiple_functions, Rule::program));
     }
+
+    #[test]
+    fn test_input_header() {
+        assert!(parse(
+            "input { n: int, a: list<int>(n) }",
+            Rule::input_header
+        ));
+        assert!(parse(
+            "input {\n    n: int, m: int\n    g: list<list<int>>(n, m)\n}",
+            Rule::input_header
+        ));
+        assert!(parse(
+            "import utils\ninput { n: int }\nprint(n)\n",
+            Rule::program
+        ));
+        // the header comes before any other code
+        assert!(!parse("print(1)\ninput { n: int }\n", Rule::program));
+        assert!(!parse("input { }", Rule::input_header));
+    }
+
+    #[test]
+    fn test_list_methods() {
+        assert!(parse("xs.push(1)", Rule::postfix_expr));
+        assert!(parse("xs.insert(0, x + 1)", Rule::postfix_expr));
+        assert!(parse("g[i].pop()", Rule::postfix_expr));
+        assert_eq!(parsed("xs.pop() + 1"), "(xs.pop() + 1)");
+    }
+
+    #[test]
+    fn test_maps() {
+        assert!(parse("map<string, int>", Rule::type_annotation));
+        assert!(parse("map<int, list<int>>()", Rule::empty_collection));
+        assert!(parse(
+            "var m: map<int, int> = map<int, int>()",
+            Rule::assignment
+        ));
+        assert!(!parse("map<int>()", Rule::empty_collection));
+    }
+
+    #[test]
+    fn test_pairs() {
+        assert!(parse("pair<int, list<string>>", Rule::type_annotation));
+        assert!(parse(
+            "var p: pair<int, int> = pair(1, 2)",
+            Rule::assignment
+        ));
+        assert!(parse("val p: pair<int, int> = q\n", Rule::program));
+        assert!(!parse("val p: pair<int> = q\n", Rule::program));
+    }
+
+    #[test]
+    fn test_heaps() {
+        assert!(parse("heap<int>()", Rule::empty_collection));
+        assert!(parse("min_heap<pair<int, int>>()", Rule::empty_collection));
+        assert!(parse(
+            "var h: min_heap<int> = min_heap<int>()\n",
+            Rule::program
+        ));
+        assert!(!parse(
+            "var h: max_heap<int> = heap<int>()\n",
+            Rule::program
+        ));
+    }
+
+    #[test]
+    fn test_containers() {
+        assert!(parse("stack<int>()", Rule::empty_collection));
+        assert!(parse("queue<pair<int, int>>()", Rule::empty_collection));
+        assert!(parse("var d: deque<int> = deque<int>()\n", Rule::program));
+        assert!(!parse("var d: deque<int, int> = q\n", Rule::program));
+    }
+
+    #[test]
+    fn test_grids() {
+        assert!(parse("grid<int>(n, m)", Rule::grid_init));
+        assert!(parse("grid<string>(2, 3, \".\")", Rule::grid_init));
+        assert!(!parse("grid<int>(n)", Rule::grid_init));
+        assert!(parse(
+            "for (r, c) in g.neighbors4(0, 0) {\n    print(r)\n}\n",
+            Rule::program
+        ));
+    }
+
+    #[test]
+    fn test_mint() {
+        assert!(parse("mint<998244353>", Rule::type_annotation));
+        assert!(parse("mint<1000000007>(x + 1)", Rule::conversion));
+        assert!(!parse("mint<p>(x)", Rule::conversion));
+    }
+
+    #[test]
+    fn test_i128() {
+        assert!(parse("i128", Rule::type_annotation));
+        assert!(parse("i128(x * 2)", Rule::conversion));
+        assert!(parse(
+            "val x: i128 = 100000000000000000000\n",
+            Rule::program
+        ));
+    }
+
+    #[test]
+    fn test_bitsets() {
+        assert!(parse("var dp: bitset = bitset(n + 1)", Rule::assignment));
+        assert_eq!(kind("dp |= dp << w"), "ReassignmentExpr");
+        assert_eq!(kind("mask ^= 1 << i"), "ReassignmentExpr");
+        assert_eq!(kind("x >>= 2"), "ReassignmentExpr");
+    }
+
+    #[test]
+    fn test_tests_directive() {
+        let multi_test = |src: &str| match *crate::parser::parse_program(src).unwrap() {
+            crate::ast::AstNode::Program(program) => program.multi_test,
+            _ => panic!("expected a program"),
+        };
+        assert!(multi_test("tests\ninput { n: int }\nprint(n)\n"));
+        assert!(multi_test("import utils\n\ntests\n\nprint(read_int())\n"));
+        assert!(!multi_test("print(read_int())\n"));
+        // a variable named tests is not the directive
+        assert!(!multi_test("val tests: int = 3\nprint(tests)\n"));
+        // only at the top; further down it is just an identifier
+        assert!(!multi_test("input { n: int }\ntests\n"));
+        assert!(!multi_test("print(1)\ntests\n"));
+    }
+
+    #[test]
+    fn test_debug() {
+        assert!(parse("debug(x)", Rule::debug_expr));
+        assert!(parse("debug(x, xs[i] + 1, f(y))", Rule::debug_expr));
+        assert!(!parse("debug()", Rule::debug_expr));
+        // each value keeps its source text as a label
+        let program = crate::parser::parse_program("debug(x,  xs[i] + 1)\n").unwrap();
+        let Some(Expr::DebugExpr(debug)) = program.Program().unwrap().expressions.pop() else {
+            panic!("expected a debug expression");
+        };
+        let labels = debug.args.iter().map(|(text, _)| text.as_str());
+        assert_eq!(labels.collect::<Vec<_>>(), ["x", "xs[i] + 1"]);
+    }
+
+    #[test]
+    fn test_interactive_directive() {
+        let flags = |src: &str| match *crate::parser::parse_program(src).unwrap() {
+            crate::ast::AstNode::Program(program) => (program.multi_test, program.interactive),
+            _ => panic!("expected a program"),
+        };
+        assert_eq!(flags("interactive\nprint(1)\n"), (false, true));
+        // directives may come in either order
+        assert_eq!(flags("tests\ninteractive\nprint(1)\n"), (true, true));
+        assert_eq!(flags("interactive\ntests\nprint(1)\n"), (true, true));
+        assert_eq!(flags("print(1)\n"), (false, false));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // every level used to parse its operand once per alternative tried,
+        // so these took time exponential in the depth
+        let depth = 40;
+        let parens = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(kind(&parens), "Integer");
+        let calls = format!("{}1{}", "max(".repeat(depth), ", 2)".repeat(depth));
+        assert_eq!(kind(&calls), "MethodCallExpr");
+        let mixed = format!("{}0{}", "(-xs[".repeat(depth), "] + 1)".repeat(depth));
+        assert_eq!(kind(&mixed), "BinOp");
+    }
+
+    #[test]
+    fn test_separators() {
+        // trailing commas
+        assert!(parse("[1, 2, 3,]", Rule::list_expr));
+        assert_eq!(kind("(1, 2,)"), "TupleExpr");
+        assert!(parse("f(1, 2,)", Rule::method_call));
+        assert!(parse("xs.push(1,)", Rule::postfix_expr));
+        assert!(parse("def f(a: int, b: int,) {\n}", Rule::function_def));
+        assert_eq!(kind("(1,)"), "");
+        assert!(!parse("f(,)", Rule::method_call));
+        // items one per line, breaking after commas
+        assert!(parse("[\n    1,\n    2\n]", Rule::list_expr));
+        assert!(parse("f(\n    a,\n\n    b,\n)", Rule::method_call));
+        assert!(parse(
+            "def f(\n    a: int,\n) -> int {\n}",
+            Rule::function_def
+        ));
+        assert!(parse(
+            "val (a,\n     b) = t",
+            Rule::destructuring_assignment
+        ));
+        assert!(!parse("[1\n, 2]", Rule::list_expr));
+        assert_eq!(parsed("f(\n    a + 1,\n    b,\n)"), "f((a + 1), b)");
+        // else on its own line, and indented or blank lines between statements
+        assert!(parse("if x {\n}\nelse {\n}", Rule::if_expr));
+        let program = crate::parser::parse_program("  val x = 1\n  \n\n    print(x)\n")
+            .unwrap()
+            .Program()
+            .unwrap();
+        assert_eq!(program.expressions.len(), 2);
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert!(parse("größe", Rule::identifier));
+        assert!(parse("名前", Rule::identifier));
+        assert!(parse("_ñ1", Rule::identifier));
+        assert!(!parse("1ñ", Rule::identifier));
+        assert!(!parse("🎉", Rule::identifier));
+        assert_eq!(parsed("größe + 名前"), "(größe + 名前)");
+        // a keyword only ends where the name does
+        assert_eq!(parsed("noneé"), "noneé");
+        let program = crate::parser::parse_program("print(\"Grüß dich! 🎉\")\n")
+            .unwrap()
+            .Program()
+            .unwrap();
+        let Expr::PrintExpr(print) = &program.expressions[0] else {
+            panic!("expected a print");
+        };
+        let Expr::String(text) = &*print.arg else {
+            panic!("expected a string");
+        };
+        assert_eq!(text.value, "Grüß dich! 🎉");
+    }
+
+    #[test]
+    fn test_operator_enums() {
+        use crate::ast::{Associativity, BinOp, UnOp};
+        for op in BinOp::ALL {
+            assert_eq!(BinOp::from_symbol(op.symbol()), Some(op));
+            assert!(op.precedence() < UnOp::Neg.precedence());
+        }
+        assert_eq!(UnOp::from_symbol("~"), Some(UnOp::BitNot));
+        assert!(BinOp::Mul.precedence() > BinOp::Add.precedence());
+        assert!(BinOp::BitAnd.precedence() > BinOp::Eq.precedence());
+        assert!(BinOp::Lt.is_comparison() && !BinOp::Shl.is_comparison());
+        assert_eq!(BinOp::Coalesce.associativity(), Associativity::Right);
+        // `-` is either operator depending on where it stands
+        assert_eq!(parsed("-a - b"), "(-a - b)");
+        let program = crate::parser::parse_program("x //= 2\ny = 1\n")
+            .unwrap()
+            .Program()
+            .unwrap();
+        let ops = program
+            .expressions
+            .iter()
+            .map(|expr| match expr {
+                Expr::ReassignmentExpr(assign) => assign.op,
+                other => panic!("expected a reassignment, got {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ops, [Some(BinOp::IntDiv), None]);
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let errors = |src: &str| {
+            crate::parser::parse_program(src)
+                .unwrap_err()
+                .iter()
+                .map(|e| (e.span.unwrap().start, e.message.clone()))
+                .collect::<Vec<_>>()
+        };
+        // each bad statement is skipped up to the end of its line
+        assert_eq!(
+            errors("val x = (1 + 2\nprint(x)\nval y = 3 3\n"),
+            [
+                (
+                    14,
+                    "expected ')', ',' or an operator, found end of line".to_string()
+                ),
+                (
+                    34,
+                    "expected an operator or end of line, found '3'".to_string()
+                ),
+            ]
+        );
+        // a bad header skips the block it opens; inside a block the closing
+        // brace stays, and braces in strings don't count
+        let src = "def f(a: int {\n    return a\n}\nif 1 > 0 {\n    val y = \n    print(\"}\")\n}\nprint(+)\n";
+        assert_eq!(
+            errors(src),
+            [
+                (13, "expected ')' or ',', found '{'".to_string()),
+                (53, "expected an expression, found end of line".to_string()),
+                (77, "expected ')' or an expression, found '+'".to_string()),
+            ]
+        );
+        // nor do braces in comments, while `//` divides
+        let src = "if 1 > 0 {\n    val y = 7 // 2 3 }\nval b = 1 + # {\nprint(1)\nval c = * /* } */\nprint(2)\n";
+        assert_eq!(
+            errors(src),
+            [
+                (
+                    30,
+                    "expected an operator or end of line, found '3'".to_string()
+                ),
+                (49, "expected an expression, found end of line".to_string()),
+                (67, "expected an expression, found '*'".to_string()),
+            ]
+        );
+        // recovery stops once nothing is left to skip
+        assert_eq!(errors("def f() {\n    print(1)\n").len(), 1);
+        assert!(crate::parser::parse_program("print(1)\n}\nprint(2)\n").is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "val x = -a + f(b * 2)\nprint(x)\n";
+        let program = crate::parser::parse_program(src)
+            .unwrap()
+            .Program()
+            .unwrap();
+        let text = |expr: &Expr| expr.extent().map(|s| &src[s.start..s.end]);
+        let Expr::AssignmentExpr(assign) = &program.expressions[0] else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(text(&program.expressions[0]), Some("val x = -a + f(b * 2)"));
+        assert_eq!(text(&assign.value), Some("-a + f(b * 2)"));
+        let Expr::BinOp(sum) = &*assign.value else {
+            panic!("expected a binop");
+        };
+        assert_eq!(text(&sum.left), Some("-a"));
+        assert_eq!(text(&sum.right), Some("f(b * 2)"));
+        // the operator keeps its own span for diagnostics
+        assert_eq!(sum.op_span.map(|s| &src[s.start..s.end]), Some("+"));
+        let Expr::MethodCallExpr(call) = &*sum.right else {
+            panic!("expected a call");
+        };
+        assert_eq!(text(&call.args[0]), Some("b * 2"));
+        assert_eq!(text(&program.expressions[1]), Some("print(x)"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |src: &str| crate::parser::parse_program(src).unwrap_err().remove(0);
+        assert_eq!(
+            error("val x = \n").message,
+            "expected an expression, found end of line"
+        );
+        assert_eq!(
+            error("val x = (1 + 2\n").message,
+            "expected ')', ',' or an operator, found end of line"
+        );
+        assert_eq!(
+            error("for x of xs {\n}\n").message,
+            "expected 'in', found 'of'"
+        );
+        assert_eq!(
+            error("val bbl_x = 1\n").message,
+            "'bbl_x' is reserved: names starting with `bbl_` belong to the generated C++"
+        );
+        // the span covers what was found, as byte offsets
+        let e = error("struct P { x int }\n");
+        assert_eq!(e.message, "expected ':', found 'int'");
+        assert_eq!(e.code, "parse-error");
+        assert_eq!(e.span.map(|s| (s.start, s.end)), Some((13, 16)));
+        // rendered with the line and a caret under the problem
+        let source = crate::diagnostic::SourceFile {
+            path: "main.bbl".to_string(),
+            text: "print(1)\nprint(2))\n".to_string(),
+            start: 0,
+        };
+        assert_eq!(
+            error(&source.text).render(&[source]),
+            "error[parse-error]: expected end of line, found ')'\n --> main.bbl:2:9\n  |\n2 | print(2))\n  |         ^"
+        );
+    }
+
+    #[test]
+    fn test_sets() {
+        assert!(parse("set<string>", Rule::type_annotation));
+        assert!(parse("set<int>()", Rule::empty_collection));
+        assert!(!parse("set<int, int>()", Rule::empty_collection));
+    }
 }
@@ -0,0 +1,124 @@
+use crate::ast::{Expr, Program};
+use crate::diagnostic::{Diagnostic, SourceFile};
+use crate::parser::parse_source;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Extensions tried, in order, when resolving `import name`
+const MODULE_EXTENSIONS: [&str; 2] = ["bbl", "bdl"];
+
+// Parses the file at `path` and inlines every module it imports, so the
+// result is a single program for typechecking and codegen. A module's code
+// lands where it is first imported; later imports of it are no-ops. Only the
+// main file's `tests` and `interactive` lines count. Errors come with the
+// files read so far, which their spans point into.
+pub fn load_program(path: &Path) -> Result<Program, (Vec<Diagnostic>, Vec<SourceFile>)> {
+    let mut loader = ModuleLoader::default();
+    let mut expressions = Vec::new();
+    if let Err(e) = loader.load(path, &mut expressions) {
+        return Err((e, loader.sources));
+    }
+    Ok(Program {
+        expressions,
+        multi_test: loader.multi_test,
+        interactive: loader.interactive,
+        sources: loader.sources,
+    })
+}
+
+#[derive(Default)]
+struct ModuleLoader {
+    loaded: HashSet<PathBuf>,
+    // modules whose imports are being resolved, innermost last
+    stack: Vec<PathBuf>,
+    multi_test: bool,
+    interactive: bool,
+    // every file parsed so far, laid end to end for spans
+    sources: Vec<SourceFile>,
+}
+
+impl ModuleLoader {
+    // every parse error in a file is reported; anything else stops loading
+    fn load(&mut self, path: &Path, out: &mut Vec<Expr>) -> Result<(), Vec<Diagnostic>> {
+        let path = path.canonicalize().map_err(|e| {
+            vec![Diagnostic::error(
+                "load-error",
+                format!("Failed to read {}: {}", path.display(), e),
+            )]
+        })?;
+        if self.stack.contains(&path) {
+            return Err(vec![Diagnostic::error(
+                "load-error",
+                format!("Circular import of {}", path.display()),
+            )]);
+        }
+        if !self.loaded.insert(path.clone()) {
+            return Ok(());
+        }
+
+        let src = fs::read_to_string(&path).map_err(|e| {
+            vec![Diagnostic::error(
+                "load-error",
+                format!("Failed to read {}: {}", path.display(), e),
+            )]
+        })?;
+        let start = self
+            .sources
+            .last()
+            .map_or(0, |file| file.start + file.text.len() + 1);
+        // recorded before parsing so a parse error can point into the file
+        self.sources.push(SourceFile {
+            path: path.display().to_string(),
+            text: src.clone(),
+            start,
+        });
+        let program = parse_source(&src, &path.display().to_string(), start)?
+            .Program()
+            .ok_or_else(|| {
+                vec![Diagnostic::error(
+                    "load-error",
+                    format!("{}: expected a program", path.display()),
+                )]
+            })?;
+
+        if self.stack.is_empty() {
+            self.multi_test = program.multi_test;
+            self.interactive = program.interactive;
+        }
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.stack.push(path);
+        for expr in program.expressions {
+            match expr {
+                Expr::ImportExpr(import) => {
+                    let module = find_module(&dir, &import.module.value).map_err(|e| {
+                        vec![match import.module.span {
+                            Some(span) => e.with_span(span),
+                            None => e,
+                        }]
+                    })?;
+                    self.load(&module, out)?;
+                }
+                other => out.push(other),
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+fn find_module(dir: &Path, name: &str) -> Result<PathBuf, Diagnostic> {
+    MODULE_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|candidate| candidate.is_file())
+        .ok_or(Diagnostic::error(
+            "load-error",
+            format!(
+                "Cannot find module '{}' ({}.bbl) in {}",
+                name,
+                name,
+                dir.display()
+            ),
+        ))
+}
@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod tests {
+    use crate::resolve::load_program;
+    use crate::typeck::TypeChecker;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Writes `files` into a fresh directory and returns its path
+    fn write_modules(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bbl_{}_{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, src) in files {
+            fs::write(dir.join(name), src).unwrap();
+        }
+        dir
+    }
+
+    fn check(path: PathBuf) -> Result<(), String> {
+        let program = load_program(&path).map_err(|(errors, _)| errors[0].message.clone())?;
+        TypeChecker::new()
+            .check_program(&program)
+            .map_err(|e| e.message)
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = write_modules(
+            "imports",
+            &[
+                (
+                    "utils.bbl",
+                    "import consts\ndef add_mod(a: int, b: int) -> int {\n    return (a + b) % MOD\n}\n",
+                ),
+                ("consts.bbl", "const MOD: int = 7\n"),
+                (
+                    "main.bbl",
+                    "import utils\nimport consts\nprint(add_mod(5, MOD))\n",
+                ),
+            ],
+        );
+        let program = load_program(&dir.join("main.bbl")).unwrap();
+        // consts is inlined once, before utils uses it
+        assert_eq!(program.expressions.len(), 3);
+        assert!(check(dir.join("main.bbl")).is_ok());
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = write_modules(
+            "import_errors",
+            &[
+                ("a.bbl", "import b\n"),
+                ("b.bbl", "import a\n"),
+                ("missing.bbl", "import nowhere\n"),
+                ("undefined.bbl", "print(add_mod(1, 2))\n"),
+                ("broken.bbl", "val x = \n"),
+                ("uses_broken.bbl", "import broken\nprint(1)\n"),
+            ],
+        );
+        assert!(load_program(&dir.join("a.bbl")).is_err());
+        assert!(load_program(&dir.join("missing.bbl")).is_err());
+        // definitions only come from imported modules
+        assert!(check(dir.join("undefined.bbl")).is_err());
+        // a parse error in a module points into that module
+        let (errors, sources) = load_program(&dir.join("uses_broken.bbl")).unwrap_err();
+        assert_eq!(errors[0].code, "parse-error");
+        assert!(errors[0].render(&sources).contains(&format!(
+            "{}:1:9",
+            dir.join("broken.bbl").canonicalize().unwrap().display()
+        )));
+    }
+}
@@ -1,103 +1,332 @@
 use crate::ast::*;
-use std::collections::HashMap;
+use crate::consteval::{self, ConstValue};
+use crate::diagnostic::Diagnostic;
+use crate::format::{parse_format, FormatPart};
+use crate::lint::terminates;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug)]
-pub struct TypeError {
-    pub message: String,
+pub type TypeResult<T> = Result<T, Diagnostic>;
+
+// Names type annotations already give a meaning, so no struct may take them
+const BUILTIN_TYPE_NAMES: [&str; 20] = [
+    "int", "i128", "float", "string", "bool", "range", "bitset", "list", "tuple", "pair", "option",
+    "map", "set", "heap", "min_heap", "stack", "queue", "deque", "grid", "mint",
+];
+
+fn type_error(message: impl Into<String>) -> Diagnostic {
+    Diagnostic::error("type-error", message)
+}
+
+// The names visible outside a block, saved by TypeChecker::enter_scope
+pub struct Scope {
+    symbol_table: HashMap<String, Type>,
+    immutables: HashSet<String>,
+    overloads: HashMap<String, Vec<Type>>,
+    depths: HashMap<String, usize>,
+    captures: HashMap<String, Capture>,
+}
+
+// A nested function a value may hold. It captures the locals of the body
+// defining it by reference, so the value must not outlive that body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    // how many function bodies enclose the nested function's own body
+    pub depth: usize,
+    pub function: String,
+    // the function whose body defines it
+    pub owner: String,
 }
 
-pub type TypeResult<T> = Result<T, TypeError>;
+// An option variable known to hold a value in one branch of an if
+pub struct Narrowing {
+    pub name: String,
+    pub inner: Type,
+    // whether the then branch is the narrowed one, else the else branch
+    pub in_then: bool,
+}
 
 pub struct TypeChecker {
     pub symbol_table: HashMap<String, Type>,
+    // struct name -> fields in declaration order
+    pub struct_table: HashMap<String, Vec<TypedIdentifier>>,
+    // each function whose body encloses the expression being checked,
+    // innermost last
+    pub functions: Vec<String>,
+    // name -> how many function bodies enclosed its declaration
+    pub depths: HashMap<String, usize>,
+    // names that may hold a nested function
+    pub captures: HashMap<String, Capture>,
+    // declared return type of each enclosing function, innermost last; None
+    // for functions that don't return a value
+    pub return_types: Vec<Option<Type>>,
+    // names declared with `const`, with their values folded at compile time
+    pub constants: HashMap<String, ConstValue>,
+    // names bound with `val` or as loop variables
+    pub immutables: HashSet<String>,
+    // every signature of a function name defined more than once with
+    // different parameter types, oldest first
+    pub overloads: HashMap<String, Vec<Type>>,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         TypeChecker {
             symbol_table: HashMap::new(),
+            struct_table: HashMap::new(),
+            functions: Vec::new(),
+            depths: HashMap::new(),
+            captures: HashMap::new(),
+            return_types: Vec::new(),
+            constants: HashMap::new(),
+            immutables: HashSet::new(),
+            overloads: HashMap::new(),
+        }
+    }
+
+    // Opens a block. A name declared inside shadows any outer one with the
+    // same name until the matching exit_scope, which forgets the block's names.
+    pub fn enter_scope(&self) -> Scope {
+        Scope {
+            symbol_table: self.symbol_table.clone(),
+            immutables: self.immutables.clone(),
+            overloads: self.overloads.clone(),
+            depths: self.depths.clone(),
+            captures: self.captures.clone(),
         }
     }
 
+    pub fn exit_scope(&mut self, scope: Scope) {
+        self.symbol_table = scope.symbol_table;
+        self.immutables = scope.immutables;
+        self.overloads = scope.overloads;
+        self.depths = scope.depths;
+        self.captures = scope.captures;
+    }
+
+    fn check_block(&mut self, block: &[Expr]) -> TypeResult<()> {
+        let scope = self.enter_scope();
+        let result = self.check_statements(block);
+        self.exit_scope(scope);
+        result
+    }
+
     pub fn check_program(&mut self, program: &Program) -> TypeResult<()> {
-        for expr in &program.expressions {
+        self.check_statements(&program.expressions)
+    }
+
+    fn check_statements(&mut self, block: &[Expr]) -> TypeResult<()> {
+        for (index, expr) in block.iter().enumerate() {
+            self.declare_ahead(block, index);
             self.check_expr(expr)?;
         }
         Ok(())
     }
 
+    // Functions defined one after another can call each other: at the start
+    // of such a run, the later ones are declared before the first is checked.
+    // A name that already means something, or is defined twice in the run,
+    // is left alone so overloading and shadowing work as before. Returns the
+    // indices in `block` of the functions declared.
+    pub fn declare_ahead(&mut self, block: &[Expr], index: usize) -> Vec<usize> {
+        if index > 0 && matches!(block[index - 1], Expr::FunctionDef(_)) {
+            return Vec::new();
+        }
+        let run: Vec<(usize, &FunctionDef)> = block[index..]
+            .iter()
+            .map_while(|expr| match expr {
+                Expr::FunctionDef(func) => Some(func),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(offset, func)| (index + offset, func))
+            .collect();
+        let mut declared = Vec::new();
+        for &(at, func) in run.iter().skip(1) {
+            let name = &func.name.value;
+            let repeated = run
+                .iter()
+                .filter(|(_, other)| &other.name.value == name)
+                .count()
+                > 1;
+            if repeated
+                || self.symbol_table.contains_key(name)
+                || self.check_type_exists(&func.get_type()).is_err()
+            {
+                continue;
+            }
+            self.symbol_table.insert(name.clone(), func.get_type());
+            self.bind_function(name);
+            declared.push(at);
+        }
+        declared
+    }
+
+    // Errors point at the innermost expression with a known position
     pub fn check_expr(&mut self, expr: &Expr) -> TypeResult<Type> {
+        self.check_expr_kind(expr)
+            .map_err(|e| match (e.span, expr.span()) {
+                (None, Some(span)) => e.with_span(span),
+                _ => e,
+            })
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> TypeResult<Type> {
         match expr {
-            Expr::Integer(_) => Ok(Type::Int),
+            Expr::Integer(i) => match &i.too_large {
+                Some(text) => Err(type_error(format!(
+                    "Integer literal {} is too large, even for an i128",
+                    text
+                ))),
+                None => Ok(i.get_type()),
+            },
             Expr::Float(_) => Ok(Type::Float),
             Expr::String(_) => Ok(Type::String),
+            Expr::FString(fstring) => {
+                for part in &fstring.parts {
+                    if let FStringPart::Expr(e) = part {
+                        let t = self.check_expr(e)?;
+                        require(
+                            &t,
+                            Capability::Printable,
+                            "values interpolated into a string",
+                        )?;
+                    }
+                }
+                Ok(Type::String)
+            }
             Expr::Boolean(_) => Ok(Type::Bool),
-            Expr::Identifier(id) => self.symbol_table.get(&id.value).cloned().ok_or(TypeError {
-                message: format!("Undefined variable '{}'", id.value),
-            }),
+            Expr::Identifier(id) => self
+                .symbol_table
+                .get(&id.value)
+                .cloned()
+                .ok_or(type_error(format!("Undefined variable '{}'", id.value))),
             Expr::AssignmentExpr(assign) => {
-                let rhs_type = self.check_expr(&assign.value)?;
-                let lhs_type = assign.target.associated_type.clone();
-                if rhs_type != lhs_type {
-                    return Err(TypeError {
-                        message: format!(
-                            "Type mismatch in assignment to '{}': expected {:?}, got {:?}",
-                            assign.target.value.value, lhs_type, rhs_type
-                        ),
-                    });
+                if let Some(annotation) = &assign.annotation {
+                    self.check_type_exists(annotation)?;
                 }
-                self.symbol_table
-                    .insert(assign.target.value.value.clone(), lhs_type.clone());
+                let rhs_type = self.check_expr(&assign.value)?;
+                let name = &assign.target.value;
+                let lhs_type = match &assign.annotation {
+                    Some(lhs_type) if unify(lhs_type, &rhs_type).is_none() => {
+                        return Err(type_error(format!(
+                            "Type mismatch in assignment to '{}': expected {}, got {}",
+                            name, lhs_type, rhs_type
+                        )));
+                    }
+                    Some(lhs_type) => lhs_type.clone(),
+                    None => {
+                        let inferred = self.infer_binding_type(name, rhs_type)?;
+                        assign.inferred.get_or_init(|| inferred.clone());
+                        inferred
+                    }
+                };
+                let capture = self.capture_in(&assign.value, &lhs_type);
+                self.symbol_table.insert(name.clone(), lhs_type.clone());
+                self.set_mutable(name, !assign.const_var);
+                self.bind(name, capture);
                 Ok(lhs_type)
             }
+            Expr::ConstDef(def) => {
+                let name = &def.target.value.value;
+                if self.symbol_table.contains_key(name) {
+                    return Err(type_error(format!(
+                        "Constant '{}' is already defined",
+                        name
+                    )));
+                }
+                if !self.is_constant_expr(&def.value) {
+                    return Err(type_error(format!(
+                            "Initializer of constant '{}' must be built from literals and other constants",
+                            name
+                        )));
+                }
+                let value_type = self.check_expr(&def.value)?;
+                let const_type = def.target.associated_type.clone();
+                if unify(&const_type, &value_type).is_none() {
+                    return Err(type_error(format!(
+                        "Type mismatch in constant '{}': expected {}, got {}",
+                        name, const_type, value_type
+                    )));
+                }
+                let value = consteval::eval(&def.value, &self.constants)
+                    .map_err(|e| type_error(format!("In constant '{}': {}", name, e)))?;
+                self.symbol_table.insert(name.clone(), const_type.clone());
+                self.constants.insert(name.clone(), value);
+                Ok(const_type)
+            }
+            Expr::DestructuringAssignmentExpr(assign) => {
+                let value_type = self.check_expr(&assign.value)?;
+                let elem_types = match value_type {
+                    Type::List(elem_type) if assign.list_pattern => {
+                        vec![*elem_type; assign.targets.len()]
+                    }
+                    Type::Tuple(elem_types)
+                        if !assign.list_pattern && elem_types.len() == assign.targets.len() =>
+                    {
+                        elem_types
+                    }
+                    Type::Pair(first, second)
+                        if !assign.list_pattern && assign.targets.len() == 2 =>
+                    {
+                        vec![*first, *second]
+                    }
+                    other => {
+                        return Err(type_error(format!(
+                            "Cannot destructure {} into {} variables",
+                            other,
+                            assign.targets.len()
+                        )))
+                    }
+                };
+                for (target, elem_type) in assign.targets.iter().zip(&elem_types) {
+                    let capture = self.capture_in(&assign.value, elem_type);
+                    self.symbol_table
+                        .insert(target.value.clone(), elem_type.clone());
+                    self.set_mutable(&target.value, !assign.const_var);
+                    self.bind(&target.value, capture);
+                }
+                Ok(Type::Tuple(elem_types))
+            }
             Expr::ReassignmentExpr(reassign) => {
                 let rhs_type = self.check_expr(&reassign.value)?;
-                let var_type = self
-                    .symbol_table
-                    .get(&reassign.target.value)
-                    .ok_or(TypeError {
-                        message: format!("Undefined variable '{}'", reassign.target.value),
-                    })?;
-                if *var_type != rhs_type {
-                    return Err(TypeError {
-                        message: format!(
-                            "Type mismatch in reassignment to '{}': expected {:?}, got {:?}",
-                            reassign.target.value, var_type, rhs_type
-                        ),
-                    });
+                let target_name = reassign
+                    .target
+                    .root_identifier()
+                    .ok_or(type_error("Invalid assignment target".to_string()))?
+                    .value
+                    .clone();
+                let var_type = self.check_expr(&reassign.target)?;
+                if self.constants.contains_key(&target_name) {
+                    return Err(type_error(format!(
+                        "Cannot assign to constant '{}'",
+                        target_name
+                    )));
                 }
+                if !self.is_mutable_place(&reassign.target) {
+                    return Err(type_error(format!(
+                            "Cannot assign to '{}', which is a val or loop variable; declare it with var to change it",
+                            target_name
+                        )));
+                }
+                // `x op= v` must typecheck as `x = x op v`
+                let rhs_type = match &reassign.op {
+                    Some(op) => self.check_binop(*op, var_type.clone(), rhs_type)?,
+                    None => rhs_type,
+                };
+                if unify(&var_type, &rhs_type).is_none() {
+                    return Err(type_error(format!(
+                        "Type mismatch in reassignment to '{}': expected {}, got {}",
+                        target_name, var_type, rhs_type
+                    )));
+                }
+                let capture = self.capture_in(&reassign.value, &rhs_type);
+                self.store(&target_name, capture)?;
                 Ok(var_type.clone())
             }
             Expr::BinOp(binop) => {
                 let left_type = self.check_expr(&binop.left)?;
                 let right_type = self.check_expr(&binop.right)?;
-                if left_type != right_type {
-                    return Err(TypeError {
-                        message: format!(
-                            "Type mismatch in binary operation '{}': left is {:?}, right is {:?}",
-                            binop.op, left_type, right_type
-                        ),
-                    });
-                }
-                // For now, just return the type if it's int/float/string/bool
-                match binop.op.as_str() {
-                    "+" | "-" | "*" | "/" => {
-                        if left_type == Type::Int || left_type == Type::Float {
-                            Ok(left_type)
-                        } else {
-                            Err(TypeError {
-                                message: format!(
-                                    "Operator '{}' not supported for type {:?}",
-                                    binop.op, left_type
-                                ),
-                            })
-                        }
-                    }
-                    "==" | "!=" | "<" | ">" | "<=" | ">=" => Ok(Type::Bool),
-                    _ => Err(TypeError {
-                        message: format!("Unknown operator '{}'", binop.op),
-                    }),
-                }
+                self.check_binop(binop.op, left_type, right_type)
             }
             Expr::ListExpr(list) => {
                 // Check all elements have the same type
@@ -105,116 +334,1907 @@ impl TypeChecker {
                 for elem in &list.elems {
                     let t = self.check_expr(elem)?;
                     if let Some(ref et) = elem_type {
-                        if *et != t {
-                            return Err(TypeError {
-                                message: format!(
-                                    "List elements have mismatched types: {:?} vs {:?}",
-                                    et, t
-                                ),
-                            });
-                        }
+                        elem_type = Some(unify(et, &t).ok_or(type_error(format!(
+                            "List elements have mismatched types: {} vs {}",
+                            et, t
+                        )))?);
                     } else {
                         elem_type = Some(t);
                     }
                 }
                 Ok(Type::List(Box::new(elem_type.unwrap_or(Type::None))))
             }
+            Expr::EmptyCollectionExpr(empty) => {
+                self.check_type_exists(&empty.collection_type)?;
+                Ok(empty.collection_type.clone())
+            }
+            Expr::ConversionExpr(conversion) => {
+                self.check_type_exists(&conversion.target_type)?;
+                let value_type = self.check_expr(&conversion.value)?;
+                match (&conversion.target_type, &value_type) {
+                    (Type::ModInt(_) | Type::I128, Type::Int) => Ok(conversion.target_type.clone()),
+                    (target, value) if target == value => Ok(target.clone()),
+                    (target, value) => Err(type_error(format!(
+                        "Cannot convert {} to {}",
+                        value, target
+                    ))),
+                }
+            }
+            Expr::GridInitExpr(init) => {
+                self.check_type_exists(&init.elem_type)?;
+                for size in [&init.rows, &init.cols] {
+                    if self.check_expr(size)? != Type::Int {
+                        return Err(type_error("Grid sizes must be int".to_string()));
+                    }
+                }
+                if let Some(fill) = &init.fill {
+                    let fill_type = self.check_expr(fill)?;
+                    if unify(&init.elem_type, &fill_type).is_none() {
+                        return Err(type_error(format!(
+                            "Grid fill value should be {}, got {}",
+                            init.elem_type, fill_type
+                        )));
+                    }
+                }
+                Ok(Type::Grid(Box::new(init.elem_type.clone())))
+            }
+            Expr::InputHeader(header) => {
+                for field in &header.fields {
+                    let name = &field.target.value.value;
+                    // one size per list layer, down to a readable value
+                    let mut elem_type = field.target.associated_type.clone();
+                    for dim in &field.dims {
+                        if self.check_expr(dim)? != Type::Int {
+                            return Err(type_error(format!(
+                                "Size of input '{}' must be int",
+                                name
+                            )));
+                        }
+                        elem_type = match elem_type {
+                            Type::List(inner) => *inner,
+                            _ => {
+                                return Err(type_error(format!(
+                                    "Too many sizes for input '{}'",
+                                    name
+                                )))
+                            }
+                        };
+                    }
+                    match elem_type {
+                        Type::Int | Type::Float | Type::String => {}
+                        Type::List(_) => {
+                            return Err(type_error(format!(
+                                "Input list '{}' needs a size per level",
+                                name
+                            )))
+                        }
+                        other => {
+                            return Err(type_error(format!(
+                                "Input '{}' cannot be read as {}",
+                                name, other
+                            )))
+                        }
+                    }
+                    self.symbol_table
+                        .insert(name.clone(), field.target.associated_type.clone());
+                    self.set_mutable(name, true);
+                    self.bind(name, None);
+                }
+                Ok(Type::None)
+            }
+            Expr::ListInitExpr(init) => {
+                self.check_type_exists(&init.list_type)?;
+                // peel one list layer per size to find the fill type
+                let mut fill_type = init.list_type.clone();
+                for dim in &init.dims {
+                    if self.check_expr(dim)? != Type::Int {
+                        return Err(type_error("List sizes must be int".to_string()));
+                    }
+                    fill_type = match fill_type {
+                        Type::List(inner) => *inner,
+                        _ => {
+                            return Err(type_error(format!(
+                                "Too many sizes for {}: got {}",
+                                init.list_type,
+                                init.dims.len()
+                            )))
+                        }
+                    };
+                }
+                let value_type = self.check_expr(&init.fill)?;
+                if unify(&fill_type, &value_type).is_none() {
+                    return Err(type_error(format!(
+                        "List fill value should be {}, got {}",
+                        fill_type, value_type
+                    )));
+                }
+                Ok(init.list_type.clone())
+            }
+            Expr::TupleExpr(tuple) => {
+                let elem_types = tuple
+                    .elems
+                    .iter()
+                    .map(|e| self.check_expr(e))
+                    .collect::<TypeResult<Vec<Type>>>()?;
+                Ok(Type::Tuple(elem_types))
+            }
+            Expr::TupleAccessExpr(access) => match self.check_expr(&access.tuple)? {
+                Type::Tuple(elem_types) => {
+                    elem_types
+                        .get(access.index)
+                        .cloned()
+                        .ok_or(type_error(format!(
+                            "Tuple index {} out of range for tuple of {} elements",
+                            access.index,
+                            elem_types.len()
+                        )))
+                }
+                other => Err(type_error(format!(
+                    "Cannot access element {} of non-tuple type {}",
+                    access.index, other
+                ))),
+            },
+            Expr::IndexExpr(index) => {
+                let list_type = self.check_expr(&index.list)?;
+                let index_type = self.check_expr(&index.index)?;
+                // a missing key reads as a default value, as with C++'s map
+                if let Type::Map(key, value) = list_type {
+                    return match unify(&key, &index_type) {
+                        Some(_) => Ok(*value),
+                        None => Err(type_error(format!(
+                            "Map key must be {}, got {}",
+                            key, index_type
+                        ))),
+                    };
+                }
+                if index_type != Type::Int {
+                    return Err(type_error(format!(
+                        "List index must be int, got {}",
+                        index_type
+                    )));
+                }
+                match list_type {
+                    Type::List(elem) | Type::Deque(elem) => Ok(*elem),
+                    // a row
+                    Type::Grid(elem) => Ok(Type::List(elem)),
+                    other => Err(type_error(format!(
+                        "Cannot index into non-list type {}",
+                        other
+                    ))),
+                }
+            }
+            Expr::FieldAccessExpr(access) => match self.check_expr(&access.object)? {
+                Type::Pair(first, second) => match access.field.value.as_str() {
+                    "first" => Ok(*first),
+                    "second" => Ok(*second),
+                    field => Err(type_error(format!("Pairs have no field '{}'", field))),
+                },
+                Type::Struct(name) => {
+                    let fields = self
+                        .struct_table
+                        .get(&name)
+                        .ok_or(type_error(format!("Undefined struct '{}'", name)))?;
+                    fields
+                        .iter()
+                        .find(|f| f.value.value == access.field.value)
+                        .map(|f| f.associated_type.clone())
+                        .ok_or(type_error(format!(
+                            "Struct '{}' has no field '{}'",
+                            name, access.field.value
+                        )))
+                }
+                other => Err(type_error(format!(
+                    "Cannot access field '{}' of non-struct type {}",
+                    access.field.value, other
+                ))),
+            },
+            Expr::StructDef(def) => {
+                if BUILTIN_TYPE_NAMES.contains(&def.name.value.as_str()) {
+                    return Err(type_error(format!(
+                        "Cannot name a struct '{}', it is a builtin type",
+                        def.name.value
+                    )));
+                }
+                if self.struct_table.contains_key(&def.name.value) {
+                    return Err(type_error(format!(
+                        "Struct '{}' is already defined",
+                        def.name.value
+                    )));
+                }
+                // register before checking fields so a struct may refer to itself
+                // through a list
+                self.struct_table
+                    .insert(def.name.value.clone(), def.fields.clone());
+                for (i, field) in def.fields.iter().enumerate() {
+                    if def.fields[..i]
+                        .iter()
+                        .any(|f| f.value.value == field.value.value)
+                    {
+                        return Err(type_error(format!(
+                            "Duplicate field '{}' in struct '{}'",
+                            field.value.value, def.name.value
+                        )));
+                    }
+                    self.check_type_exists(&field.associated_type)?;
+                }
+                Ok(Type::None)
+            }
             Expr::PrintExpr(print) => {
-                self.check_expr(&print.arg)?;
+                let t = self.check_expr(&print.arg)?;
+                // cout has no `<<` for containers, so say how to print one
+                let hint = match t {
+                    Type::List(_) => {
+                        "; print the elements in a for loop, or join a list<string> with join(parts, \" \")"
+                    }
+                    Type::Pair(..) => "; print its parts, e.g. f\"{p.first} {p.second}\"",
+                    Type::Tuple(_) => "; print its elements, e.g. f\"{t.0} {t.1}\"",
+                    _ => "",
+                };
+                require(&t, Capability::Printable, "printed values").map_err(|mut e| {
+                    e.message += hint;
+                    e
+                })?;
+                Ok(Type::None)
+            }
+            Expr::DebugExpr(debug) => {
+                for (text, arg) in &debug.args {
+                    let t = self.check_expr(arg)?;
+                    require(
+                        &t,
+                        Capability::Printable,
+                        &format!("debugged values like '{}'", text),
+                    )?;
+                    // it's compiled out without -DLOCAL, and its effects with it
+                    if !self.is_pure(arg) {
+                        return Err(type_error(format!(
+                            "debug only runs in local builds, so '{}' must not have side effects",
+                            text
+                        )));
+                    }
+                }
                 Ok(Type::None)
             }
             Expr::IfExpr(ifexpr) => {
                 let cond_type = self.check_expr(&ifexpr.condition)?;
                 if cond_type != Type::Bool {
-                    return Err(TypeError {
-                        message: "Condition in if expression must be boolean".to_string(),
-                    });
-                }
-                for expr in &ifexpr.then_block {
-                    self.check_expr(expr)?;
+                    return Err(type_error(
+                        "Condition in if expression must be boolean".to_string(),
+                    ));
                 }
+                let narrowing = self.narrowing(&ifexpr.condition);
+                let narrowed = |in_then: bool| narrowing.as_ref().filter(|n| n.in_then == in_then);
+                self.check_branch(&ifexpr.then_block, narrowed(true))?;
                 if let Some(else_block) = &ifexpr.else_block {
-                    for expr in else_block {
-                        self.check_expr(expr)?;
-                    }
+                    self.check_branch(else_block, narrowed(false))?;
                 }
                 Ok(Type::None)
             }
+            Expr::TernaryExpr(ternary) => {
+                let cond_type = self.check_expr(&ternary.condition)?;
+                if cond_type != Type::Bool {
+                    return Err(type_error(
+                        "Condition in conditional expression must be boolean".to_string(),
+                    ));
+                }
+                let then_type = self.check_expr(&ternary.then_value)?;
+                let else_type = self.check_expr(&ternary.else_value)?;
+                unify(&then_type, &else_type).ok_or(type_error(format!(
+                    "Branches of conditional expression have mismatched types: {} vs {}",
+                    then_type, else_type
+                )))
+            }
             Expr::RepExpr(repexpr) => {
                 let count_type = self.check_expr(&repexpr.num_iterations)?;
                 if count_type != Type::Int {
-                    return Err(TypeError {
-                        message: "rep count must be int".to_string(),
-                    });
+                    return Err(type_error("rep count must be int".to_string()));
                 }
-                for expr in &repexpr.body {
-                    self.check_expr(expr)?;
+                self.check_block(&repexpr.body)?;
+                Ok(Type::None)
+            }
+            Expr::TryExpr(try_expr) => {
+                let value_type = self.check_expr(&try_expr.value)?;
+                let fallback_type = self.check_expr(&try_expr.fallback)?;
+                if try_expr.fallback_panics() {
+                    return Ok(value_type);
                 }
+                unify(&value_type, &fallback_type).ok_or(type_error(format!(
+                    "try value and fallback have mismatched types: {} vs {}",
+                    value_type, fallback_type
+                )))
+            }
+            Expr::RangeExpr(range) => {
+                for bound in [&range.start, &range.end] {
+                    let bound_type = self.check_expr(bound)?;
+                    if bound_type != Type::Int {
+                        return Err(type_error(format!(
+                            "Range bounds must be int, got {}",
+                            bound_type
+                        )));
+                    }
+                }
+                Ok(Type::Range)
+            }
+            Expr::ForExpr(for_expr) => {
+                let elem_type = self.loop_element_type(&for_expr.iterable)?;
+                let target_types = match (for_expr.targets.len(), elem_type) {
+                    (1, elem_type) => vec![elem_type],
+                    (n, Type::Tuple(elems)) if elems.len() == n => elems,
+                    (2, Type::Pair(first, second)) => vec![*first, *second],
+                    (n, other) => {
+                        return Err(type_error(format!(
+                            "Cannot unpack loop element {} into {} variables",
+                            other, n
+                        )))
+                    }
+                };
+                // loop variables only live in the body
+                let scope = self.enter_scope();
+                for (target, t) in for_expr.targets.iter().zip(target_types) {
+                    let capture = self.capture_in(&for_expr.iterable, &t);
+                    self.symbol_table.insert(target.value.clone(), t);
+                    self.set_mutable(&target.value, false);
+                    self.bind(&target.value, capture);
+                }
+                let body_result = self.check_block(&for_expr.body);
+                self.exit_scope(scope);
+                body_result?;
                 Ok(Type::None)
             }
             Expr::FunctionDef(func) => {
-                // Save current symbol table
-                let old_table = self.symbol_table.clone();
+                self.check_type_exists(&func.get_type())?;
+                // Declare the function before checking its body so it can
+                // call itself
+                self.add_overload(&func.name.value, &func.get_type());
+                self.symbol_table
+                    .insert(func.name.value.clone(), func.get_type());
+                self.bind_function(&func.name.value);
+                // The body sees everything declared before the function,
+                // captured by reference; its own locals and nested functions
+                // are dropped when it ends.
+                let scope = self.enter_scope();
                 // Add arguments to symbol table
                 for arg in &func.args {
                     self.symbol_table
-                        .insert(arg.value.value.clone(), arg.associated_type.clone());
+                        .insert(arg.value.value.clone(), arg.associated_type.without_ref());
+                    self.set_mutable(&arg.value.value, true);
+                    self.bind(&arg.value.value, None);
                 }
-                for expr in &func.body {
-                    self.check_expr(expr)?;
+                self.functions.push(func.name.value.clone());
+                self.return_types.push(func.return_type.clone());
+                let body_result = self.check_block(&func.body);
+                self.return_types.pop();
+                self.functions.pop();
+                self.exit_scope(scope);
+                body_result?;
+                // a value must be returned on every path; raw C++ at the end
+                // is trusted to return one itself
+                if let Some(return_type) = &func.return_type {
+                    let returns = func.body.iter().any(terminates)
+                        || matches!(func.body.last(), Some(Expr::CppBlock(_)));
+                    if !returns {
+                        return Err(type_error(format!(
+                            "Missing return in '{}': the end of its body can be reached without returning {}",
+                            func.name.value, return_type
+                        )));
+                    }
+                }
+                Ok(func.get_type())
+            }
+            Expr::ReturnExpr(ret) => {
+                let value_type = self.check_expr(&ret.value)?;
+                // a nested function must not outlive the call that made it,
+                // on its own or inside an option, a tuple and the like
+                if let Some(capture) = self.capture_in(&ret.value, &value_type) {
+                    if capture.depth >= self.functions.len() {
+                        let returned = match ret.value.as_ref() {
+                            Expr::Identifier(id) if id.value == capture.function => {
+                                format!("nested function '{}'", capture.function)
+                            }
+                            Expr::Identifier(id) => format!(
+                                "'{}', which holds nested function '{}',",
+                                id.value, capture.function
+                            ),
+                            _ => format!("a value holding nested function '{}'", capture.function),
+                        };
+                        return Err(type_error(format!(
+                            "Cannot return {} from '{}': '{}' is local to '{}' and can't outlive its call",
+                            returned,
+                            self.functions.last().cloned().unwrap_or_default(),
+                            capture.function,
+                            capture.owner
+                        )));
+                    }
+                }
+                let bare = matches!(ret.value.as_ref(), Expr::NoneExpr(_));
+                match self.return_types.last() {
+                    None => Err(type_error("'return' outside of a function".to_string())),
+                    Some(None) if bare => Ok(Type::None),
+                    Some(Some(return_type)) if bare => Err(type_error(format!(
+                        "Missing return value: the function returns {}",
+                        return_type
+                    ))),
+                    Some(None) => Err(type_error(format!(
+                        "Cannot return {} from a function without a return type",
+                        value_type
+                    ))),
+                    Some(Some(return_type)) => {
+                        unify(return_type, &value_type).ok_or(type_error(format!(
+                            "Return type mismatch: expected {}, got {}",
+                            return_type, value_type
+                        )))
+                    }
                 }
-                // Restore symbol table
-                self.symbol_table = old_table;
-                Ok(Type::FunctionType(
-                    func.args
-                        .iter()
-                        .map(|a| a.associated_type.clone())
-                        .collect(),
-                    Box::new(None),
-                ))
             }
-            Expr::ReturnExpr(ret) => self.check_expr(&ret.value),
             Expr::UnOp(unop) => {
                 let arg_type = self.check_expr(&unop.arg)?;
-                match unop.op.as_str() {
-                    "-" => {
-                        if arg_type == Type::Int || arg_type == Type::Float {
+                match unop.op {
+                    UnOp::Neg => {
+                        if matches!(
+                            arg_type,
+                            Type::Int | Type::I128 | Type::Float | Type::ModInt(_)
+                        ) {
+                            Ok(arg_type)
+                        } else {
+                            Err(type_error(format!(
+                                "Unary '-' not supported for type {}",
+                                arg_type
+                            )))
+                        }
+                    }
+                    UnOp::BitNot => {
+                        if matches!(arg_type, Type::Int | Type::I128) {
                             Ok(arg_type)
                         } else {
-                            Err(TypeError {
-                                message: format!("Unary '-' not supported for type {:?}", arg_type),
-                            })
+                            Err(type_error(format!(
+                                "Unary '~' not supported for type {}",
+                                arg_type
+                            )))
                         }
                     }
-                    "!" => {
+                    UnOp::Not => {
                         if arg_type == Type::Bool {
                             Ok(Type::Bool)
                         } else {
-                            Err(TypeError {
-                                message: format!("Unary '!' not supported for type {:?}", arg_type),
-                            })
+                            Err(type_error(format!(
+                                "Unary '!' not supported for type {}",
+                                arg_type
+                            )))
                         }
                     }
-                    _ => Err(TypeError {
-                        message: format!("Unknown unary operator '{}'", unop.op),
-                    }),
                 }
             }
             Expr::NoneExpr(_) => Ok(Type::None),
-            Expr::MethodCallExpr(_) => Err(TypeError {
-                message: "Method calls not supported in type checker yet".to_string(),
+            Expr::NoneLiteral(_) => Ok(Type::Option(Box::new(Type::None))),
+            // opaque to the typechecker
+            Expr::CppBlock(_) => Ok(Type::None),
+            Expr::ExternDef(def) => {
+                let name = &def.name.value;
+                if self.symbol_table.contains_key(name) {
+                    return Err(type_error(format!("'{}' is already defined", name)));
+                }
+                for t in def.args.iter().map(|a| &a.associated_type) {
+                    self.check_type_exists(t)?;
+                }
+                if let Some(t) = &def.return_type {
+                    self.check_type_exists(t)?;
+                }
+                self.symbol_table.insert(name.clone(), def.get_type());
+                Ok(def.get_type())
+            }
+            // resolve::load_program replaces imports with the imported code
+            Expr::ImportExpr(import) => Err(type_error(format!(
+                "Unresolved import '{}': imports need the program to be loaded from a file",
+                import.module.value
+            ))),
+            Expr::MethodCallExpr(call) => self.check_call(call),
+        }
+    }
+
+    // `format(template, args...)`: the template is a string literal so its
+    // placeholders can be matched against the arguments
+    fn check_format(&mut self, args: &[Expr]) -> TypeResult<Type> {
+        let Some(Expr::String(template)) = args.first() else {
+            return Err(type_error(
+                "format expects a string literal as its first argument".to_string(),
+            ));
+        };
+        let placeholders = parse_format(&template.value)
+            .map_err(type_error)?
+            .into_iter()
+            .filter_map(|part| match part {
+                FormatPart::Arg { precision } => Some(precision),
+                FormatPart::Text(_) => None,
+            })
+            .collect::<Vec<_>>();
+        if placeholders.len() != args.len() - 1 {
+            return Err(type_error(format!(
+                "format string has {} placeholders but {} values were given",
+                placeholders.len(),
+                args.len() - 1
+            )));
+        }
+        for (precision, arg) in placeholders.iter().zip(&args[1..]) {
+            match (precision, self.check_expr(arg)?) {
+                (Some(_), Type::Int | Type::Float) => {}
+                (Some(_), other) => {
+                    return Err(type_error(format!(
+                        "A precision needs an int or float, got {}",
+                        other
+                    )))
+                }
+                (None, t) => require(&t, Capability::Printable, "formatted values")?,
+            }
+        }
+        Ok(Type::String)
+    }
+
+    // Type of `val name = value` without an annotation. The value's type is
+    // used as is unless part of it is unknown, as in `none`.
+    fn infer_binding_type(&self, name: &str, value_type: Type) -> TypeResult<Type> {
+        if is_fully_known(&value_type) {
+            Ok(value_type)
+        } else {
+            Err(type_error(format!(
+                "Cannot infer the type of '{}' from {}; add a type annotation",
+                name, value_type
+            )))
+        }
+    }
+
+    fn check_call(&mut self, call: &MethodCallExpr) -> TypeResult<Type> {
+        let name = call.method_name.value.as_str();
+        if let Some(receiver) = &call.receiver {
+            return self.check_method(receiver, call);
+        }
+        if let Some(fields) = self.struct_table.get(name).cloned() {
+            return self.check_struct_constructor(name, &fields, &call.args);
+        }
+        match name {
+            "int" => match self.check_args(name, &call.args)? {
+                [Type::Int
+                | Type::I128
+                | Type::Float
+                | Type::Bool
+                | Type::String
+                | Type::ModInt(_)] => Ok(Type::Int),
+                [other] => Err(type_error(format!("Cannot convert {} to int", other))),
+            },
+            "float" => match self.check_args(name, &call.args)? {
+                [Type::Int | Type::I128 | Type::Float | Type::String] => Ok(Type::Float),
+                [other] => Err(type_error(format!("Cannot convert {} to float", other))),
+            },
+            "str" => match self.check_args(name, &call.args)? {
+                [t] => {
+                    require(&t, Capability::Printable, "arguments of str")?;
+                    Ok(Type::String)
+                }
+            },
+            "format" => self.check_format(&call.args),
+            // none unless the whole string (give or take whitespace) is a number
+            "parse_int" | "parse_float" => match self.check_args(name, &call.args)? {
+                [Type::String] if name == "parse_int" => Ok(Type::Option(Box::new(Type::Int))),
+                [Type::String] => Ok(Type::Option(Box::new(Type::Float))),
+                [other] => Err(type_error(format!(
+                    "{} expects a string, got {}",
+                    name, other
+                ))),
+            },
+            "some" => {
+                let [value] = self.check_args(name, &call.args)?;
+                Ok(Type::Option(Box::new(value)))
+            }
+            "unwrap" => match self.check_args(name, &call.args)? {
+                [Type::Option(inner)] if *inner != Type::None => Ok(*inner),
+                [other] => Err(type_error(format!(
+                    "unwrap expects an option, got {}",
+                    other
+                ))),
+            },
+            "len" => match self.check_args(name, &call.args)? {
+                [Type::List(_)
+                | Type::String
+                | Type::Map(..)
+                | Type::Set(_)
+                | Type::Heap(..)
+                | Type::Stack(_)
+                | Type::Queue(_)
+                | Type::Deque(_)
+                | Type::Grid(_)] => Ok(Type::Int),
+                [other] => Err(type_error(format!(
+                    "len expects a list, string, map or set, got {}",
+                    other
+                ))),
+            },
+            // sorts in place, by the elements themselves, a key, or a comparator
+            "sort" | "sort_by" => self.check_sort(name, &call.args),
+            // sorts, then drops repeated elements, in place
+            "dedup" => {
+                let [list] = self.check_args(name, &call.args)?;
+                match list {
+                    Type::List(elem) if is_orderable(&elem) => {}
+                    other => {
+                        return Err(type_error(format!(
+                            "dedup expects a list of comparable values, got {}",
+                            other
+                        )))
+                    }
+                }
+                if !self.is_mutable_place(&call.args[0]) {
+                    return Err(type_error(
+                        "dedup works in place, so its argument must be a var".to_string(),
+                    ));
+                }
+                Ok(Type::None)
+            }
+            "reverse" | "reversed" => {
+                let [seq] = self.check_args(name, &call.args)?;
+                if !matches!(seq, Type::List(_) | Type::String) {
+                    return Err(type_error(format!(
+                        "{} expects a list or string, got {}",
+                        name, seq
+                    )));
+                }
+                if name == "reversed" {
+                    return Ok(seq);
+                }
+                if !self.is_mutable_place(&call.args[0]) {
+                    return Err(type_error(
+                        "reverse works in place, so its argument must be a var".to_string(),
+                    ));
+                }
+                Ok(Type::None)
+            }
+            "pow" => match self.check_args(name, &call.args)? {
+                [Type::Int, Type::Int] => Ok(Type::Int),
+                [Type::ModInt(m), Type::Int] => Ok(Type::ModInt(m)),
+                [base, exp] => Err(type_error(format!(
+                    "pow expects two ints, got {} and {}",
+                    base, exp
+                ))),
+            },
+            "modpow" => match self.check_args(name, &call.args)? {
+                [Type::Int, Type::Int, Type::Int] => Ok(Type::Int),
+                [base, exp, modulus] => Err(type_error(format!(
+                    "modpow expects three ints, got {}, {} and {}",
+                    base, exp, modulus
+                ))),
+            },
+            // real-valued math; floor, ceil and round land back on ints
+            "sqrt" | "log" | "floor" | "ceil" | "round" => {
+                match self.check_args(name, &call.args)? {
+                    [Type::Int | Type::Float] if matches!(name, "sqrt" | "log") => Ok(Type::Float),
+                    [Type::Int | Type::Float] => Ok(Type::Int),
+                    [other] => Err(type_error(format!(
+                        "{} expects an int or float, got {}",
+                        name, other
+                    ))),
+                }
+            }
+            "isqrt" => match self.check_args(name, &call.args)? {
+                [Type::Int] => Ok(Type::Int),
+                [other] => Err(type_error(format!("isqrt expects an int, got {}", other))),
+            },
+            // milliseconds since the program started
+            "elapsed_ms" => {
+                let [] = self.check_args(name, &call.args)?;
+                Ok(Type::Int)
+            }
+            "rand_int" => match self.check_args(name, &call.args)? {
+                [Type::Int, Type::Int] => Ok(Type::Int),
+                [lo, hi] => Err(type_error(format!(
+                    "rand_int expects two ints, got {} and {}",
+                    lo, hi
+                ))),
+            },
+            "shuffle" => match self.check_args(name, &call.args)? {
+                [Type::List(_)] if self.is_mutable_place(&call.args[0]) => Ok(Type::None),
+                [Type::List(_)] => Err(type_error(
+                    "shuffle works in place, so its argument must be a var".to_string(),
+                )),
+                [other] => Err(type_error(format!("shuffle expects a list, got {}", other))),
+            },
+            "join" => match self.check_args(name, &call.args)? {
+                [Type::List(elem), Type::String] if *elem == Type::String => Ok(Type::String),
+                [parts, sep] => Err(type_error(format!(
+                    "join expects a list of strings and a separator, got {} and {}",
+                    parts, sep
+                ))),
+            },
+            "abs" => match self.check_args(name, &call.args)? {
+                [t @ (Type::Int | Type::Float)] => Ok(t),
+                [other] => Err(type_error(format!(
+                    "abs expects an int or float, got {}",
+                    other
+                ))),
+            },
+            // of two numbers, or of the elements of a list
+            "min" | "max" => match call.args.len() {
+                1 => match self.check_args(name, &call.args)? {
+                    [Type::List(elem)] if is_orderable(&elem) => Ok(*elem),
+                    [other] => Err(type_error(format!(
+                        "{} expects a list of comparable values, got {}",
+                        name, other
+                    ))),
+                },
+                _ => match self.check_args(name, &call.args)? {
+                    [Type::Int, Type::Int] => Ok(Type::Int),
+                    [Type::Float, Type::Float] => Ok(Type::Float),
+                    [a, b] => Err(type_error(format!(
+                        "{} expects two ints or two floats, got {} and {}",
+                        name, a, b
+                    ))),
+                },
+            },
+            // index of the first element >= v (or > v) in a sorted list
+            "lower_bound" | "upper_bound" => match self.check_args(name, &call.args)? {
+                [Type::List(elem), value]
+                    if is_orderable(&elem) && unify(&elem, &value).is_some() =>
+                {
+                    Ok(Type::Int)
+                }
+                [list, value] => Err(type_error(format!(
+                    "{} expects a sorted list of comparable values and an element, got {} and {}",
+                    name, list, value
+                ))),
+            },
+            // `bitset(n)` has n bits, all clear
+            "bitset" => match self.check_args(name, &call.args)? {
+                [Type::Int] => Ok(Type::Bitset),
+                [other] => Err(type_error(format!(
+                    "bitset expects an int size, got {}",
+                    other
+                ))),
+            },
+            "pair" => {
+                let [first, second] = self.check_args(name, &call.args)?;
+                Ok(Type::Pair(Box::new(first), Box::new(second)))
+            }
+            "count" => match self.check_args(name, &call.args)? {
+                [Type::List(elem), value] if unify(&elem, &value).is_some() => Ok(Type::Int),
+                [list, value] => Err(type_error(format!(
+                    "count expects a list and an element, got {} and {}",
+                    list, value
+                ))),
+            },
+            // new lists: the elements where pred(x) holds, or f(x) for each x
+            "filter" | "map" => {
+                let [list, func] = self.check_args(name, &call.args)?;
+                let elem_type = match list {
+                    Type::List(elem_type) => *elem_type,
+                    other => {
+                        return Err(type_error(format!(
+                            "{} expects a list, got {}",
+                            name, other
+                        )))
+                    }
+                };
+                match func {
+                    Type::FunctionType(params, ret)
+                        if params.len() == 1 && unify(&params[0], &elem_type).is_some() =>
+                    {
+                        match (name, *ret) {
+                            ("filter", Some(Type::Bool)) => Ok(Type::List(Box::new(elem_type))),
+                            ("map", Some(ret)) if ret != Type::None => {
+                                Ok(Type::List(Box::new(ret)))
+                            }
+                            (_, ret) => Err(type_error(format!(
+                                "{} got a function returning {}",
+                                name,
+                                ret.unwrap_or(Type::None)
+                            ))),
+                        }
+                    }
+                    other => Err(type_error(format!(
+                        "{} expects a function taking {}, got {}",
+                        name, elem_type, other
+                    ))),
+                }
+            }
+            // p[i] is the sum of the first i elements, so p has one more
+            "prefix_sums" => match self.check_args(name, &call.args)? {
+                [Type::List(elem)] if matches!(*elem, Type::Int | Type::Float) => {
+                    Ok(Type::List(elem))
+                }
+                [other] => Err(type_error(format!(
+                    "prefix_sums expects a list of numbers, got {}",
+                    other
+                ))),
+            },
+            // first x in [lo, hi) where pred(x) holds, for a pred that is false
+            // then true; hi if there is none
+            "bsearch" => match self.check_args(name, &call.args)? {
+                [Type::Int, Type::Int, Type::FunctionType(params, ret)]
+                    if params == [Type::Int] && *ret == Some(Type::Bool) =>
+                {
+                    Ok(Type::Int)
+                }
+                [lo, hi, pred] => Err(type_error(format!(
+                    "bsearch expects two ints and a fn(int) -> bool, got {}, {} and {}",
+                    lo, hi, pred
+                ))),
+            },
+            "read_int" => {
+                let [] = self.check_args(name, &call.args)?;
+                Ok(Type::Int)
+            }
+            "read_list" => match self.check_args(name, &call.args)? {
+                [Type::Int] => Ok(Type::List(Box::new(Type::Int))),
+                [other] => Err(type_error(format!(
+                    "read_list expects an int count, got {}",
+                    other
+                ))),
+            },
+            // a whitespace-separated token, or the rest of the input line
+            "read_str" | "read_line" => {
+                let [] = self.check_args(name, &call.args)?;
+                Ok(Type::String)
+            }
+            "input_ints" | "input_floats" | "input_strs" => Err(type_error(format!(
+                "{} can only be looped over with for",
+                name
+            ))),
+            // `panic(msg)` never produces a value
+            "panic" => match self.check_args(name, &call.args)? {
+                [t] => {
+                    require(&t, Capability::Printable, "panic messages")?;
+                    Ok(Type::None)
+                }
+            },
+            "flush" => {
+                let [] = self.check_args(name, &call.args)?;
+                Ok(Type::None)
+            }
+            // ends the program with the given status
+            "exit" => match self.check_args(name, &call.args)? {
+                [Type::Int] => Ok(Type::None),
+                [other] => Err(type_error(format!(
+                    "exit expects an int status, got {}",
+                    other
+                ))),
+            },
+            _ => match self
+                .resolve_overload(name, &call.args)?
+                .or(self.symbol_table.get(name).cloned())
+            {
+                Some(Type::FunctionType(params, return_type)) => {
+                    if params.len() != call.args.len() {
+                        return Err(type_error(format!(
+                            "'{}' expects {} argument(s), got {}",
+                            name,
+                            params.len(),
+                            call.args.len()
+                        )));
+                    }
+                    for (i, (param, arg)) in params.iter().zip(&call.args).enumerate() {
+                        let arg_type = self.check_expr(arg)?;
+                        if unify(&param.without_ref(), &arg_type).is_none() {
+                            return Err(type_error(format!(
+                                "Argument {} of '{}' must be {}, got {}",
+                                i + 1,
+                                name,
+                                param.without_ref(),
+                                arg_type
+                            )));
+                        }
+                    }
+                    // a `ref` parameter needs a variable the callee may write to
+                    for (param, arg) in params.iter().zip(&call.args) {
+                        if matches!(param, Type::Ref(_)) && !self.is_mutable_place(arg) {
+                            return Err(type_error(format!(
+                                "'{}' takes a ref parameter, which needs a mutable variable",
+                                name
+                            )));
+                        }
+                    }
+                    Ok(return_type.unwrap_or(Type::None))
+                }
+                Some(other) => Err(type_error(format!(
+                    "'{}' is not a function, it has type {}",
+                    name, other
+                ))),
+                None => Err(type_error(format!("Undefined function '{}'", name))),
+            },
+        }
+    }
+
+    fn check_sort(&mut self, name: &str, args: &[Expr]) -> TypeResult<Type> {
+        let (list, by) = match (name, args) {
+            ("sort", [list]) => (list, None),
+            ("sort", [list, key]) | ("sort_by", [list, key]) => (list, Some(key)),
+            _ => {
+                return Err(type_error(format!(
+                    "{} expects a list and a function, got {} argument(s)",
+                    name,
+                    args.len()
+                )))
+            }
+        };
+        let elem_type = match self.check_expr(list)? {
+            Type::List(elem_type) => *elem_type,
+            Type::String if by.is_none() => Type::String,
+            other => {
+                return Err(type_error(format!(
+                    "{} expects a list, got {}",
+                    name, other
+                )))
+            }
+        };
+        if !self.is_mutable_place(list) {
+            return Err(type_error(format!(
+                "{} sorts in place, so its argument must be a var",
+                name
+            )));
+        }
+        let Some(by) = by else {
+            require(
+                &elem_type,
+                Capability::Comparable,
+                "elements sorted without a key",
+            )?;
+            return Ok(Type::None);
+        };
+        let takes_elems = |params: &[Type], n: usize| {
+            params.len() == n && params.iter().all(|p| unify(p, &elem_type).is_some())
+        };
+        match self.check_expr(by)? {
+            // key(x) gives the value elements are ordered by
+            Type::FunctionType(params, ret)
+                if name == "sort"
+                    && takes_elems(&params, 1)
+                    && ret.as_ref().as_ref().is_some_and(is_orderable) =>
+            {
+                Ok(Type::None)
+            }
+            // cmp(a, b) is true when a goes before b
+            Type::FunctionType(params, ret)
+                if name == "sort_by" && takes_elems(&params, 2) && *ret == Some(Type::Bool) =>
+            {
+                Ok(Type::None)
+            }
+            other => Err(type_error(if name == "sort" {
+                format!(
+                    "sort key must take {} and return an orderable value, got {}",
+                    elem_type, other
+                )
+            } else {
+                format!(
+                    "sort_by comparator must take two {} and return bool, got {}",
+                    elem_type, other
+                )
+            })),
+        }
+    }
+
+    // `receiver.name(args)`: the methods of lists and strings
+    // Builtin methods are looked up in method_signatures by receiver type
+    fn check_method(&mut self, receiver: &Expr, call: &MethodCallExpr) -> TypeResult<Type> {
+        let receiver_type = self.check_expr(receiver)?;
+        let name = call.method_name.value.as_str();
+        if matches!(receiver_type, Type::Grid(_)) && matches!(name, "neighbors4" | "neighbors8") {
+            return Err(type_error(format!(
+                "{} can only be looped over with for",
+                name
+            )));
+        }
+        let signatures = method_signatures(&receiver_type, name);
+        if signatures.is_empty() {
+            return Err(type_error(format!(
+                "{} has no method '{}'",
+                receiver_type, name
+            )));
+        }
+        let arg_types = call
+            .args
+            .iter()
+            .map(|a| self.check_expr(a))
+            .collect::<TypeResult<Vec<Type>>>()?;
+        let signature = signatures
+            .iter()
+            .find(|signature| {
+                signature.params.len() == arg_types.len()
+                    && signature
+                        .params
+                        .iter()
+                        .zip(&arg_types)
+                        .all(|(param, arg)| unify(param, arg).is_some())
+            })
+            .ok_or_else(|| {
+                type_error(format!(
+                    "{} expects {}, got {}",
+                    name,
+                    signatures
+                        .iter()
+                        .map(|signature| type_list(&signature.params))
+                        .collect::<Vec<_>>()
+                        .join(" or "),
+                    type_list(&arg_types)
+                ))
+            })?;
+        if signature.mutates && !self.is_mutable_place(receiver) {
+            return Err(type_error(format!(
+                "'{}' modifies the {} it is called on, which must be a var",
+                name, receiver_type
+            )));
+        }
+        if let (true, Some(target)) = (signature.mutates, receiver.root_identifier()) {
+            for (arg, arg_type) in call.args.iter().zip(&arg_types) {
+                let capture = self.capture_in(arg, arg_type);
+                self.store(&target.value, capture)?;
+            }
+        }
+        Ok(signature.result.clone())
+    }
+
+    // Type of the elements a for loop visits. `enumerate(xs)` and
+    // `zip(xs, ys, ..)` are only valid here, yielding index/element tuples, as
+    // are `g.neighbors4(r, c)` / `g.neighbors8(r, c)`, yielding (row, col),
+    // and `input_ints()` / `input_floats()` / `input_strs()`, reading tokens
+    // until the end of the input.
+    pub fn loop_element_type(&mut self, iterable: &Expr) -> TypeResult<Type> {
+        if let Expr::MethodCallExpr(call) = iterable {
+            let name = call.method_name.value.as_str();
+            if let (Some(grid), "neighbors4" | "neighbors8") = (&call.receiver, name) {
+                return match (self.check_expr(grid)?, self.check_args(name, &call.args)?) {
+                    (Type::Grid(_), [Type::Int, Type::Int]) => {
+                        Ok(Type::Tuple(vec![Type::Int, Type::Int]))
+                    }
+                    (grid, [r, c]) => Err(type_error(format!(
+                        "{} expects a grid and two ints, got {}, {} and {}",
+                        name, grid, r, c
+                    ))),
+                };
+            }
+            match name {
+                "enumerate" => {
+                    return match self.check_args("enumerate", &call.args)? {
+                        [Type::List(inner)] => Ok(Type::Tuple(vec![Type::Int, *inner])),
+                        [other] => Err(type_error(format!(
+                            "enumerate expects a list, got {}",
+                            other
+                        ))),
+                    };
+                }
+                "zip" => {
+                    if call.args.len() < 2 {
+                        return Err(type_error("zip expects at least 2 lists".to_string()));
+                    }
+                    let mut elems = Vec::new();
+                    for arg in &call.args {
+                        match self.check_expr(arg)? {
+                            Type::List(inner) => elems.push(*inner),
+                            other => {
+                                return Err(type_error(format!("zip expects lists, got {}", other)))
+                            }
+                        }
+                    }
+                    return Ok(Type::Tuple(elems));
+                }
+                "input_ints" | "input_floats" | "input_strs" => {
+                    let [] = self.check_args(name, &call.args)?;
+                    return Ok(match name {
+                        "input_ints" => Type::Int,
+                        "input_floats" => Type::Float,
+                        _ => Type::String,
+                    });
+                }
+                _ => {}
+            }
+        }
+        match self.check_expr(iterable)? {
+            Type::Range => Ok(Type::Int),
+            Type::Set(elem) | Type::Deque(elem) => Ok(*elem),
+            Type::Grid(elem) => Ok(Type::List(elem)),
+            // (key, value) pairs in increasing key order
+            Type::Map(key, value) => Ok(Type::Tuple(vec![*key, *value])),
+            Type::List(inner) => Ok(*inner),
+            other => Err(type_error(format!("Cannot iterate over {}", other))),
+        }
+    }
+
+    // Checks a builtin's arguments, requiring exactly N of them
+    fn check_args<const N: usize>(&mut self, name: &str, args: &[Expr]) -> TypeResult<[Type; N]> {
+        if args.len() != N {
+            return Err(type_error(format!(
+                "{} expects {} argument(s), got {}",
+                name,
+                N,
+                args.len()
+            )));
+        }
+        let types = args
+            .iter()
+            .map(|a| self.check_expr(a))
+            .collect::<TypeResult<Vec<Type>>>()?;
+        Ok(types.try_into().unwrap())
+    }
+
+    fn set_mutable(&mut self, name: &str, mutable: bool) {
+        if mutable {
+            self.immutables.remove(name);
+        } else {
+            self.immutables.insert(name.to_string());
+        }
+    }
+
+    // Records where a variable was declared, and the nested function its
+    // value may hold
+    fn bind(&mut self, name: &str, capture: Option<Capture>) {
+        self.depths.insert(name.to_string(), self.functions.len());
+        match capture {
+            Some(capture) => self.captures.insert(name.to_string(), capture),
+            None => self.captures.remove(name),
+        };
+    }
+
+    // A function defined inside another captures that body's locals; one at
+    // the top level lives as long as the program
+    fn bind_function(&mut self, name: &str) {
+        let capture = self.functions.last().map(|owner| Capture {
+            depth: self.functions.len(),
+            function: name.to_string(),
+            owner: owner.clone(),
+        });
+        self.bind(name, capture);
+    }
+
+    // The innermost nested function a value of type `t` computed by `expr`
+    // may hold, e.g. `add` for `some(add)` or `(add, 1)`
+    fn capture_in(&self, expr: &Expr, t: &Type) -> Option<Capture> {
+        if self.holds_function(t, &mut Vec::new()) {
+            self.reached_capture(expr)
+        } else {
+            None
+        }
+    }
+
+    // The innermost nested function named in the parts of `expr` its value
+    // is built from; a function that is only called doesn't count
+    fn reached_capture(&self, expr: &Expr) -> Option<Capture> {
+        let parts: Vec<&Expr> = match expr {
+            Expr::Identifier(id) => return self.captures.get(&id.value).cloned(),
+            Expr::MethodCallExpr(call) => call
+                .receiver
+                .iter()
+                .map(|receiver| receiver.as_ref())
+                .chain(&call.args)
+                .collect(),
+            Expr::ListExpr(list) => list.elems.iter().collect(),
+            Expr::TupleExpr(tuple) => tuple.elems.iter().collect(),
+            Expr::TernaryExpr(ternary) => vec![&ternary.then_value, &ternary.else_value],
+            Expr::BinOp(binop) => vec![&binop.left, &binop.right],
+            Expr::TryExpr(try_expr) => vec![&try_expr.value, &try_expr.fallback],
+            Expr::IndexExpr(index) => vec![&index.list],
+            Expr::TupleAccessExpr(access) => vec![&access.tuple],
+            Expr::FieldAccessExpr(access) => vec![&access.object],
+            _ => Vec::new(),
+        };
+        parts
+            .into_iter()
+            .filter_map(|part| self.reached_capture(part))
+            .max_by_key(|capture| capture.depth)
+    }
+
+    // Whether a value of type `t` can hold a function; `seen` guards against
+    // structs that contain themselves through a list
+    fn holds_function(&self, t: &Type, seen: &mut Vec<String>) -> bool {
+        match t {
+            Type::FunctionType(..) => true,
+            Type::Option(inner)
+            | Type::List(inner)
+            | Type::Set(inner)
+            | Type::Grid(inner)
+            | Type::Stack(inner)
+            | Type::Queue(inner)
+            | Type::Deque(inner)
+            | Type::Heap(inner, _)
+            | Type::Ref(inner) => self.holds_function(inner, seen),
+            Type::Pair(a, b) | Type::Map(a, b) => {
+                self.holds_function(a, seen) || self.holds_function(b, seen)
+            }
+            Type::Tuple(elems) => elems.iter().any(|elem| self.holds_function(elem, seen)),
+            Type::Struct(name) if !seen.contains(name) => {
+                seen.push(name.clone());
+                self.struct_table.get(name).is_some_and(|fields| {
+                    fields
+                        .iter()
+                        .any(|field| self.holds_function(&field.associated_type, seen))
+                })
+            }
+            _ => false,
+        }
+    }
+
+    // Stores a value holding `capture` into the variable `target` or a part
+    // of it. A variable declared outside the defining body would outlive it.
+    fn store(&mut self, target: &str, capture: Option<Capture>) -> TypeResult<()> {
+        let Some(capture) = capture else {
+            return Ok(());
+        };
+        if self.depths.get(target).copied().unwrap_or(0) < capture.depth {
+            return Err(type_error(format!(
+                "Cannot store nested function '{}' in '{}', declared outside '{}': '{}' is local to '{}' and can't outlive its call",
+                capture.function, target, capture.owner, capture.function, capture.owner
+            )));
+        }
+        let deeper = match self.captures.get(target) {
+            Some(held) => held.depth < capture.depth,
+            None => true,
+        };
+        if deeper {
+            self.captures.insert(target.to_string(), capture);
+        }
+        Ok(())
+    }
+
+    // A function defined while another function of the same name is visible
+    // overloads it if their parameter types differ, and replaces it if not
+    fn add_overload(&mut self, name: &str, signature: &Type) {
+        let mut signatures = match self.symbol_table.get(name) {
+            Some(existing @ Type::FunctionType(..)) => self
+                .overloads
+                .remove(name)
+                .unwrap_or_else(|| vec![existing.clone()]),
+            _ => {
+                self.overloads.remove(name);
+                return;
+            }
+        };
+        signatures.retain(|other| param_types(other) != param_types(signature));
+        signatures.push(signature.clone());
+        if signatures.len() > 1 {
+            self.overloads.insert(name.to_string(), signatures);
+        }
+    }
+
+    // The signature of the overload of `name` that a call with `args` goes
+    // to, or None if `name` is not overloaded
+    pub fn resolve_overload(&mut self, name: &str, args: &[Expr]) -> TypeResult<Option<Type>> {
+        let Some(signatures) = self.overloads.get(name).cloned() else {
+            return Ok(None);
+        };
+        // a later variable of the same name hides the functions
+        if !signatures.contains(self.symbol_table.get(name).unwrap_or(&Type::None)) {
+            return Ok(None);
+        }
+        let arg_types = args
+            .iter()
+            .map(|arg| self.check_expr(arg))
+            .collect::<TypeResult<Vec<Type>>>()?;
+        let matching = signatures
+            .into_iter()
+            .filter(|signature| {
+                let params = param_types(signature);
+                params.len() == arg_types.len()
+                    && params
+                        .iter()
+                        .zip(&arg_types)
+                        .all(|(param, arg)| unify(param, arg).is_some())
+            })
+            .collect::<Vec<Type>>();
+        match matching.as_slice() {
+            [signature] => Ok(Some(signature.clone())),
+            [] => Err(type_error(format!(
+                "No overload of '{}' takes arguments {}",
+                name,
+                type_list(&arg_types)
+            ))),
+            _ => Err(type_error(format!(
+                "Call to '{}' with arguments {} matches several overloads",
+                name,
+                type_list(&arg_types)
+            ))),
+        }
+    }
+
+    // `x != none` narrows the option `x` to its inner type in the then
+    // branch, and `x == none` in the else branch. Only variables that can't
+    // be assigned are narrowed, so the value can't go away mid-branch.
+    pub fn narrowing(&self, condition: &Expr) -> Option<Narrowing> {
+        let Expr::BinOp(binop) = condition else {
+            return None;
+        };
+        let name = match (binop.left.as_ref(), binop.right.as_ref()) {
+            (Expr::Identifier(id), Expr::NoneLiteral(_))
+            | (Expr::NoneLiteral(_), Expr::Identifier(id)) => &id.value,
+            _ => return None,
+        };
+        if !self.immutables.contains(name) || self.constants.contains_key(name) {
+            return None;
+        }
+        match self.symbol_table.get(name)? {
+            Type::Option(inner) if **inner != Type::None => Some(Narrowing {
+                name: name.clone(),
+                inner: inner.as_ref().clone(),
+                in_then: match binop.op {
+                    BinOp::Ne => true,
+                    BinOp::Eq => false,
+                    _ => return None,
+                },
             }),
+            _ => None,
+        }
+    }
+
+    // Whether evaluating `expr` only computes a value: it reads no input,
+    // prints nothing, assigns nothing and calls no user function, which could
+    // do any of those. Failing, like unwrap() of none, doesn't count.
+    pub fn is_pure(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Integer(_)
+            | Expr::Float(_)
+            | Expr::Boolean(_)
+            | Expr::String(_)
+            | Expr::Identifier(_)
+            | Expr::NoneLiteral(_)
+            | Expr::EmptyCollectionExpr(_) => true,
+            Expr::FString(fstring) => fstring.parts.iter().all(|part| match part {
+                FStringPart::Expr(expr) => self.is_pure(expr),
+                FStringPart::Text(_) => true,
+            }),
+            Expr::MethodCallExpr(call) => {
+                if !call.args.iter().all(|arg| self.is_pure(arg)) {
+                    return false;
+                }
+                let name = call.method_name.value.as_str();
+                match &call.receiver {
+                    Some(receiver) => {
+                        self.is_pure(receiver)
+                            && match self.check_expr(receiver) {
+                                Ok(receiver_type) => method_signatures(&receiver_type, name)
+                                    .iter()
+                                    .all(|signature| !signature.mutates),
+                                Err(_) => false,
+                            }
+                    }
+                    None => {
+                        self.struct_table.contains_key(name)
+                            || matches!(
+                                name,
+                                "int"
+                                    | "float"
+                                    | "str"
+                                    | "format"
+                                    | "parse_int"
+                                    | "parse_float"
+                                    | "some"
+                                    | "unwrap"
+                                    | "len"
+                                    | "reversed"
+                                    | "pow"
+                                    | "modpow"
+                                    | "sqrt"
+                                    | "log"
+                                    | "floor"
+                                    | "ceil"
+                                    | "round"
+                                    | "isqrt"
+                                    | "join"
+                                    | "abs"
+                                    | "min"
+                                    | "max"
+                                    | "lower_bound"
+                                    | "upper_bound"
+                                    | "bitset"
+                                    | "pair"
+                                    | "count"
+                                    | "prefix_sums"
+                            )
+                    }
+                }
+            }
+            Expr::TernaryExpr(ternary) => {
+                self.is_pure(&ternary.condition)
+                    && self.is_pure(&ternary.then_value)
+                    && self.is_pure(&ternary.else_value)
+            }
+            Expr::TryExpr(try_expr) => {
+                self.is_pure(&try_expr.value) && self.is_pure(&try_expr.fallback)
+            }
+            Expr::ConversionExpr(conversion) => self.is_pure(&conversion.value),
+            Expr::ListExpr(list) => list.elems.iter().all(|elem| self.is_pure(elem)),
+            Expr::TupleExpr(tuple) => tuple.elems.iter().all(|elem| self.is_pure(elem)),
+            Expr::TupleAccessExpr(access) => self.is_pure(&access.tuple),
+            Expr::FieldAccessExpr(access) => self.is_pure(&access.object),
+            Expr::IndexExpr(index) => self.is_pure(&index.list) && self.is_pure(&index.index),
+            Expr::BinOp(binop) => self.is_pure(&binop.left) && self.is_pure(&binop.right),
+            Expr::UnOp(unop) => self.is_pure(&unop.arg),
+            Expr::RangeExpr(range) => self.is_pure(&range.start) && self.is_pure(&range.end),
+            Expr::ListInitExpr(init) => {
+                init.dims.iter().all(|dim| self.is_pure(dim)) && self.is_pure(&init.fill)
+            }
+            Expr::GridInitExpr(init) => {
+                self.is_pure(&init.rows)
+                    && self.is_pure(&init.cols)
+                    && init.fill.as_ref().is_none_or(|fill| self.is_pure(fill))
+            }
+            _ => false,
+        }
+    }
+
+    fn check_branch(&mut self, block: &[Expr], narrowing: Option<&Narrowing>) -> TypeResult<()> {
+        let scope = self.enter_scope();
+        if let Some(narrowing) = narrowing {
+            self.symbol_table
+                .insert(narrowing.name.clone(), narrowing.inner.clone());
+        }
+        let result = self.check_block(block);
+        self.exit_scope(scope);
+        result
+    }
+
+    // Whether `expr` names storage that may be written, like `xs` or `p.x`
+    // where `xs` and `p` are `var`s
+    fn is_mutable_place(&self, expr: &Expr) -> bool {
+        match expr.root_identifier() {
+            Some(id) => {
+                !self.immutables.contains(&id.value) && !self.constants.contains_key(&id.value)
+            }
+            None => false,
+        }
+    }
+
+    // Whether an expression can be evaluated at C++ compile time
+    fn is_constant_expr(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Integer(_) | Expr::Float(_) | Expr::Boolean(_) | Expr::String(_) => true,
+            Expr::Identifier(id) => self.constants.contains_key(&id.value),
+            Expr::BinOp(binop) => {
+                self.is_constant_expr(&binop.left) && self.is_constant_expr(&binop.right)
+            }
+            Expr::UnOp(unop) => self.is_constant_expr(&unop.arg),
+            Expr::TernaryExpr(ternary) => {
+                self.is_constant_expr(&ternary.condition)
+                    && self.is_constant_expr(&ternary.then_value)
+                    && self.is_constant_expr(&ternary.else_value)
+            }
+            _ => false,
+        }
+    }
+
+    fn check_type_exists(&self, t: &Type) -> TypeResult<()> {
+        match t {
+            Type::Struct(name) if !self.struct_table.contains_key(name) => {
+                Err(type_error(format!("Undefined type '{}'", name)))
+            }
+            Type::List(inner) | Type::Option(inner) => self.check_type_exists(inner),
+            // maps, sets and heaps keep their elements ordered
+            Type::Map(key, value) => {
+                self.check_type_exists(key)?;
+                self.check_type_exists(value)?;
+                require(key, Capability::Comparable, "map keys")
+            }
+            Type::Set(elem) => {
+                self.check_type_exists(elem)?;
+                require(elem, Capability::Comparable, "set elements")
+            }
+            Type::Heap(elem, _) => {
+                self.check_type_exists(elem)?;
+                require(elem, Capability::Comparable, "heap elements")
+            }
+            Type::Stack(elem) | Type::Queue(elem) | Type::Deque(elem) | Type::Grid(elem) => {
+                self.check_type_exists(elem)
+            }
+            // below 2^62, so a sum of two values can't overflow
+            Type::ModInt(modulus) if !(1..1 << 62).contains(modulus) => Err(type_error(format!(
+                "mint modulus must be between 1 and 2^62, got {}",
+                modulus
+            ))),
+            Type::Tuple(elems) => elems.iter().try_for_each(|e| self.check_type_exists(e)),
+            Type::FunctionType(params, return_type) => {
+                params.iter().try_for_each(|p| self.check_type_exists(p))?;
+                match return_type.as_ref() {
+                    Some(t) => self.check_type_exists(t),
+                    None => Ok(()),
+                }
+            }
+            Type::Ref(inner) => self.check_type_exists(inner),
+            Type::Pair(first, second) => {
+                self.check_type_exists(first)?;
+                self.check_type_exists(second)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_struct_constructor(
+        &mut self,
+        name: &str,
+        fields: &[TypedIdentifier],
+        args: &[Expr],
+    ) -> TypeResult<Type> {
+        if fields.len() != args.len() {
+            return Err(type_error(format!(
+                "Struct '{}' has {} fields but {} values were given",
+                name,
+                fields.len(),
+                args.len()
+            )));
+        }
+        for (field, arg) in fields.iter().zip(args) {
+            let arg_type = self.check_expr(arg)?;
+            if unify(&field.associated_type, &arg_type).is_none() {
+                return Err(type_error(format!(
+                    "Type mismatch for field '{}' of '{}': expected {}, got {}",
+                    field.value.value, name, field.associated_type, arg_type
+                )));
+            }
+        }
+        Ok(Type::Struct(name.to_string()))
+    }
+
+    fn check_binop(&self, op: BinOp, left_type: Type, right_type: Type) -> TypeResult<Type> {
+        // the left side of `??` is an option, so none of the rules below apply
+        if op == BinOp::Coalesce {
+            return check_coalesce(left_type, right_type);
+        }
+        // tuples compare element by element, each pair by the usual rules
+        if let (Type::Tuple(lefts), Type::Tuple(rights)) = (&left_type, &right_type) {
+            if op.is_comparison() {
+                if lefts.len() != rights.len() {
+                    return Err(type_error(format!(
+                        "Cannot compare tuples of {} and {} elements with '{}'",
+                        lefts.len(),
+                        rights.len(),
+                        op
+                    )));
+                }
+                for (i, (left, right)) in lefts.iter().zip(rights).enumerate() {
+                    self.check_binop(op, left.clone(), right.clone())
+                        .map_err(|e| {
+                            type_error(format!("In element {} of the tuples: {}", i, e.message))
+                        })?;
+                }
+                return Ok(Type::Bool);
+            }
+        }
+        // an int operand is converted to the other side's mint
+        let (left_type, right_type) = match (left_type, right_type) {
+            (Type::ModInt(m), Type::Int) | (Type::Int, Type::ModInt(m)) => {
+                (Type::ModInt(m), Type::ModInt(m))
+            }
+            // and an int with an i128 is widened
+            (Type::I128, Type::Int) | (Type::Int, Type::I128) => (Type::I128, Type::I128),
+            // and an int with a float is promoted. Storing an int where a
+            // float is expected still takes an explicit float(x).
+            (Type::Float, Type::Int) | (Type::Int, Type::Float) => (Type::Float, Type::Float),
+            (Type::Bitset, Type::Int) if matches!(op, BinOp::Shl | BinOp::Shr) => {
+                return Ok(Type::Bitset)
+            }
+            types => types,
+        };
+        // any option can be checked against `none`
+        let none = Type::Option(Box::new(Type::None));
+        let against_none = left_type == none || right_type == none;
+        let left_type = unify(&left_type, &right_type).ok_or(type_error(format!(
+            "Type mismatch in binary operation '{}': left is {}, right is {}",
+            op, left_type, right_type
+        )))?;
+        // For now, just return the type if it's int/float/string/bool
+        match op {
+            // union, intersection and difference
+            BinOp::BitOr | BinOp::BitAnd | BinOp::Sub if matches!(left_type, Type::Set(_)) => {
+                Ok(left_type)
+            }
+            // `/` multiplies by the inverse, which needs a prime modulus
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Eq | BinOp::Ne
+                if matches!(left_type, Type::ModInt(_)) =>
+            {
+                Ok(if op.is_comparison() {
+                    Type::Bool
+                } else {
+                    left_type
+                })
+            }
+            BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Eq | BinOp::Ne
+                if left_type == Type::Bitset =>
+            {
+                Ok(if op.is_comparison() {
+                    Type::Bool
+                } else {
+                    left_type
+                })
+            }
+            _ if matches!(left_type, Type::ModInt(_) | Type::Bitset) => Err(type_error(format!(
+                "Operator '{}' not supported for type {}",
+                op, left_type
+            ))),
+            // concatenation
+            BinOp::Add if left_type == Type::String => Ok(left_type),
+            BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                if matches!(left_type, Type::Int | Type::I128 | Type::Float) {
+                    Ok(left_type)
+                } else {
+                    Err(type_error(format!(
+                        "Operator '{}' not supported for type {}",
+                        op, left_type
+                    )))
+                }
+            }
+            // `/` is true division and always produces a float
+            BinOp::Div => {
+                if matches!(left_type, Type::Int | Type::I128 | Type::Float) {
+                    Ok(Type::Float)
+                } else {
+                    Err(type_error(format!(
+                        "Operator '{}' not supported for type {}",
+                        op, left_type
+                    )))
+                }
+            }
+            // `//` is integer division, truncating toward zero like `%`
+            BinOp::IntDiv
+            | BinOp::Rem
+            | BinOp::BitAnd
+            | BinOp::BitOr
+            | BinOp::BitXor
+            | BinOp::Shl
+            | BinOp::Shr => {
+                if matches!(left_type, Type::Int | Type::I128) {
+                    Ok(left_type)
+                } else {
+                    Err(type_error(format!(
+                        "Operator '{}' requires int operands, got {}",
+                        op, left_type
+                    )))
+                }
+            }
+            BinOp::And | BinOp::Or => {
+                if left_type == Type::Bool {
+                    Ok(Type::Bool)
+                } else {
+                    Err(type_error(format!(
+                        "Operator '{}' requires bool operands, got {}",
+                        op, left_type
+                    )))
+                }
+            }
+            BinOp::Eq | BinOp::Ne if against_none => Ok(Type::Bool),
+            BinOp::Eq | BinOp::Ne => {
+                require(&left_type, Capability::Equatable, "operands of '=='")?;
+                Ok(Type::Bool)
+            }
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => {
+                require(&left_type, Capability::Comparable, "operands of '<'")?;
+                Ok(Type::Bool)
+            }
+            BinOp::Coalesce => unreachable!("'??' is checked above"),
+        }
+    }
+}
+
+// A function type's parameters as the caller sees them, without `ref`
+pub fn param_types(signature: &Type) -> Vec<Type> {
+    match signature {
+        Type::FunctionType(params, _) => params.iter().map(Type::without_ref).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// `x ?? default` unwraps `x` when it holds a value. The default either has
+// the inner type, giving a plain value, or is itself an option of that type.
+fn check_coalesce(left_type: Type, right_type: Type) -> TypeResult<Type> {
+    let inner = match &left_type {
+        Type::Option(inner) => inner.as_ref().clone(),
+        other => {
+            return Err(type_error(format!(
+                "Left side of '??' must be an option, got {}",
+                other
+            )))
+        }
+    };
+    if matches!(right_type, Type::Option(_)) {
+        if let Some(result) = unify(&left_type, &right_type) {
+            return Ok(result);
+        }
+    } else if let Some(result) = unify(&inner, &right_type) {
+        return Ok(result);
+    }
+    Err(type_error(format!(
+        "Default of '??' must match {}, got {}",
+        inner, right_type
+    )))
+}
+
+// Returns the common type of two types if a value of one can stand in for the
+// other. `none` has type `Option(None)` and fits any option.
+pub fn unify(a: &Type, b: &Type) -> Option<Type> {
+    match (a, b) {
+        (Type::Option(x), Type::Option(y)) if **x == Type::None => Some(Type::Option(y.clone())),
+        (Type::Option(x), Type::Option(y)) if **y == Type::None => Some(Type::Option(x.clone())),
+        (Type::Option(x), Type::Option(y)) => Some(Type::Option(Box::new(unify(x, y)?))),
+        (Type::List(x), Type::List(y)) => Some(Type::List(Box::new(unify(x, y)?))),
+        (Type::Pair(a1, b1), Type::Pair(a2, b2)) => Some(Type::Pair(
+            Box::new(unify(a1, a2)?),
+            Box::new(unify(b1, b2)?),
+        )),
+        (Type::Set(x), Type::Set(y)) => Some(Type::Set(Box::new(unify(x, y)?))),
+        (Type::Stack(x), Type::Stack(y)) => Some(Type::Stack(Box::new(unify(x, y)?))),
+        (Type::Queue(x), Type::Queue(y)) => Some(Type::Queue(Box::new(unify(x, y)?))),
+        (Type::Deque(x), Type::Deque(y)) => Some(Type::Deque(Box::new(unify(x, y)?))),
+        (Type::Grid(x), Type::Grid(y)) => Some(Type::Grid(Box::new(unify(x, y)?))),
+        (Type::Heap(x, x_min), Type::Heap(y, y_min)) if x_min == y_min => {
+            Some(Type::Heap(Box::new(unify(x, y)?), *x_min))
+        }
+        (Type::Map(k1, v1), Type::Map(k2, v2)) => Some(Type::Map(
+            Box::new(unify(k1, k2)?),
+            Box::new(unify(v1, v2)?),
+        )),
+        (Type::Tuple(xs), Type::Tuple(ys)) if xs.len() == ys.len() => Some(Type::Tuple(
+            xs.iter()
+                .zip(ys)
+                .map(|(x, y)| unify(x, y))
+                .collect::<Option<Vec<Type>>>()?,
+        )),
+        _ if a == b => Some(a.clone()),
+        _ => None,
+    }
+}
+
+// What a builtin method takes and gives
+pub struct MethodSignature {
+    pub params: Vec<Type>,
+    pub result: Type,
+    // whether it changes the receiver, which must then be a var
+    pub mutates: bool,
+}
+
+// The overloads of builtin method `name` on a `receiver`, none if it has no
+// such method
+pub fn method_signatures(receiver: &Type, name: &str) -> Vec<MethodSignature> {
+    use Type::*;
+    let sig = |params: &[Type], result: Type| MethodSignature {
+        params: params.to_vec(),
+        result,
+        mutates: false,
+    };
+    let mutator = |params: &[Type], result: Type| MethodSignature {
+        mutates: true,
+        ..sig(params, result)
+    };
+    let list = |t: Type| List(Box::new(t));
+    let signature = match (receiver, name) {
+        (List(elem), "push") => mutator(&[(**elem).clone()], None),
+        // pop removes and returns the last element
+        (List(elem), "pop") => mutator(&[], (**elem).clone()),
+        (List(elem), "insert") => mutator(&[Int, (**elem).clone()], None),
+        (List(_), "remove") => mutator(&[Int], None),
+        (List(_) | String, "len") => sig(&[], Int),
+        // on a separator, or on runs of whitespace without one
+        (String, "split") => {
+            return vec![sig(&[], list(String)), sig(&[String], list(String))];
+        }
+        // copies with ASCII letters converted, or surrounding whitespace removed
+        (String, "to_upper" | "to_lower" | "trim") => sig(&[], String),
+        // the index of the first match, or -1
+        (String, "find") => sig(&[String], Int),
+        (String, "contains") => sig(&[String], Bool),
+        (String, "replace") => sig(&[String, String], String),
+        // the value for a key, or none when it is missing
+        (Map(key, value), "get") => sig(&[(**key).clone()], Option(value.clone())),
+        // sets the value for a key, replacing any earlier one
+        (Map(key, value), "insert") => mutator(&[(**key).clone(), (**value).clone()], None),
+        (Map(key, _), "contains") => sig(&[(**key).clone()], Bool),
+        // in increasing key order
+        (Map(key, _), "keys") => sig(&[], List(key.clone())),
+        (Map(_, value), "values") => sig(&[], List(value.clone())),
+        (Set(elem), "insert" | "erase") => mutator(&[(**elem).clone()], None),
+        (Set(elem), "contains") => sig(&[(**elem).clone()], Bool),
+        (Heap(elem, _) | Stack(elem) | Queue(elem), "push") => mutator(&[(**elem).clone()], None),
+        (Deque(elem), "push_back" | "push_front") => mutator(&[(**elem).clone()], None),
+        // pops remove and return the element
+        (Heap(elem, _) | Stack(elem) | Queue(elem), "pop")
+        | (Deque(elem), "pop_back" | "pop_front") => mutator(&[], (**elem).clone()),
+        (Heap(elem, _) | Stack(elem), "top") | (Queue(elem) | Deque(elem), "front" | "back") => {
+            sig(&[], (**elem).clone())
+        }
+        (Set(_) | Heap(..) | Stack(_) | Queue(_) | Deque(_) | Bitset, "size") => sig(&[], Int),
+        (Bitset, "set" | "reset" | "flip") => mutator(&[Int], None),
+        (Bitset, "test") => sig(&[Int], Bool),
+        // bits set
+        (Bitset, "count") => sig(&[], Int),
+        (Grid(_), "in_bounds") => sig(&[Int, Int], Bool),
+        (Grid(_), "rows" | "cols") => sig(&[], Int),
+        _ => return vec![],
+    };
+    vec![signature]
+}
+
+// Whether `t` names a concrete type, which `none` or a call returning nothing
+// does not
+fn is_fully_known(t: &Type) -> bool {
+    match t {
+        Type::None => false,
+        Type::Option(inner)
+        | Type::List(inner)
+        | Type::Set(inner)
+        | Type::Grid(inner)
+        | Type::Stack(inner)
+        | Type::Queue(inner)
+        | Type::Deque(inner)
+        | Type::Heap(inner, _) => is_fully_known(inner),
+        Type::Pair(a, b) | Type::Map(a, b) => is_fully_known(a) && is_fully_known(b),
+        Type::Tuple(elems) => elems.iter().all(is_fully_known),
+        _ => true,
+    }
+}
+
+// What generic operations need of a type, so a type lacking it is reported
+// by the checker instead of failing in the C++ compiler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    // print, format, str and debug
+    Printable,
+    // `==` and `!=`
+    Equatable,
+    // `<` and friends, sorting, and keys of maps, sets and heaps
+    Comparable,
+}
+
+impl Capability {
+    pub fn holds_for(self, t: &Type) -> bool {
+        match self {
+            Capability::Printable => is_printable(t),
+            Capability::Equatable => is_equatable(t),
+            Capability::Comparable => is_orderable(t),
         }
     }
 }
 
-fn main() {
-    let prog = parser::parse_program(&src).expect("Failed to parse program");
-    let prog = prog.Program().unwrap();
+// Argument types as a call would list them, e.g. "(int, string)"
+fn type_list(types: &[Type]) -> String {
+    let types = types.iter().map(Type::to_string).collect::<Vec<_>>();
+    format!("({})", types.join(", "))
+}
+
+// e.g. "list<fn(int)> is not Comparable, as map keys must be"
+fn require(t: &Type, capability: Capability, what: &str) -> TypeResult<()> {
+    if capability.holds_for(t) {
+        Ok(())
+    } else {
+        Err(type_error(format!(
+            "{} is not {:?}, as {} must be",
+            t, capability, what
+        )))
+    }
+}
 
-    let mut checker = typeck::TypeChecker::new();
-    match checker.check_program(&prog) {
-        Ok(_) => println!("Type check passed!"),
-        Err(e) => println!("Type error: {}", e.message),
+// Types that can be written to an output stream as-is
+pub fn is_printable(t: &Type) -> bool {
+    matches!(
+        t,
+        Type::Int | Type::I128 | Type::Float | Type::String | Type::Bool | Type::ModInt(_)
+    )
+}
+
+// Types C++ can compare with `<`, so sort and friends can order them
+pub fn is_orderable(t: &Type) -> bool {
+    match t {
+        Type::Int | Type::I128 | Type::Float | Type::String | Type::Bool => true,
+        Type::Tuple(elems) => elems.iter().all(is_orderable),
+        Type::Pair(first, second) => is_orderable(first) && is_orderable(second),
+        _ => false,
+    }
+}
+
+// Types C++ can compare with `==`; structs and functions can't be
+pub fn is_equatable(t: &Type) -> bool {
+    match t {
+        Type::Int
+        | Type::I128
+        | Type::Float
+        | Type::String
+        | Type::Bool
+        | Type::ModInt(_)
+        | Type::Bitset => true,
+        Type::List(elem)
+        | Type::Option(elem)
+        | Type::Set(elem)
+        | Type::Deque(elem)
+        | Type::Grid(elem) => is_equatable(elem),
+        Type::Tuple(elems) => elems.iter().all(is_equatable),
+        Type::Pair(first, second) | Type::Map(first, second) => {
+            is_equatable(first) && is_equatable(second)
+        }
+        _ => false,
     }
 }
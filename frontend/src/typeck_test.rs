@@ -0,0 +1,1694 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expr, Type};
+    use crate::consteval::ConstValue;
+    use crate::parser::parse_program;
+    use crate::typeck::TypeChecker;
+
+    fn check(input: &str) -> Result<(), String> {
+        let program = parse_program(input)
+            .map_err(|errors| errors[0].message.clone())?
+            .Program()
+            .unwrap();
+        TypeChecker::new()
+            .check_program(&program)
+            .map_err(|e| e.message)
+    }
+
+    #[test]
+    fn test_structs() {
+        let program = r#"
+struct Point { x: int, y: int }
+val p: Point = Point(1, 2)
+val s: int = p.x + p.y
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("struct P { x: int }\nval p: P = P(1.5)\n").is_err());
+        assert!(check("struct P { x: int }\nval p: P = P(1, 2)\n").is_err());
+        assert!(check("struct P { x: int }\nval p: P = P(1)\nval z: int = p.z\n").is_err());
+        assert!(check("struct P { x: int, x: float }\n").is_err());
+        assert!(check("struct P { q: Q }\n").is_err());
+        // every annotation must name a known type
+        assert!(check("val q: Q = 1\n").is_err());
+        assert!(check("def f(p: Q) {\n}\n").is_err());
+        assert!(check("def f() -> list<Q> {\n}\n").is_err());
+        assert!(check("def f(g: fn(Q) -> int) {\n}\n").is_err());
+        assert!(check(
+            "struct Node { kids: list<Node> }\ndef f(n: Node) -> list<Node> {\nreturn n.kids\n}\n"
+        )
+        .is_ok());
+        // builtin type names can't be reused
+        assert!(check("struct int { x: int }\n").is_err());
+        assert!(check("struct list { x: int }\n").is_err());
+    }
+
+    #[test]
+    fn test_indexing() {
+        let program = r#"
+var xs: list<int> = [1, 2, 3]
+xs[0] = xs[1] + xs[2]
+xs[2] += 1
+var grid: list<list<float>> = [[1.0], [2.0]]
+grid[1][0] = 0.5
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val xs: list<int> = [1]\nval y: int = xs[0.5]\n").is_err());
+        assert!(check("val x: int = 3\nval y: int = x[0]\n").is_err());
+        assert!(check("var xs: list<int> = [1]\nxs[0] = 2.5\n").is_err());
+    }
+
+    #[test]
+    fn test_fstrings() {
+        assert!(check("val x: int = 3\nval s: string = f\"x = {x}, half = {x / 2}\"\n").is_ok());
+        assert!(check("val xs: list<int> = [1]\nprint(f\"{xs}\")\n").is_err());
+        assert!(check("print(f\"{missing}\")\n").is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let program = r#"
+val n: int = 7
+val f: float = float(n)
+val back: int = int(f)
+val s: string = str(n)
+val parsed: int = int(s)
+val parsed_f: float = float("2.5")
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val x: float = float([1])\n").is_err());
+        assert!(check("val x: int = int(1, 2)\n").is_err());
+        assert!(check("val x: string = int(3)\n").is_err());
+    }
+
+    #[test]
+    fn test_recursion() {
+        let program = r#"
+def fib(n: int) -> int {
+    if n < 2 {
+        return n
+    }
+    return fib(n - 1) + fib(n - 2)
+}
+val x: int = fib(10)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val x: int = undefined(3)\n").is_err());
+        assert!(check("val f: int = 3\nval x: int = f(3)\n").is_err());
+    }
+
+    #[test]
+    fn test_function_arguments() {
+        let program = r#"
+def twice(f: fn(int) -> int, x: int) -> int {
+    return f(f(x))
+}
+def inc(x: int) -> int {
+    return x + 1
+}
+val y: int = twice(inc, 3)
+val g: fn(int) -> int = inc
+"#;
+        assert!(check(program).is_ok());
+
+        let program = r#"
+def inc(x: int) -> int {
+    return x + 1
+}
+val g: fn(int) -> bool = inc
+"#;
+        assert!(check(program).is_err());
+    }
+
+    #[test]
+    fn test_multiple_returns() {
+        let program = r#"
+def divmod(a: int, b: int) -> tuple<int, int> {
+    return a // b, a % b
+}
+val (q, r) = divmod(7, 2)
+val s: int = q + r
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val (a, b) = (1, 2, 3)\n").is_err());
+        assert!(check("val (a, b) = 5\n").is_err());
+    }
+
+    #[test]
+    fn test_list_destructuring() {
+        let program = r#"
+val xs: list<int> = [3, 1, 2]
+val [a, b, c] = xs
+var [lo, hi] = [1.5, 2.5]
+lo = hi
+val s: int = a + b + c
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val [a, b] = (1, 2)\n").is_err());
+        assert!(check("val (a, b) = [1, 2]\n").is_err());
+        assert!(check("val [a, b] = [1, 2]\nval s: string = a\n").is_err());
+    }
+
+    #[test]
+    fn test_nested_functions() {
+        let program = r#"
+var total: int = 0
+def outer(n: int) -> int {
+    var acc: int = n
+    def add(x: int) {
+        acc += x
+        total += x
+    }
+    add(1)
+    add(2)
+    return acc
+}
+val r: int = outer(3)
+"#;
+        assert!(check(program).is_ok());
+
+        // nested functions are local to the enclosing body
+        let program = r#"
+def outer() {
+    def inner() {
+    }
+}
+inner()
+"#;
+        assert!(check(program).is_err());
+
+        // later declarations aren't visible to an earlier function
+        let program = r#"
+def f() -> int {
+    return later
+}
+val later: int = 1
+"#;
+        assert!(check(program).is_err());
+
+        let program = r#"
+def make(k: int) -> fn(int) -> int {
+    def add(x: int) -> int {
+        return x + k
+    }
+    return add
+}
+"#;
+        assert!(check(program).is_err());
+
+        // nor may it escape inside another value or through an outer variable
+        let escapes = [
+            "def make(k: int) -> option<fn(int) -> int> {\n    def add(x: int) -> int {\n        return x + k\n    }\n    return some(add)\n}\n",
+            "def make(k: int) -> tuple<fn(int) -> int, int> {\n    def add(x: int) -> int {\n        return x + k\n    }\n    return (add, k)\n}\n",
+            "def make(k: int) -> fn(int) -> int {\n    def add(x: int) -> int {\n        return x + k\n    }\n    val held = [add]\n    return held[0]\n}\n",
+            "def id(x: int) -> int {\n    return x\n}\nvar kept = [id]\ndef make(k: int) {\n    def add(x: int) -> int {\n        return x + k\n    }\n    kept.push(add)\n}\n",
+            "def id(x: int) -> int {\n    return x\n}\nvar saved: fn(int) -> int = id\ndef make(k: int) {\n    def add(x: int) -> int {\n        return x + k\n    }\n    saved = add\n}\n",
+        ];
+        for program in escapes {
+            let error = check(program).unwrap_err();
+            assert!(error.contains("nested function 'add'"), "{}", error);
+        }
+        // even one that captures nothing, and the error says how it escapes
+        let program = r#"
+def make() -> list<fn(int) -> int> {
+    def twice(x: int) -> int {
+        return 2 * x
+    }
+    val held = [twice]
+    return held
+}
+"#;
+        assert_eq!(
+            check(program).unwrap_err(),
+            "Cannot return 'held', which holds nested function 'twice', from 'make': 'twice' is local to 'make' and can't outlive its call"
+        );
+        let error = check(escapes[3]).unwrap_err();
+        assert!(
+            error.contains("in 'kept', declared outside 'make'"),
+            "{}",
+            error
+        );
+        // calling it, or keeping it in the function's own variables, is fine
+        let program = r#"
+def make(k: int) -> int {
+    def add(x: int) -> int {
+        return x + k
+    }
+    var fs = [add]
+    fs.push(add)
+    val f = fs[0]
+    return add(1) + f(2) + len(fs)
+}
+"#;
+        assert!(check(program).is_ok());
+    }
+
+    #[test]
+    fn test_constants() {
+        let program = r#"
+const MOD: int = 998244353
+const N: int = 2 * 100000 + 5
+const NAME: string = "bbl"
+val x: int = 5 % MOD
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("const M: int = 1.5\n").is_err());
+        assert!(check("val n: int = 3\nconst M: int = n\n").is_err());
+        assert!(check("const M: int = 1\nconst M: int = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_division() {
+        let program = r#"
+val a: int = 7
+val b: int = 2
+val q: int = a // b
+val f: float = a / b
+var x: int = 100
+x //= 3
+var y: float = f / 2.0
+y /= f
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val q: int = 7 / 2\n").is_err());
+        assert!(check("val q: float = 7.0 // 2.0\n").is_err());
+        assert!(check("var x: int = 7\nx /= 2\n").is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let program = r#"
+var best: option<int> = none
+best = some(3)
+val found: bool = best != none
+val x: int = unwrap(best)
+val xs: list<option<int>> = [none, some(1)]
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val x: option<int> = some(1.5)\n").is_err());
+        assert!(check("val x: int = none\n").is_err());
+        assert!(check("val x: int = unwrap(3)\n").is_err());
+        assert!(check("val x: int = unwrap(none)\n").is_err());
+    }
+
+    #[test]
+    fn test_panic() {
+        let program = r#"
+def checked_div(a: int, b: int) -> int {
+    if b == 0 {
+        panic("division by zero")
+    }
+    return a // b
+}
+panic(f"bad state {checked_div(4, 2)}")
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("panic()\n").is_err());
+        assert!(check("val x: int = panic(\"no\")\n").is_err());
+        assert!(check("panic([1])\n").is_err());
+    }
+
+    #[test]
+    fn test_extern_functions() {
+        let program = r#"
+extern fn __builtin_popcountll(x: int) -> int
+extern fn __gcd(a: int, b: int) -> int
+extern fn abort()
+val bits: int = __builtin_popcountll(255)
+val g: int = __gcd(12, 18)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("extern fn f() -> int\nval s: string = f()\n").is_err());
+        assert!(check("extern fn f(p: Point)\n").is_err());
+        assert!(check("val f: int = 1\nextern fn f()\n").is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let program = r#"
+val n: int = 5
+val r: range = 0..n
+def total(rr: range) -> int {
+    var t: int = 0
+    for i in rr {
+        t += i
+    }
+    return t
+}
+for i in 1..=n {
+    print(i)
+}
+val t: int = total(r)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val r: range = 0..1.5\n").is_err());
+        assert!(check("for i in 5 {\n}\n").is_err());
+        // the loop variable is scoped to the body
+        assert!(check("for i in 0..3 {\n}\nprint(i)\n").is_err());
+    }
+
+    #[test]
+    fn test_for_each() {
+        let program = r#"
+val xs: list<int> = [3, 1, 2]
+var total: int = 0
+for x in xs {
+    total += x
+}
+val words: list<string> = ["a", "b"]
+for w in words {
+    print(w)
+}
+val grid: list<list<float>> = [[1.0], [2.0, 3.0]]
+for row in grid {
+    for v in row {
+        print(v)
+    }
+}
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(
+            check("val xs: list<string> = [\"a\"]\nfor x in xs {\n    val y: int = x\n}\n")
+                .is_err()
+        );
+        assert!(check("for x in \"abc\" {\n}\n").is_err());
+    }
+
+    #[test]
+    fn test_enumerate_zip() {
+        let program = r#"
+val xs: list<int> = [3, 1, 2]
+val ys: list<float> = [0.5, 1.5]
+for (i, x) in enumerate(xs) {
+    val s: int = i + x
+}
+for (x, y) in zip(xs, ys) {
+    val s: float = y * 2.0
+}
+val ps: list<tuple<int, string>> = [(1, "a")]
+for (n, name) in ps {
+    print(name)
+}
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val xs: list<int> = [1]\nfor (i, x, y) in enumerate(xs) {\n}\n").is_err());
+        assert!(check("val xs: list<int> = [1]\nfor (a, b) in zip(xs) {\n}\n").is_err());
+        assert!(check("for (i, x) in enumerate(3) {\n}\n").is_err());
+        // only loops understand enumerate
+        assert!(check("val xs: list<int> = [1]\nval e: int = enumerate(xs)\n").is_err());
+    }
+
+    #[test]
+    fn test_ref_parameters() {
+        let program = r#"
+def push_front(ref xs: list<int>, v: int) {
+    xs[0] = v
+}
+def bump(ref n: int) {
+    n += 1
+}
+var xs: list<int> = [1, 2]
+var count: int = 0
+push_front(xs, 5)
+bump(count)
+bump(xs[1])
+val cb: fn(ref int) = bump
+"#;
+        assert!(check(program).is_ok());
+
+        // the callee could write through the reference
+        assert!(check("def bump(ref n: int) {\n    n += 1\n}\nval x: int = 1\nbump(x)\n").is_err());
+        assert!(check("def bump(ref n: int) {\n    n += 1\n}\nbump(3)\n").is_err());
+        assert!(check("const N: int = 3\ndef bump(ref n: int) {\n}\nbump(N)\n").is_err());
+        assert!(check(
+            "val xs: list<int> = [1]\nfor x in xs {\n    def f(ref n: int) {\n    }\n    f(x)\n}\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_list_init() {
+        let program = r#"
+val n: int = 3
+var grid: list<list<int>> = list<list<int>>(n, n + 1, 0)
+grid[0][1] = 5
+val rows: list<list<float>> = list<list<float>>(2, [1.5])
+var best: list<option<int>> = list<option<int>>(n, none)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val g: list<int> = list<int>(2, 3, 0)\n").is_err());
+        assert!(check("val g: list<int> = list<int>(1.5, 0)\n").is_err());
+        assert!(check("val g: list<list<int>> = list<list<int>>(2, 2, 0.5)\n").is_err());
+        assert!(check("val g: list<int> = list<list<int>>(2, 2, 0)\n").is_err());
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let program = r#"
+const MOD: int = 1_000_000_007
+const INV2: int = (MOD + 1) // 2
+const HALF: float = 1 / 2
+const BIG: bool = MOD > 1000 == (2 > 1)
+const LIMIT: int = 1 << 62
+"#;
+        let ast = parse_program(program).unwrap().Program().unwrap();
+        let mut checker = TypeChecker::new();
+        assert!(checker.check_program(&ast).is_ok());
+        assert_eq!(checker.constants["INV2"], ConstValue::Int(500_000_004));
+        assert_eq!(checker.constants["HALF"], ConstValue::Float(0.5));
+        assert_eq!(checker.constants["BIG"], ConstValue::Bool(true));
+
+        assert!(check("const A: int = 9223372036854775807 + 1\n").is_err());
+        assert!(check("const A: int = 3 * 4611686018427387904\n").is_err());
+        assert!(check("const A: int = 1 << 63\n").is_err());
+        assert!(check("const A: int = 5 // (3 - 3)\n").is_err());
+        assert!(check("const A: int = 7 % 0\n").is_err());
+        assert!(check("const A: float = 1.0 / 0.0\n").is_err());
+        assert!(check("const A: int = 99999999999999999999\n").is_err());
+    }
+
+    #[test]
+    fn test_try_expressions() {
+        let program = r#"
+val s: string = "12"
+val n: int = try int(s) else 0
+var best: option<int> = none
+val m: int = try unwrap(best) * 2 else n
+val f: float = try float(s) else panic("not a number")
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val n: int = try int(\"1\") else \"zero\"\n").is_err());
+        assert!(check("val n: string = try int(\"1\") else 0\n").is_err());
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let program = r#"
+var best: option<int> = none
+val fallback: option<int> = some(3)
+val n: int = best ?? 0
+val m: int = best ?? fallback ?? -1
+val o: option<int> = best ?? fallback
+val c: bool = best ?? 0 == 1
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val n: int = 5 ?? 0\n").is_err());
+        assert!(check("var x: option<int> = none\nval s: string = x ?? \"\"\n").is_err());
+        assert!(check("var x: option<int> = none\nval n: int = x ?? some(1)\n").is_err());
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let program = r#"
+val x: int = 1
+val x: string = "one"
+var n: int = 3
+def f(n: int) -> int {
+    val n: int = n * 10
+    return n
+}
+if n > 0 {
+    val n: string = "inner"
+    print(n)
+}
+n = n + 1
+"#;
+        assert!(check(program).is_ok());
+
+        // names declared in a block end with it
+        assert!(check("if true {\n    val y: int = 1\n}\nprint(y)\n").is_err());
+        assert!(check("rep 3 {\n    var y: int = 1\n}\ny = 2\n").is_err());
+        // an inner `val` does not make the outer variable immutable
+        assert!(check("var y: int = 1\nrep 2 {\n    val y: int = 5\n}\ny = 2\n").is_ok());
+    }
+
+    #[test]
+    fn test_read_int() {
+        assert!(check("val n: int = read_int()\nrep n {\n    print(read_int() * 2)\n}\n").is_ok());
+        assert!(check("val s: string = read_int()\n").is_err());
+        assert!(check("val n: int = read_int(5)\n").is_err());
+    }
+
+    #[test]
+    fn test_read_strings() {
+        let program = r#"
+val n: int = read_int()
+val name: string = read_str()
+val line: string = read_line()
+print(f"{n} {name}: {line}")
+"#;
+        assert!(check(program).is_ok());
+        assert!(check("val n: int = read_line()\n").is_err());
+        assert!(check("val s: string = read_str(1)\n").is_err());
+    }
+
+    #[test]
+    fn test_read_list() {
+        assert!(
+            check("val n: int = read_int()\nval a: list<int> = read_list(n)\nprint(a[0])\n")
+                .is_ok()
+        );
+        assert!(check("val a: list<string> = read_list(3)\n").is_err());
+        assert!(check("val a: list<int> = read_list(\"3\")\n").is_err());
+    }
+
+    #[test]
+    fn test_input_header() {
+        let program = r#"
+input {
+    n: int, m: int
+    a: list<int>(n)
+    grid: list<list<string>>(n, m)
+}
+a[0] = n * m
+print(grid[0][0])
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("input { a: list<int> }\n").is_err());
+        assert!(check("input { n: int, a: list<int>(n, n) }\n").is_err());
+        assert!(check("input { a: list<int>(k) }\n").is_err());
+        assert!(check("input { s: string, a: list<int>(s) }\n").is_err());
+        assert!(check("input { t: tuple<int, int> }\n").is_err());
+    }
+
+    #[test]
+    fn test_len() {
+        let program = r#"
+val xs: list<int> = [1, 2, 3]
+val s: string = "abc"
+val n: int = len(xs) + len(s)
+rep len(xs) - 1 {
+    print(len([[1], [2]]))
+}
+"#;
+        assert!(check(program).is_ok());
+        assert!(check("val n: int = len(5)\n").is_err());
+        assert!(check("val s: string = len(\"abc\")\n").is_err());
+    }
+
+    #[test]
+    fn test_list_methods() {
+        let program = r#"
+var xs: list<int> = [1, 2, 3]
+xs.push(4)
+xs.insert(0, 10)
+xs.remove(1)
+val last: int = xs.pop()
+var g: list<list<int>> = [[1], [2]]
+g[0].push(last)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("var xs: list<int> = [1]\nxs.push(\"a\")\n").is_err());
+        assert!(check("var xs: list<int> = [1]\nxs.insert(\"0\", 1)\n").is_err());
+        assert!(check("val xs: list<int> = [1]\nxs.push(2)\n").is_err());
+        assert!(check("var n: int = 1\nn.push(2)\n").is_err());
+        assert!(check("var xs: list<int> = [1]\nxs.append(2)\n").is_err());
+        assert!(check("var xs: list<int> = [1]\nval s: string = xs.pop()\n").is_err());
+    }
+
+    #[test]
+    fn test_sort() {
+        let program = r#"
+var xs: list<int> = [5, -3, 2]
+sort(xs)
+def absval(x: int) -> int {
+    return x if x >= 0 else -x
+}
+sort(xs, absval)
+def desc(a: int, b: int) -> bool {
+    return a > b
+}
+sort_by(xs, desc)
+var s: string = "hello"
+sort(s)
+var ps: list<tuple<int, int>> = [(2, 1), (1, 5)]
+sort(ps)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val xs: list<int> = [2, 1]\nsort(xs)\n").is_err());
+        assert!(check("var xs: list<list<int>> = [[1]]\nsort(xs)\n").is_err());
+        let wrong_key =
+            "def f(x: string) -> int {\n    return 0\n}\nvar xs: list<int> = [1]\nsort(xs, f)\n";
+        assert!(check(wrong_key).is_err());
+        let wrong_cmp = "def f(a: int, b: int) -> int {\n    return a - b\n}\nvar xs: list<int> = [1]\nsort_by(xs, f)\n";
+        assert!(check(wrong_cmp).is_err());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let program = r#"
+var xs: list<int> = [1, 2, 3]
+reverse(xs)
+val ys: list<int> = reversed(xs)
+val s: string = reversed("abc")
+var t: string = s
+reverse(t)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val xs: list<int> = [1]\nreverse(xs)\n").is_err());
+        assert!(check("val n: int = reversed(5)\n").is_err());
+        assert!(check("val xs: list<string> = reversed([1, 2])\n").is_err());
+    }
+
+    #[test]
+    fn test_min_max() {
+        let program = r#"
+val xs: list<int> = [3, 1, 2]
+val a: int = min(xs[0], 5) + max(xs)
+val b: float = max(1.5, 2.5)
+val c: string = min(["b", "a"])
+val d: int = min([4, 2])
+val e: int = max(reversed(xs)[0], 1)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val a: int = min(1, 2.5)\n").is_err());
+        assert!(check("val a: int = max(1, 2, 3)\n").is_err());
+        assert!(check("val a: int = max(5)\n").is_err());
+        assert!(check("val a: string = max(\"a\", \"b\")\n").is_err());
+    }
+
+    #[test]
+    fn test_binary_search() {
+        let program = r#"
+def ok(x: int) -> bool {
+    return x * x >= 50
+}
+val xs: list<int> = [1, 3, 3, 5]
+val i: int = lower_bound(xs, 3) + upper_bound(xs, 3)
+val j: int = lower_bound(["a", "c"], "b")
+val k: int = bsearch(0, 100, ok)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(
+            check("val xs: list<int> = [1, 2]\nval i: int = lower_bound(xs, \"a\")\n").is_err()
+        );
+        assert!(check("val i: int = upper_bound(5, 5)\n").is_err());
+        assert!(
+            check("def f(x: int) -> int {\n    return x\n}\nval k: int = bsearch(0, 9, f)\n")
+                .is_err()
+        );
+        assert!(check(
+            "def f(x: int) -> bool {\n    return x > 3\n}\nval k: int = bsearch(0, 9.5, f)\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_prefix_sums() {
+        let program = r#"
+val xs: list<int> = [3, 1, 4]
+val p: list<int> = prefix_sums(xs)
+val q: list<float> = prefix_sums([0.5, 1.5])
+print(p[3] - p[1])
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val p: list<string> = prefix_sums([\"a\"])\n").is_err());
+        assert!(check("val p: list<int> = prefix_sums(5)\n").is_err());
+        assert!(check("val p: list<int> = prefix_sums([0.5])\n").is_err());
+    }
+
+    #[test]
+    fn test_dedup() {
+        assert!(check("var xs: list<int> = [3, 1, 3]\ndedup(xs)\n").is_ok());
+        assert!(check("val xs: list<int> = [3, 1, 3]\ndedup(xs)\n").is_err());
+        assert!(check("var xs: list<list<int>> = [[1]]\ndedup(xs)\n").is_err());
+        assert!(check("var s: string = \"aa\"\ndedup(s)\n").is_err());
+    }
+
+    #[test]
+    fn test_count_filter_map() {
+        let program = r#"
+def even(x: int) -> bool {
+    return x % 2 == 0
+}
+def show(x: int) -> string {
+    return f"{x}"
+}
+val xs: list<int> = [1, 2, 2]
+val n: int = count(xs, 2)
+val evens: list<int> = filter(xs, even)
+val shown: list<string> = map(evens, show)
+"#;
+        assert!(check(program).is_ok());
+
+        let funcs = "def even(x: int) -> bool {\n    return x % 2 == 0\n}\ndef show(s: string) -> string {\n    return s\n}\n";
+        assert!(check(&format!("{}val n: int = count([1], \"a\")\n", funcs)).is_err());
+        assert!(check(&format!("{}val ys: list<string> = map([1], show)\n", funcs)).is_err());
+        assert!(check(&format!(
+            "{}val ys: list<int> = filter([\"a\"], even)\n",
+            funcs
+        ))
+        .is_err());
+        assert!(check(&format!(
+            "{}val ys: list<string> = filter([\"a\"], show)\n",
+            funcs
+        ))
+        .is_err());
+        assert!(check(&format!("{}val ys: list<bool> = map(5, even)\n", funcs)).is_err());
+    }
+
+    #[test]
+    fn test_abs() {
+        assert!(check("val a: int = abs(-5)\nval b: float = abs(-2.5)\n").is_ok());
+        assert!(check("val a: int = abs(-2.5)\n").is_err());
+        assert!(check("val a: int = abs(\"x\")\n").is_err());
+    }
+
+    #[test]
+    fn test_pow() {
+        assert!(check("val a: int = pow(2, 10) + modpow(3, 200, 1_000_000_007)\n").is_ok());
+        assert!(check("val a: int = pow(2.0, 3)\n").is_err());
+        assert!(check("val a: float = pow(2, 3)\n").is_err());
+        assert!(check("val a: int = modpow(2, 3)\n").is_err());
+    }
+
+    #[test]
+    fn test_math() {
+        let program = r#"
+val r: float = sqrt(2) + log(10.0)
+val f: int = floor(2.5) + ceil(r) + round(-1.5)
+val i: int = isqrt(1_000_000_000_000)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val r: int = sqrt(4)\n").is_err());
+        assert!(check("val r: float = floor(2.5)\n").is_err());
+        assert!(check("val r: int = isqrt(4.0)\n").is_err());
+        assert!(check("val r: float = log(\"e\")\n").is_err());
+    }
+
+    #[test]
+    fn test_random() {
+        assert!(check("var xs: list<int> = [rand_int(1, 6), 2]\nshuffle(xs)\n").is_ok());
+        assert!(check("val x: int = rand_int(1.0, 6)\n").is_err());
+        assert!(check("val xs: list<int> = [1]\nshuffle(xs)\n").is_err());
+        assert!(check("var s: string = \"ab\"\nshuffle(s)\n").is_err());
+    }
+
+    #[test]
+    fn test_split_join() {
+        let program = r#"
+val line: string = "a b  c"
+val words: list<string> = line.split()
+val cells: list<string> = "1,2,3".split(",")
+val back: string = join(cells, ";")
+print(len(words) + len(back))
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val xs: list<string> = \"a b\".split(1)\n").is_err());
+        assert!(check("val n: int = \"a b\".split()\n").is_err());
+        assert!(check("val s: string = join([1, 2], \",\")\n").is_err());
+        assert!(check("val s: string = \"a\".shout()\n").is_err());
+    }
+
+    #[test]
+    fn test_string_search() {
+        let program = r#"
+val s: string = "banana"
+val i: int = s.find("na")
+val has: bool = s.contains("nan")
+val literal: bool = "abc".contains("b")
+val t: string = s.replace("a", "o")
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val b: bool = \"abc\".find(\"b\")\n").is_err());
+        assert!(check("val i: int = \"abc\".find(1)\n").is_err());
+        assert!(check("val s: string = \"abc\".replace(\"a\")\n").is_err());
+    }
+
+    #[test]
+    fn test_string_case() {
+        let program = r#"
+val s: string = "  Hello World "
+val shout: string = s.trim().to_upper()
+val quiet: string = "ABC".to_lower()
+"#;
+        assert!(check(program).is_ok());
+        assert!(check("val s: string = \"a\".trim(\" \")\n").is_err());
+        assert!(check("val n: int = \"a\".to_upper()\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        let program = r#"
+val n: int = parse_int("42") ?? 0
+val f: option<float> = parse_float("2.5")
+val g: float = unwrap(f)
+"#;
+        assert!(check(program).is_ok());
+        assert!(check("val n: int = parse_int(\"42\")\n").is_err());
+        assert!(check("val n: option<int> = parse_int(42)\n").is_err());
+    }
+
+    #[test]
+    fn test_maps() {
+        let program = r#"
+var cnt: map<string, int> = map<string, int>()
+cnt["a"] += 1
+cnt.insert("b", 2)
+val n: int = cnt.get("c") ?? 0
+val has: bool = cnt.contains("a")
+val keys: list<string> = cnt.keys()
+val values: list<int> = cnt.values()
+for (k, v) in cnt {
+    print(f"{k} {v}")
+}
+print(len(cnt))
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("var m: map<int, int> = map<int, int>()\nprint(m[\"a\"])\n").is_err());
+        assert!(check("var m: map<int, int> = map<int, int>()\nm.insert(1, \"x\")\n").is_err());
+        assert!(check("val m: map<int, int> = map<int, int>()\nm.insert(1, 2)\n").is_err());
+        assert!(check("var m: map<int, int> = map<int, int>()\nval v: int = m.get(1)\n").is_err());
+        assert!(check("val m: map<list<int>, int> = map<list<int>, int>()\n").is_err());
+    }
+
+    #[test]
+    fn test_pairs() {
+        let program = r#"
+var p: pair<int, string> = pair(1, "a")
+p.first += 1
+val s: string = p.second
+var ps: list<pair<int, int>> = [pair(2, 1), pair(1, 5)]
+sort(ps)
+for (a, b) in ps {
+    print(a + b)
+}
+val (x, y) = ps[0]
+val less: bool = ps[0] < ps[1]
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val p: pair<int, int> = pair(1, \"a\")\n").is_err());
+        assert!(check("val p: pair<int, int> = pair(1, 2)\nprint(p.third)\n").is_err());
+        assert!(check("val p: pair<int, int> = pair(1, 2)\nval (a, b, c) = p\n").is_err());
+        assert!(check("val p: pair<int, int> = pair(1, 2, 3)\n").is_err());
+    }
+
+    #[test]
+    fn test_heaps() {
+        let program = r#"
+var h: heap<int> = heap<int>()
+h.push(3)
+val top: int = h.top()
+val popped: int = h.pop()
+val n: int = h.size() + len(h)
+var dist: min_heap<pair<int, int>> = min_heap<pair<int, int>>()
+dist.push(pair(0, 1))
+val (d, node) = dist.pop()
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("var h: heap<int> = heap<int>()\nh.push(\"a\")\n").is_err());
+        assert!(check("val h: heap<int> = heap<int>()\nh.push(1)\n").is_err());
+        assert!(check("val h: heap<int> = heap<int>()\nval x: int = h.pop()\n").is_err());
+        assert!(check("var h: heap<int> = min_heap<int>()\n").is_err());
+        assert!(check("var h: heap<list<int>> = heap<list<int>>()\n").is_err());
+    }
+
+    #[test]
+    fn test_containers() {
+        let program = r#"
+var st: stack<int> = stack<int>()
+st.push(1)
+val a: int = st.top() + st.pop()
+var q: queue<string> = queue<string>()
+q.push("x")
+val s: string = q.front()
+val t: string = q.back()
+val u: string = q.pop()
+var d: deque<int> = deque<int>()
+d.push_back(1)
+d.push_front(2)
+d[0] = 3
+val b: int = d.front() + d.back() + d.pop_back() + d.pop_front() + d[0]
+val n: int = st.size() + q.size() + len(d)
+for x in d {
+    print(x)
+}
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("var st: stack<int> = stack<int>()\nst.push(\"a\")\n").is_err());
+        assert!(check("var st: stack<int> = stack<int>()\nval x: int = st.front()\n").is_err());
+        assert!(check("var q: queue<int> = queue<int>()\nq.push_front(1)\n").is_err());
+        assert!(check("val d: deque<int> = deque<int>()\nd.push_back(1)\n").is_err());
+        assert!(check("var q: queue<int> = queue<int>()\nprint(q[0])\n").is_err());
+    }
+
+    #[test]
+    fn test_grids() {
+        let program = r#"
+var g: grid<int> = grid<int>(3, 4)
+g[1][2] = 5
+val row: list<int> = g[0]
+val ok: bool = g.in_bounds(2, 3)
+val n: int = g.rows() * g.cols() + len(g)
+for (nr, nc) in g.neighbors4(1, 1) {
+    g[nr][nc] += 1
+}
+for cell in g.neighbors8(0, 0) {
+    print(cell.0)
+}
+val walls: grid<string> = grid<string>(2, 2, "x")
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val g: grid<int> = grid<int>(2, 2, \"#\")\n").is_err());
+        assert!(check("val g: grid<int> = grid<int>(2, 2.5)\n").is_err());
+        assert!(check("val g: grid<int> = grid<int>(2, 2)\nprint(g.in_bounds(0))\n").is_err());
+        assert!(check(
+            "val g: grid<int> = grid<int>(2, 2)\nval c: int = len(g.neighbors4(0, 0))\n"
+        )
+        .is_err());
+        assert!(check(
+            "val xs: list<int> = [1]\nfor (r, c) in xs.neighbors4(0, 0) {\n    print(r)\n}\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_mint() {
+        let program = r#"
+var a: mint<998244353> = mint<998244353>(5)
+a += 1
+a = a * a - 3 / a
+val b: mint<998244353> = 2 * -a + pow(a, 10)
+val same: bool = a == b
+val n: int = int(b)
+print(a)
+print(str(a))
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val a: mint<7> = 3\n").is_err());
+        assert!(check("val a: int = mint<7>(3)\n").is_err());
+        assert!(
+            check("val a: mint<7> = mint<7>(3)\nval b: mint<5> = mint<5>(1)\nprint(a + b)\n")
+                .is_err()
+        );
+        assert!(check("val a: mint<7> = mint<7>(3)\nprint(a < a)\n").is_err());
+        assert!(check("val a: mint<7> = mint<7>(2.5)\n").is_err());
+        assert!(check("val a: mint<0> = mint<0>(1)\n").is_err());
+    }
+
+    #[test]
+    fn test_i128() {
+        let program = r#"
+val big: i128 = 170141183460469231731687303715884105727
+var x: i128 = i128(1000000000000)
+x = x * 1000000000000 + 1
+x %= 1000000007
+val y: i128 = -x // 3 + (x << 2)
+val less: bool = x < big
+val n: int = int(y)
+val f: float = x / 2
+print(x)
+print(str(y))
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val x: int = 100000000000000000000\n").is_err());
+        assert!(check("val x: int = i128(5)\n").is_err());
+        assert!(check("val x: i128 = i128(5)\nval y: int = x + 1\n").is_err());
+        assert!(check("val x: i128 = i128(2.5)\n").is_err());
+        // past the i128 range a literal is an error rather than dropped
+        assert!(check("val x = 170141183460469231731687303715884105728\n").is_err());
+        assert!(check("print(0x1000000000000000000000000000000000)\n").is_err());
+        assert!(check("val x: i128 = 9223372036854775808\n").is_ok());
+        // the minus belongs to the literal, so -2^63 is still an int
+        assert!(check("val x: int = -9223372036854775808\n").is_ok());
+        assert!(check("val x: int = -9223372036854775809\n").is_err());
+        assert!(check("val x: i128 = -9223372036854775809\n").is_ok());
+    }
+
+    #[test]
+    fn test_bitsets() {
+        let program = r#"
+var dp: bitset = bitset(100)
+dp.set(0)
+dp |= dp << 3
+dp &= dp >> 1
+dp ^= dp
+dp <<= 2
+dp.reset(0)
+dp.flip(1)
+val on: bool = dp.test(4)
+val n: int = dp.count() + dp.size()
+val same: bool = (dp | dp) == dp
+var mask: int = 5
+mask ^= 1 << 2
+mask |= 8
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val b: bitset = bitset(2.5)\n").is_err());
+        assert!(check("val b: bitset = bitset(5)\nb.set(1)\n").is_err());
+        assert!(check("var b: bitset = bitset(5)\nb.set(\"a\")\n").is_err());
+        assert!(check("var b: bitset = bitset(5)\nval c: bitset = b + b\n").is_err());
+        assert!(check("var b: bitset = bitset(5)\nval c: bitset = b & 1\n").is_err());
+        assert!(check("var b: bitset = bitset(5)\nprint(b < b)\n").is_err());
+    }
+
+    #[test]
+    fn test_elapsed_ms() {
+        assert!(check("val t: int = elapsed_ms()\nprint(t < 1900)\n").is_ok());
+        assert!(check("val t: int = elapsed_ms(0)\n").is_err());
+        assert!(check("val t: float = elapsed_ms()\n").is_err());
+    }
+
+    #[test]
+    fn test_exit() {
+        let program = r#"
+for i in 0..10 {
+    if i == 3 {
+        print(i)
+        exit(0)
+    }
+}
+"#;
+        assert!(check(program).is_ok());
+        assert!(check("exit(\"done\")\n").is_err());
+        assert!(check("exit()\n").is_err());
+        assert!(check("val x: int = exit(0)\n").is_err());
+    }
+
+    #[test]
+    fn test_val_reassignment() {
+        let program = r#"
+var total = 0
+var xs = [1, 2]
+def add(x: int) {
+    total += x
+    x = 0
+}
+for x in xs {
+    add(x)
+}
+xs[0] = total
+val total = 5
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val x = 1\nx = 2\n").is_err());
+        assert!(check("val x = 1\nx += 2\n").is_err());
+        assert!(check("val xs = [1]\nxs[0] = 2\n").is_err());
+        assert!(check("const C: int = 1\nC = 2\n").is_err());
+        assert!(check("for i in 0..3 {\n    i = 2\n}\n").is_err());
+        assert!(check("val x = 1\ndef f() {\n    x = 2\n}\n").is_err());
+    }
+
+    #[test]
+    fn test_numeric_promotion() {
+        let program = r#"
+val n = 3
+val x: float = n + 1.5
+val y: float = 0.5 * n
+var f = 0.5
+f += n
+val below: bool = n < 2.5
+val g: float = float(n)
+const HALF_MAX: float = 1000000 / 2 + 0.5
+"#;
+        assert!(check(program).is_ok());
+
+        // no implicit narrowing, and no implicit widening on assignment
+        assert!(check("var n = 1\nn += 0.5\n").is_err());
+        assert!(check("val n: int = 1 + 1.5\n").is_err());
+        assert!(check("val f: float = 1\n").is_err());
+        // int-only operators stay int-only
+        assert!(check("val r = 7 % 2.0\n").is_err());
+        assert!(check("val r = 7 // 2.0\n").is_err());
+    }
+
+    #[test]
+    fn test_method_signatures() {
+        let program = r#"
+val xs = [1, 2, 3]
+val s = "a,b"
+val n: int = xs.len() + s.len() + "abc".len()
+val parts: list<string> = s.split(",")
+val words: list<string> = s.split()
+var m = map<string, int>()
+m.insert("a", 1)
+val v: option<int> = m.get("a")
+var q = queue<int>()
+q.push(n)
+val front: int = q.front()
+"#;
+        assert!(check(program).is_ok());
+
+        // unknown methods, wrong arguments and immutable receivers
+        assert!(check("val xs = [1]\nval n = xs.size()\n").is_err());
+        assert!(check("val s = \"a\"\nval n = s.len(1)\n").is_err());
+        assert!(check("val s = \"a\"\nval p = s.split(1)\n").is_err());
+        assert!(check("var m = map<string, int>()\nm.insert(1, 1)\n").is_err());
+        assert!(check("val xs = [1]\nxs.push(2)\n").is_err());
+        assert!(check("val n = 3\nval m = n.len()\n").is_err());
+    }
+
+    #[test]
+    fn test_call_arguments() {
+        let program = r#"
+def sq(x: int) -> int {
+    return x * x
+}
+def apply(f: fn(int) -> int, x: int) -> int {
+    return f(x)
+}
+def fill(ref xs: list<int>, v: int) {
+    xs.push(v)
+}
+def first(p: option<int>) -> int {
+    return p ?? 0
+}
+var xs = [1]
+fill(xs, apply(sq, 3))
+val a = first(none)
+val b = first(some(2))
+"#;
+        assert!(check(program).is_ok());
+
+        let sq = "def sq(x: int) -> int {\n    return x * x\n}\n";
+        assert!(check(&format!("{}val a = sq()\n", sq)).is_err());
+        assert!(check(&format!("{}val a = sq(1, 2)\n", sq)).is_err());
+        assert!(check(&format!("{}val a = sq(\"3\")\n", sq)).is_err());
+        assert!(check(&format!("{}val s: string = sq(3)\n", sq)).is_err());
+        // function arguments must have the parameter's signature
+        assert!(check(&format!(
+            "{}def apply(f: fn(float) -> int) -> int {{\n    return f(1.0)\n}}\nval a = apply(sq)\n",
+            sq
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_return_types() {
+        let program = r#"
+def sign(x: int) -> int {
+    if x < 0 {
+        return -1
+    }
+    return 1
+}
+def find(xs: list<int>, v: int) -> option<int> {
+    for (i, x) in enumerate(xs) {
+        if x == v {
+            return some(i)
+        }
+    }
+    return none
+}
+def bounds(xs: list<int>) -> tuple<int, int> {
+    return min(xs), max(xs)
+}
+def outer() -> fn(int) -> int {
+    return sign
+}
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("def f() -> int {\n    return \"a\"\n}\n").is_err());
+        assert!(check("def f(x: int) -> bool {\n    if x > 0 {\n        return 1\n    }\n    return false\n}\n").is_err());
+        assert!(check("def f() {\n    return 1\n}\n").is_err());
+        assert!(check("return 1\n").is_err());
+        // a nested function checks its own return type
+        assert!(check(
+            "def f() -> int {\n    def g() -> string {\n        return 1\n    }\n    return 1\n}\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_inferred_bindings() {
+        let program = r#"
+val n = 5
+var xs = [3, 1, 2]
+var total = 0
+for x in xs {
+    total += x
+}
+val avg = total / n
+val name = f"n={n}"
+val p = pair(n, "a")
+var m = map<string, int>()
+m[name] = total
+def sq(x: int) -> int {
+    val y = x * x
+    return y
+}
+val big = sq(n) + 1
+val half: float = avg
+"#;
+        assert!(check(program).is_ok());
+
+        // and is recorded for codegen
+        let program = parse_program("val p = pair(1, \"a\")\nval q: float = 1.5\n")
+            .unwrap()
+            .Program()
+            .unwrap();
+        TypeChecker::new().check_program(&program).unwrap();
+        let inferred = program
+            .expressions
+            .iter()
+            .map(|expr| match expr {
+                Expr::AssignmentExpr(assign) => assign.inferred.get().cloned(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inferred,
+            [
+                Some(Type::Pair(Box::new(Type::Int), Box::new(Type::String))),
+                None
+            ]
+        );
+
+        // the inferred type sticks
+        assert!(check("var x = 1\nx = 2.5\n").is_err());
+        assert!(check("val x = 1\nval s: string = x\n").is_err());
+        // nothing to infer from
+        assert!(check("val x = none\n").is_err());
+        assert!(check("def f() {\n    print(1)\n}\nval x = f()\n").is_err());
+    }
+
+    #[test]
+    fn test_debug() {
+        let program = r#"
+val xs: list<int> = [1, 2]
+val s: string = "a"
+debug(xs[0], len(xs) * 2, s, xs[1] > 0)
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("debug(y)\n").is_err());
+        assert!(check("val xs: list<int> = [1]\ndebug(xs)\n").is_err());
+        // a submission runs without debug, so it mustn't change anything
+        assert!(check("var xs = [1]\ndebug(xs.pop())\n").is_err());
+        assert!(check("debug(read_int())\n").is_err());
+    }
+
+    #[test]
+    fn test_flush() {
+        let program = r#"
+interactive
+print("? 1")
+flush()
+val answer: int = read_int()
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("flush(1)\n").is_err());
+        assert!(check("val x: int = flush()\n").is_err());
+    }
+
+    #[test]
+    fn test_input_until_eof() {
+        let program = r#"
+var total: int = 0
+for x in input_ints() {
+    total += x
+}
+var words: string = ""
+for w in input_strs() {
+    words += w
+}
+for f in input_floats() {
+    print(f / 2.0)
+}
+"#;
+        assert!(check(program).is_ok());
+
+        // only valid as the iterable of a for loop
+        assert!(check("val xs: list<int> = input_ints()\n").is_err());
+        assert!(check("for x in input_ints() {\n    val s: string = x\n}\n").is_err());
+        assert!(check("for (a, b) in input_ints() {\n    print(a)\n}\n").is_err());
+        assert!(check("for x in input_strs(3) {\n    print(x)\n}\n").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let program = r#"
+val x: float = 3.14159
+val n: int = 7
+val s: string = format("{:.6} {} {:.2} {{}}", x, n, n)
+print(format("{}", s))
+print(format("done"))
+"#;
+        assert!(check(program).is_ok());
+
+        // placeholders must match the values
+        assert!(check("print(format(\"{} {}\", 1))\n").is_err());
+        assert!(check("print(format(\"{}\", 1, 2))\n").is_err());
+        assert!(check("print(format(\"{:.2}\", \"a\"))\n").is_err());
+        assert!(check("print(format(\"{:x}\", 1))\n").is_err());
+        assert!(check("print(format(\"{\", 1))\n").is_err());
+        // the template must be a literal
+        assert!(check("val t: string = \"{}\"\nprint(format(t, 1))\n").is_err());
+    }
+
+    #[test]
+    fn test_string_concat() {
+        let program = r#"
+var s: string = "a" + "b"
+for i in 0..3 {
+    s = s + str(i)
+    s += "-"
+}
+val t: string = s + s
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("val s: string = \"a\" + 1\n").is_err());
+        assert!(check("val s: string = \"a\" - \"b\"\n").is_err());
+        assert!(check("var s: string = \"a\"\ns += 1\n").is_err());
+    }
+
+    #[test]
+    fn test_missing_return() {
+        assert_eq!(
+            check("def f(x: int) -> int {\nif x > 0 {\nreturn 1\n}\n}\n").unwrap_err(),
+            "Missing return in 'f': the end of its body can be reached without returning int"
+        );
+        assert!(check("def f() -> int {\nprint(1)\n}\n").is_err());
+        // every path returning, or stopping the program, is enough
+        assert!(
+            check("def f(x: int) -> int {\nif x > 0 {\nreturn 1\n} else {\nreturn 2\n}\n}\n")
+                .is_ok()
+        );
+        assert!(check("def f(x: int) -> int {\nif x > 0 {\nreturn 1\n}\nexit(0)\n}\n").is_ok());
+        // a bare return leaves a function without a return type
+        assert!(check("def f(x: int) {\nif x < 0 {\nreturn\n}\nprint(x)\n}\n").is_ok());
+        assert_eq!(
+            check("def f() -> int {\nreturn\n}\n").unwrap_err(),
+            "Missing return value: the function returns int"
+        );
+        // `return` is a whole word
+        assert!(check("val return_count = 1\nprint(return_count)\n").is_ok());
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        assert!(check("val x = 3\nval a: bool = x > 0 && x < 5\n").is_ok());
+        assert!(check("val x = 3\nval b: bool = x < 0 || !(x < 5)\n").is_ok());
+        // `&&` binds tighter than `||`, and both take a comparison's result
+        assert!(check("val x = 3\nprint(x == 1 || x > 2 && x != 4)\n").is_ok());
+        assert!(check("val o: option<int> = none\nval c = o != none && o != none\n").is_ok());
+        assert_eq!(
+            check("val a = 1 && 2\n").unwrap_err(),
+            "Operator '&&' requires bool operands, got int"
+        );
+        assert_eq!(
+            check("val s = \"a\" || \"b\"\n").unwrap_err(),
+            "Operator '||' requires bool operands, got string"
+        );
+        assert!(check("val a = 1 < 2 || 3\n").is_err());
+    }
+
+    #[test]
+    fn test_definite_initialization() {
+        // every declaration has a value, and a variable assigned in a branch
+        // is local to it, so any read before an assignment is undefined
+        assert_eq!(
+            check("if 1 < 2 {\nval y = 1\n}\nprint(y)\n").unwrap_err(),
+            "Undefined variable 'y'"
+        );
+        assert_eq!(
+            check("val x = x + 1\n").unwrap_err(),
+            "Undefined variable 'x'"
+        );
+        // a function only sees what was declared before it
+        assert_eq!(
+            check("def f() -> int {\nreturn z\n}\nval z = 1\n").unwrap_err(),
+            "Undefined variable 'z'"
+        );
+        // an inner declaration may read the outer variable it shadows
+        assert!(check("var a = 1\nif a > 0 {\nvar a = a + 1\nprint(a)\n}\n").is_ok());
+    }
+
+    #[test]
+    fn test_purity() {
+        // whether the value of the program's last statement is pure
+        let pure = |input: &str| {
+            let program = parse_program(input).unwrap().Program().unwrap();
+            let mut checker = TypeChecker::new();
+            checker.check_program(&program).unwrap();
+            checker.is_pure(&program.expressions[program.expressions.len() - 1])
+        };
+        let setup = "def f() -> int {\nreturn 1\n}\nvar xs = [1, 2]\nstruct P { x: int }\n";
+        assert!(pure(&format!("{}xs[0] + len(xs) * 2\n", setup)));
+        assert!(pure(&format!("{}P(max(1, 2)).x\n", setup)));
+        assert!(pure(&format!("{}(xs.len(), str(3))\n", setup)));
+        assert!(!pure(&format!("{}f() + 1\n", setup)));
+        assert!(!pure(&format!("{}xs[read_int()]\n", setup)));
+        assert!(!pure(&format!("{}xs.pop()\n", setup)));
+    }
+
+    #[test]
+    fn test_print_types() {
+        assert!(check("print(1)\nprint(\"a\")\nprint(2.5)\n").is_ok());
+        assert_eq!(
+            check("val xs = [1, 2]\nprint(xs)\n").unwrap_err(),
+            "list<int> is not Printable, as printed values must be; print the elements in a for loop, or join a list<string> with join(parts, \" \")"
+        );
+        assert!(check("val p = pair(1, 2)\nprint(p)\n")
+            .unwrap_err()
+            .ends_with("print its parts, e.g. f\"{p.first} {p.second}\""));
+        assert!(check("val t = (1, 2)\nprint(t)\n").is_err());
+        assert!(check("struct P { x: int }\nprint(P(1))\n").is_err());
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let program = r#"
+def is_even(n: int) -> bool {
+    if n == 0 {
+        return n == 0
+    }
+    return is_odd(n - 1)
+}
+def is_odd(n: int) -> bool {
+    if n == 0 {
+        return n != 0
+    }
+    return is_even(n - 1)
+}
+val b: bool = is_even(10)
+"#;
+        assert!(check(program).is_ok());
+        // the later function's signature is checked at the call
+        assert!(check(
+            "def f() -> int {\nreturn g(\"a\")\n}\ndef g(x: int) -> int {\nreturn x\n}\n"
+        )
+        .is_err());
+        // only functions defined together see each other
+        assert_eq!(
+            check("def f() -> int {\nreturn g()\n}\nval x = 1\ndef g() -> int {\nreturn x\n}\n")
+                .unwrap_err(),
+            "Undefined function 'g'"
+        );
+    }
+
+    #[test]
+    fn test_capabilities() {
+        assert_eq!(
+            check("val s = set<list<int>>()\n").unwrap_err(),
+            "list<int> is not Comparable, as set elements must be"
+        );
+        assert_eq!(
+            check("struct P { x: int }\nval b = P(1) == P(2)\n").unwrap_err(),
+            "P is not Equatable, as operands of '==' must be"
+        );
+        assert_eq!(
+            check("val xs = [[1]]\nval s = str(xs)\n").unwrap_err(),
+            "list<list<int>> is not Printable, as arguments of str must be"
+        );
+        assert!(check("struct P { x: int }\nval b = P(1) < P(2)\n").is_err());
+        assert!(check("val b = (1, [2]) == (1, [2])\n").is_ok());
+        assert!(check("val xs = [1, 2]\nval b = xs == xs\n").is_ok());
+        // any option may be checked against none
+        assert!(check("struct P { x: int }\nval p: option<P> = none\nval b = p == none\n").is_ok());
+    }
+
+    #[test]
+    fn test_overloading() {
+        let program = r#"
+def show(x: int) -> string {
+    return str(x)
+}
+def show(s: string) -> string {
+    return s
+}
+def show(a: int, b: int) -> string {
+    return show(a) + show(b)
+}
+val x: string = show(1)
+val y: string = show("a")
+val z: string = show(1, 2)
+"#;
+        assert!(check(program).is_ok());
+
+        let show = "def show(x: int) -> int {\nreturn x\n}\ndef show(s: string) -> string {\nreturn s\n}\n";
+        // each call gets the return type of the overload it picks
+        assert!(check(&format!("{}val n: int = show(\"a\")\n", show)).is_err());
+        assert!(check(&format!("{}val n = show(1.5)\n", show)).is_err());
+        assert!(check(&format!("{}val n = show(1, 2)\n", show)).is_err());
+        // a variable of the same name hides every overload
+        assert!(check(&format!("{}val show = 3\nval n = show(1)\n", show)).is_err());
+        // `none` fits both of these
+        let options = "def f(x: option<int>) {\n}\ndef f(x: option<string>) {\n}\n";
+        assert!(check(&format!("{}f(parse_int(\"1\"))\n", options)).is_ok());
+        assert!(check(&format!("{}f(none)\n", options)).is_err());
+    }
+
+    #[test]
+    fn test_none_narrowing() {
+        let program = r#"
+val a = parse_int("42")
+if a != none {
+    val n: int = a + 1
+}
+if none != a {
+    val n: int = a
+}
+if a == none {
+    print("missing")
+} else {
+    val n: int = a * 2
+}
+"#;
+        assert!(check(program).is_ok());
+
+        // outside the narrowed branch the variable is still an option
+        assert!(check("val a = parse_int(\"1\")\nif a != none {\n}\nval n: int = a\n").is_err());
+        assert!(check("val a = parse_int(\"1\")\nif a == none {\nval n: int = a\n}\n").is_err());
+        // a var could be set to none inside the branch, so it isn't narrowed
+        assert!(check("var a = parse_int(\"1\")\nif a != none {\nval n: int = a\n}\n").is_err());
+    }
+
+    #[test]
+    fn test_tuples() {
+        let program = r#"
+val t: tuple<int, string, float> = (1, "a", 2.5)
+val n: int = t.0
+val s: string = t.1
+val nested = ((1, 2), 3)
+val inner: int = nested.0.1
+val same: bool = (1, 2) == (1, 2)
+val less: bool = (1, "b") < (2, "a")
+val mixed: bool = (1, 2) < (1, 2.5)
+"#;
+        assert!(check(program).is_ok());
+
+        // element access is checked against the arity and element types
+        assert!(check(
+            "val t = (1, 2)
+val x = t.2
+"
+        )
+        .is_err());
+        assert!(check(
+            "val t = (1, \"a\")
+val x: int = t.1
+"
+        )
+        .is_err());
+        assert!(check(
+            "val x = 5
+val y = x.0
+"
+        )
+        .is_err());
+        // comparisons need the same arity and comparable elements
+        assert!(check(
+            "val b = (1, 2) == (1, 2, 3)
+"
+        )
+        .is_err());
+        assert!(check(
+            "val b = (1, \"a\") < (1, 2)
+"
+        )
+        .is_err());
+        assert!(check(
+            "val b = (1, 2) + (3, 4)
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let render = |input: &str| {
+            let program = parse_program(input).unwrap().Program().unwrap();
+            let error = TypeChecker::new().check_program(&program).unwrap_err();
+            error.render(&program.sources)
+        };
+        assert_eq!(
+            render("val a = 1\nval x = y + 1\n"),
+            "error[type-error]: Undefined variable 'y'\n --> <input>:2:9\n  |\n2 | val x = y + 1\n  |         ^"
+        );
+        assert_eq!(
+            render("val n = 1\nprint(n + \"s\")\n"),
+            "error[type-error]: Type mismatch in binary operation '+': left is int, right is string\n --> <input>:2:9\n  |\n2 | print(n + \"s\")\n  |         ^"
+        );
+        assert_eq!(
+            render("val total: int = 1.5\n"),
+            "error[type-error]: Type mismatch in assignment to 'total': expected int, got float\n --> <input>:1:5\n  |\n1 | val total: int = 1.5\n  |     ^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_sets() {
+        let program = r#"
+var seen: set<int> = set<int>()
+seen.insert(3)
+seen.erase(3)
+val has: bool = seen.contains(3)
+val n: int = seen.size() + len(seen)
+val other: set<int> = set<int>()
+val both: set<int> = seen & other
+val either: set<int> = seen | other
+val only: set<int> = seen - other
+for x in either {
+    print(x)
+}
+"#;
+        assert!(check(program).is_ok());
+
+        assert!(check("var s: set<int> = set<int>()\ns.insert(\"a\")\n").is_err());
+        assert!(check("val s: set<int> = set<int>()\ns.insert(1)\n").is_err());
+        assert!(check(
+            "val s: set<int> = set<int>()\nval t: set<string> = set<string>()\nprint(len(s | t))\n"
+        )
+        .is_err());
+        assert!(check("val s: set<int> = set<int>()\nprint(len(s * s))\n").is_err());
+        assert!(check("val s: set<list<int>> = set<list<int>>()\n").is_err());
+    }
+}
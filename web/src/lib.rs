@@ -21,8 +21,17 @@ pub fn compile_and_run(input: &str, stdin: &str) -> String {
     // Parse BBL code
     let ast = match bbl_frontend::parser::parse_program(input) {
         Ok(ast) => *ast, // Dereference the Box<AstNode>
-        Err(e) => {
-            return format!("Parse error: {}", e);
+        Err(errors) => {
+            let sources = [bbl_frontend::diagnostic::SourceFile {
+                path: "<input>".to_string(),
+                text: input.to_string(),
+                start: 0,
+            }];
+            return errors
+                .iter()
+                .map(|e| e.render(&sources))
+                .collect::<Vec<_>>()
+                .join("\n");
         }
     };
 
@@ -34,6 +43,11 @@ pub fn compile_and_run(input: &str, stdin: &str) -> String {
         }
     };
 
+    // Codegen relies on the program being well typed
+    if let Err(e) = bbl_frontend::typeck::TypeChecker::new().check_program(&program) {
+        return e.render(&program.sources);
+    }
+
     // Generate C++ code
     bbl_backend::codegen::generate(&program)
 }